@@ -0,0 +1,400 @@
+use clap::ArgMatches;
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::error::TileError;
+use crate::run_report::RunReport;
+use crate::{load_checked_config, make_mosaic, report_counts, save_mosaic, write_pdf};
+
+// Batch rendering, many configs and sweeps of palettes x tile sizes x dither modes in one run.
+// Every variant is a config with the swept settings given as command line overrides, rendered on
+// its own thread into the batch directory as <config>[_<palette>][_t<tile size>][_<dither>].png
+// with its tile json, run config, run report and (unless --no-pdf) instructions pdf. The batch directory also gets
+//   contact_sheet.png  a thumbnail of every variant, left to right and top to bottom in index order
+//   index.html         the thumbnails with the colour error and colours used by each variant
+//   index.json         the same for scripts
+// Colour error is the RGB distance between a tile's average colour and its palette colour, see dither.rs
+
+// Largest thumbnail side on the contact sheet, and the space around each thumbnail
+const THUMB_PX: u32 = 240;
+const THUMB_GAP_PX: u32 = 8;
+
+// Exit code of a variant that crashed, the code Rust exits with on a panic
+pub(crate) const PANIC_EXIT_CODE: i32 = 101;
+
+// One config with the settings swept for it
+struct Variant {
+    name: String,               // output file stem, unique in the batch
+    config: String,
+    palette: Option<String>,
+    tile_size: Option<String>,
+    dither: Option<String>,
+}
+
+// How one variant turned out, written to index.json
+#[derive(Serialize)]
+struct VariantEntry {
+    name: String,
+    config: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    palette: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tile_size: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dither: Option<String>,
+    seconds: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<VariantStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip)]
+    exit_code: i32,
+}
+
+#[derive(Serialize)]
+struct VariantStats {
+    image: String,              // mosaic image, relative to the batch directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pdf: Option<String>,
+    palette_name: String,
+    palette_colors: usize,
+    colors_used: usize,
+    tiles_across: usize,
+    tiles_down: usize,
+    mean_error: f64,
+    max_error: f64,
+}
+
+#[derive(Serialize)]
+struct BatchIndex<'a> {
+    contact_sheet: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    best: Option<&'a str>,      // variant with the lowest mean colour error
+    variants: &'a [VariantEntry],
+}
+
+/// Run the batch subcommand
+pub(crate) fn batch_command(matches: &ArgMatches) -> Result<(), TileError> {
+
+    let out_dir = Path::new(matches.get_one::<String>("out_dir").expect("batch directory missing"));
+    let strings = |id: &str| -> Vec<String> { matches.get_many::<String>(id).map(|v| v.cloned().collect()).unwrap_or_default() };
+    let configs = expand_globs(&strings("configs"), "configs")?;
+    let palettes = expand_globs(&strings("palettes"), "palettes")?;
+    let variants = sweep(&configs, &palettes, &strings("tile_sizes"), &strings("dither"));
+
+    let overrides: Vec<(String, String)> = matches.get_many::<(String, String)>("set").map(|v| v.cloned().collect()).unwrap_or_default();
+    let preset: Option<&str> = matches.get_one::<String>("preset").map(|s| s.as_str());
+    let pdf = !matches.get_flag("no_pdf");
+    let jobs: usize = *matches.get_one::<usize>("jobs").unwrap_or(&0);
+
+    std::fs::create_dir_all(out_dir).map_err(|e| TileError::output(out_dir, e))?;
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()
+        .map_err(|e| TileError::Export { what: "batch worker threads", source: e.into() })?;
+    log::info!("Batch of {} variants into {} on {} threads", variants.len(), out_dir.display(), pool.current_num_threads());
+
+    // render every variant, results in variant order
+    let done = AtomicUsize::new(0);
+    let results: Vec<(VariantEntry, Option<DynamicImage>)> = pool.install(|| {
+        variants.par_iter().map(|variant| {
+            let result = run_variant(variant, &overrides, preset, pdf, out_dir);
+            log::info!("Batch variant {} of {} {} {}", done.fetch_add(1, Ordering::Relaxed) + 1, variants.len(), variant.name,
+                     result.0.error.as_deref().and_then(|e| e.lines().next()).map(|e| format!("failed - {}", e)).unwrap_or_else(|| format!("done in {:.1}s", result.0.seconds)));
+            result
+        }).collect()
+    });
+    let (entries, thumbnails): (Vec<VariantEntry>, Vec<Option<DynamicImage>>) = results.into_iter().unzip();
+
+    let sheet_path = out_dir.join("contact_sheet.png");
+    contact_sheet(&thumbnails).save(&sheet_path).map_err(|e| TileError::output(&sheet_path, e))?;
+
+    let best: Option<&str> = entries.iter()
+        .filter_map(|entry| entry.stats.as_ref().map(|stats| (entry.name.as_str(), stats.mean_error)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(name, _)| name);
+    let index = BatchIndex { contact_sheet: "contact_sheet.png", best, variants: &entries };
+    let json_path = out_dir.join("index.json");
+    let json = serde_json::to_string_pretty(&index).map_err(|e| TileError::output(&json_path, e))?;
+    std::fs::write(&json_path, json).map_err(|e| TileError::output(&json_path, e))?;
+    let html_path = out_dir.join("index.html");
+    std::fs::write(&html_path, index_html(&index)).map_err(|e| TileError::output(&html_path, e))?;
+
+    log::info!("Batch index {} and contact sheet {}", html_path.display(), sheet_path.display());
+    if let Some(best) = best {
+        log::info!("Lowest colour error {}", best);
+    }
+
+    let failed: Vec<&VariantEntry> = entries.iter().filter(|entry| entry.error.is_some()).collect();
+    match failed.first() {
+        None => Ok(()),
+        Some(first) => Err(TileError::Batch { failed: failed.len(), total: entries.len(), index: html_path, code: first.exit_code }),
+    }
+} // batch_command
+
+// Config or palette files named by `patterns`, glob patterns such as config/*.json are expanded in name order
+fn expand_globs(patterns: &[String], what: &str) -> Result<Vec<String>, TileError> {
+    let mut files: Vec<String> = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            files.push(pattern.to_owned());
+            continue;
+        }
+        let matched: Vec<String> = glob::glob(pattern)
+            .map_err(|e| TileError::Layout(format!("bad {} pattern {} - {}", what, pattern, e)))?
+            .filter_map(|path| path.ok())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if matched.is_empty() {
+            return Err(TileError::Layout(format!("no {} match {}", what, pattern)));
+        }
+        files.extend(matched);
+    }
+    files.dedup();
+    Ok(files)
+}
+
+// Every combination of config, palette, tile size and dither mode, an empty list leaves the setting as the config has it
+fn sweep(configs: &[String], palettes: &[String], tile_sizes: &[String], dithers: &[String]) -> Vec<Variant> {
+    let or_config = |values: &[String]| -> Vec<Option<String>> {
+        if values.is_empty() { vec![None] } else { values.iter().cloned().map(Some).collect() }
+    };
+    let stem = |path: &str| Path::new(path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+
+    let mut variants: Vec<Variant> = Vec::new();
+    let mut names: HashSet<String> = HashSet::new();
+    for config in configs {
+        for palette in or_config(palettes) {
+            for tile_size in or_config(tile_sizes) {
+                for dither in or_config(dithers) {
+                    let mut parts: Vec<String> = vec![stem(config)];
+                    parts.extend(palette.as_deref().map(stem));
+                    parts.extend(tile_size.as_ref().map(|size| format!("t{}", size)));
+                    parts.extend(dither.clone());
+                    let stem: String = parts.join("_").chars()
+                        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+                        .collect();
+
+                    // the same config name in two directories, numbered past any name already taken
+                    let mut name = stem.clone();
+                    let mut n = 1;
+                    while !names.insert(name.clone()) {
+                        n += 1;
+                        name = format!("{}_{}", stem, n);
+                    }
+                    variants.push(Variant { name, config: config.to_owned(), palette: palette.clone(), tile_size: tile_size.clone(), dither });
+                }
+            }
+        }
+    }
+    variants
+} // sweep
+
+// Render one variant, its index entry and the thumbnail of the mosaic when it worked
+fn run_variant(variant: &Variant,
+               overrides: &[(String, String)],
+               preset: Option<&str>,
+               pdf: bool,
+               out_dir: &Path) -> (VariantEntry, Option<DynamicImage>) {
+
+    let started = Instant::now();
+    let image_name = format!("{}.png", variant.name);
+
+    // the swept settings override the config and the batch's own --set overrides
+    let mut overrides: Vec<(String, String)> = overrides.to_vec();
+    if let Some(palette) = &variant.palette {
+        overrides.push(("tile_colors".to_owned(), palette.to_owned()));
+    }
+    if let Some(size) = &variant.tile_size {
+        overrides.push(("tile_size_x".to_owned(), size.to_owned()));
+        overrides.push(("tile_size_y".to_owned(), size.to_owned()));
+    }
+    if let Some(dither) = &variant.dither {
+        overrides.push(("dither".to_owned(), dither.to_owned()));
+    }
+    overrides.push(("output".to_owned(), out_dir.join(&image_name).to_string_lossy().into_owned()));
+
+    // each variant has its own run report next to its image
+    let mut report = RunReport::new("batch");
+    // a crash in one variant is recorded as its failure rather than losing the rest of the batch
+    let made = std::panic::catch_unwind(AssertUnwindSafe(|| load_checked_config(&variant.config, &overrides, preset).and_then(|(cfg, board)| {
+        report.input("config", &variant.config);
+        report.input("image", &cfg.input);
+        report.input("palette", &cfg.tile_colors);
+        let mosaic = report.time("match tile colours", |_| make_mosaic(&cfg))?;
+        report_counts(&mut report, &mosaic, &cfg);
+        report.time("save mosaic image and json", |report| save_mosaic(&mosaic, &cfg, report))?;
+        if pdf {
+            report.time("instructions pdf", |report| write_pdf(&mosaic, &cfg, board, report))?;
+        }
+        let report_path = Path::new(&cfg.output).with_extension("report.json");
+        report.write(&report_path).map_err(|e| TileError::output(&report_path, e))?;
+        Ok(mosaic)
+    })));
+
+    let mut entry = VariantEntry {
+        name: variant.name.clone(),
+        config: variant.config.clone(),
+        palette: variant.palette.clone(),
+        tile_size: variant.tile_size.clone(),
+        dither: variant.dither.clone(),
+        seconds: round1(started.elapsed().as_secs_f64()),
+        stats: None,
+        error: None,
+        exit_code: 0,
+    };
+    match made {
+        Ok(Ok(mosaic)) => {
+            entry.stats = Some(VariantStats {
+                image: image_name,
+                pdf: if pdf { Some(format!("{}.pdf", variant.name)) } else { None },
+                palette_name: mosaic.all_colors.name.clone(),
+                palette_colors: mosaic.all_colors.colors.len(),
+                colors_used: mosaic.tile_color_count_vec.len(),
+                tiles_across: mosaic.grid.width_tile_count,
+                tiles_down: mosaic.grid.height_tile_count,
+                mean_error: round1(mosaic.mean_error),
+                max_error: round1(mosaic.max_error),
+            });
+            (entry, Some(mosaic.out_img.thumbnail(THUMB_PX, THUMB_PX)))
+        }
+        Ok(Err(e)) => {
+            entry.error = Some(e.to_string());
+            entry.exit_code = e.exit_code();
+            (entry, None)
+        }
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown error".to_owned());
+            entry.error = Some(format!("crashed - {}", message));
+            entry.exit_code = PANIC_EXIT_CODE;
+            (entry, None)
+        }
+    }
+} // run_variant
+
+fn round1(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+// Thumbnails on a white sheet, as near square as the count allows, failed variants are grey
+fn contact_sheet(thumbnails: &[Option<DynamicImage>]) -> DynamicImage {
+    let count = thumbnails.len().max(1) as u32;
+    let columns = (count as f64).sqrt().ceil() as u32;
+    let rows = count.div_ceil(columns);
+    let cell = THUMB_PX + THUMB_GAP_PX;
+
+    let (width, height) = (columns * cell + THUMB_GAP_PX, rows * cell + THUMB_GAP_PX);
+    let mut sheet = DynamicImage::new_rgb8(width, height);
+    fill(&mut sheet, (0, 0, width, height), Rgba([255, 255, 255, 255]));
+    for (i, thumbnail) in thumbnails.iter().enumerate() {
+        let (x, y) = (THUMB_GAP_PX + (i as u32 % columns) * cell, THUMB_GAP_PX + (i as u32 / columns) * cell);
+        match thumbnail {
+            // centre the thumbnail in its cell
+            Some(thumbnail) => {
+                let (x, y) = (x + (THUMB_PX - thumbnail.width()) / 2, y + (THUMB_PX - thumbnail.height()) / 2);
+                // cannot fail, the cell is inside the sheet
+                let _ = sheet.copy_from(thumbnail, x, y);
+            }
+            None => fill(&mut sheet, (x, y, THUMB_PX, THUMB_PX), Rgba([200, 200, 200, 255])),
+        }
+    }
+    sheet
+}
+
+// Fill the (x, y, width, height) rectangle of `img` with `color`
+fn fill(img: &mut DynamicImage, rect: (u32, u32, u32, u32), color: Rgba<u8>) {
+    for y in rect.1..rect.1 + rect.3 {
+        for x in rect.0..rect.0 + rect.2 {
+            img.put_pixel(x, y, color);
+        }
+    }
+}
+
+// Batch index page, the contact sheet then a table of the variants in the same order
+fn index_html(index: &BatchIndex) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>img_tile batch</title>\n<style>\n");
+    html.push_str("body { font-family: sans-serif; } table { border-collapse: collapse; } td, th { border: 1px solid #ccc; padding: 4px 8px; text-align: left; }\n");
+    html.push_str("td.num { text-align: right; } tr.best { background: #e6f4e6; } tr.failed { color: #a00; } img.thumb { max-width: 120px; max-height: 120px; }\n");
+    html.push_str("</style>\n</head>\n<body>\n");
+    html.push_str(&format!("<h1>img_tile batch of {} variants</h1>\n", index.variants.len()));
+    html.push_str(&format!("<p><a href=\"{0}\"><img src=\"{0}\" alt=\"contact sheet\" style=\"max-width: 100%\"></a></p>\n", index.contact_sheet));
+    html.push_str("<p>The contact sheet shows the variants left to right and top to bottom in table order. Colour error is the RGB distance between a tile's average colour and its tile colour, the lowest mean error is highlighted.</p>\n");
+    html.push_str("<table>\n<tr><th>#</th><th>Mosaic</th><th>Variant</th><th>Config</th><th>Palette</th><th>Tile size</th><th>Dither</th><th>Tiles</th><th>Colours used</th><th>Mean error</th><th>Max error</th><th>Seconds</th></tr>\n");
+
+    for (i, entry) in index.variants.iter().enumerate() {
+        let class = if index.best == Some(entry.name.as_str()) { " class=\"best\"" } else if entry.error.is_some() { " class=\"failed\"" } else { "" };
+        let or_config = |value: &Option<String>| escape(value.as_deref().unwrap_or("as config"));
+        html.push_str(&format!("<tr{}><td class=\"num\">{}</td>", class, i + 1));
+        match &entry.stats {
+            Some(stats) => {
+                let pdf = stats.pdf.as_ref().map(|pdf| format!("<br><a href=\"{}\">pdf</a>", escape(pdf))).unwrap_or_default();
+                html.push_str(&format!("<td><a href=\"{0}\"><img class=\"thumb\" src=\"{0}\" alt=\"{1}\"></a>{2}</td><td>{1}</td><td>{3}</td><td>{4}</td><td>{5}</td><td>{6}</td>",
+                                       escape(&stats.image), escape(&entry.name), pdf, escape(&entry.config),
+                                       escape(&format!("{} ({} colours)", stats.palette_name, stats.palette_colors)),
+                                       or_config(&entry.tile_size), or_config(&entry.dither)));
+                html.push_str(&format!("<td class=\"num\">{} x {}</td><td class=\"num\">{}</td><td class=\"num\">{:.1}</td><td class=\"num\">{:.1}</td>",
+                                       stats.tiles_across, stats.tiles_down, stats.colors_used, stats.mean_error, stats.max_error));
+            }
+            None => {
+                html.push_str(&format!("<td></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td colspan=\"4\">{}</td>",
+                                       escape(&entry.name), escape(&entry.config), or_config(&entry.palette),
+                                       or_config(&entry.tile_size), or_config(&entry.dither),
+                                       escape(entry.error.as_deref().unwrap_or_default()).replace('\n', "<br>")));
+            }
+        }
+        html.push_str(&format!("<td class=\"num\">{:.1}</td></tr>\n", entry.seconds));
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+} // index_html
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn names(variants: &[Variant]) -> Vec<&str> {
+        variants.iter().map(|v| v.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sweeps_every_combination() {
+        let variants = sweep(&strings(&["config/cat.json", "dog.toml"]), &strings(&["tile_json/perler colors.json"]),
+                             &strings(&["5", "2.5mm"]), &strings(&["none", "floyd-steinberg"]));
+        assert_eq!(variants.len(), 8);
+        assert_eq!(names(&variants[..4]), ["cat_perler_colors_t5_none", "cat_perler_colors_t5_floyd-steinberg",
+                                           "cat_perler_colors_t2.5mm_none", "cat_perler_colors_t2.5mm_floyd-steinberg"]);
+        assert_eq!(variants[7].name, "dog_perler_colors_t2.5mm_floyd-steinberg");
+        assert_eq!(variants[7].config, "dog.toml");
+        assert_eq!(variants[7].palette.as_deref(), Some("tile_json/perler colors.json"));
+        assert_eq!(variants[7].tile_size.as_deref(), Some("2.5mm"));
+        assert_eq!(variants[7].dither.as_deref(), Some("floyd-steinberg"));
+    }
+
+    #[test]
+    fn settings_not_swept_are_left_to_the_config() {
+        let variants = sweep(&strings(&["cat.json"]), &[], &[], &[]);
+        assert_eq!(names(&variants), ["cat"]);
+        assert_eq!((variants[0].palette.as_ref(), variants[0].tile_size.as_ref(), variants[0].dither.as_ref()), (None, None, None));
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let variants = sweep(&strings(&["a/cat.json", "b/cat.json", "cat_2.json", "c/cat.yaml"]), &[], &[], &[]);
+        assert_eq!(names(&variants), ["cat", "cat_2", "cat_2_2", "cat_3"]);
+    }
+}
@@ -0,0 +1,246 @@
+use euclid::Box2D;
+use printpdf::*;
+
+use crate::fonts::PdfFonts;
+use crate::modtile::{self, RGB};
+use crate::page_layout::PageLayout;
+use crate::pdf_util::{draw_quarter_arc, get_points_for_line, get_points_for_rect, pane_color_counts};
+use crate::tile_symbols::{self, TileSymbols};
+
+// Assembly checklist at the end of the instructions pdf, turned on with "checklist": true in the config.
+//   progress table - one row per pane with its tile and colour counts, boxes to tick when the tiles
+//                    are sorted, laid and grouted and the count of each colour by tile symbol
+//   pick lists     - the tiles of each colour needed for every pane, so a helper can sort the
+//                    tiles for a pane into cups before it is laid
+
+// Steps ticked off for every pane in the progress table
+pub const CHECKLIST_STEPS: [&str; 3] = ["Sorted", "Laid", "Grouted"];
+
+// Progress table column positions from the left margin, the colour counts use the rest of the row
+const COL_TILES_MM: f64 = 22.0;
+const COL_COLOURS_MM: f64 = 40.0;
+const COL_FIRST_STEP_MM: f64 = 60.0;
+const COL_STEP_WID_MM: f64 = 18.0;
+const CHECKBOX_MM: f64 = 4.0;
+
+// Columns of pick lists across a page
+const PICK_LIST_COLUMNS: usize = 3;
+
+/// Add the progress table and pick list pages for `panes`, labelled "Pane" or "Board" by `pane_label`.
+/// Returns the number of pages added.
+pub(crate) fn construct_checklist_pages(doc: &PdfDocumentReference,
+                                        fonts: &PdfFonts,
+                                        page: &PageLayout,
+                                        all_colors: &modtile::AllColors,
+                                        symbols: &TileSymbols,
+                                        panes: &[Vec<(Box2D<i32, i32>, RGB)>],
+                                        pane_label: &str) -> usize {
+
+    log::debug!("Construct assembly checklist pages");
+
+    let pane_counts: Vec<Vec<(RGB, i32)>> = panes.iter().map(|pane| pane_color_counts(pane)).collect();
+    let table_pages = draw_progress_table(doc, fonts, page, symbols, &pane_counts, pane_label);
+    let pick_pages = draw_pick_lists(doc, fonts, page, all_colors, symbols, &pane_counts, pane_label);
+    table_pages + pick_pages
+} // construct_checklist_pages
+
+// Start a checklist page with its title, returns the layer to draw on
+fn add_checklist_page(doc: &PdfDocumentReference, fonts: &PdfFonts, page: &PageLayout, title: &str) -> PdfLayerReference {
+    let (page_idx, layer) = doc.add_page(Mm(page.width_mm), Mm(page.height_mm), format!("{}, Layer 1", title));
+    let current_layer = doc.get_page(page_idx).get_layer(layer);
+
+    // draw a simple quarter arc at (0,0). Leave as a "makers mark"
+    draw_quarter_arc(&&current_layer);
+
+    current_layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    current_layer.use_text(title, 24.0, Mm(page.margin_left_mm), Mm(page.header_y_mm()), &fonts.heading);
+    current_layer
+}
+
+// Empty tick box with its lower left corner at (x_mm, y_mm)
+fn draw_checkbox(current_layer: &PdfLayerReference, x_mm: f64, y_mm: f64) {
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    current_layer.set_outline_thickness(0.8);
+    current_layer.add_shape(Line {
+        points: get_points_for_rect(Mm(CHECKBOX_MM), Mm(CHECKBOX_MM), Mm(x_mm), Mm(y_mm)),
+        is_closed: true,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    });
+}
+
+// One row per pane: pane number, tiles, colours, a tick box per step and the colour counts.
+// Rows grow to fit the colour counts and run onto more pages as needed. Returns the pages added.
+fn draw_progress_table(doc: &PdfDocumentReference,
+                       fonts: &PdfFonts,
+                       page: &PageLayout,
+                       symbols: &TileSymbols,
+                       pane_counts: &[Vec<(RGB, i32)>],
+                       pane_label: &str) -> usize {
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let gray = Color::Rgb(Rgb::new(0.6, 0.6, 0.6, None));
+    let x0_mm = page.margin_left_mm;
+    let counts_x_mm = x0_mm + COL_FIRST_STEP_MM + CHECKLIST_STEPS.len() as f64 * COL_STEP_WID_MM;
+
+    // 8pt text is roughly 1.5mm per character
+    let counts_chars = ((page.content_width_mm() - (counts_x_mm - x0_mm)) / 1.5).floor().max(10.0) as usize;
+    let header_hgt_mm = 8.0;
+    let counts_line_mm = 3.6;
+
+    let mut page_count = 0;
+    let mut current_layer: Option<PdfLayerReference> = None;
+    let mut row_top_mm = 0.0;
+
+    for (pane_idx, counts) in pane_counts.iter().enumerate() {
+        // colour counts as "symbol x count" in the order of the pane legend
+        // wrapped between entries so a symbol always stays with its count
+        let mut counts_lines: Vec<String> = Vec::new();
+        let mut line = String::new();
+        for (rgb, count) in counts {
+            let entry = format!("{} x{}", symbols.symbol(rgb), count);
+            if !line.is_empty() && line.chars().count() + 3 + entry.chars().count() > counts_chars {
+                counts_lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push_str("   ");
+            }
+            line.push_str(&entry);
+        }
+        counts_lines.push(line);
+        let row_hgt_mm = (counts_lines.len() as f64 * counts_line_mm + 3.0).max(8.0);
+
+        // start a new page with the column headings when the row does not fit
+        if current_layer.is_none() || row_top_mm - row_hgt_mm < page.margin_bottom_mm {
+            let title = if page_count == 0 { "Assembly Checklist".to_owned() } else { "Assembly Checklist (continued)".to_owned() };
+            let layer = add_checklist_page(doc, fonts, page, &title);
+            page_count += 1;
+
+            let heading_y_mm = page.content_top_mm() - header_hgt_mm + 2.5;
+            layer.use_text(pane_label, 10.0, Mm(x0_mm), Mm(heading_y_mm), &fonts.heading);
+            layer.use_text("Tiles", 10.0, Mm(x0_mm + COL_TILES_MM), Mm(heading_y_mm), &fonts.heading);
+            layer.use_text("Colours", 10.0, Mm(x0_mm + COL_COLOURS_MM), Mm(heading_y_mm), &fonts.heading);
+            for (i, step) in CHECKLIST_STEPS.iter().enumerate() {
+                layer.use_text(*step, 10.0, Mm(x0_mm + COL_FIRST_STEP_MM + i as f64 * COL_STEP_WID_MM), Mm(heading_y_mm), &fonts.heading);
+            }
+            layer.use_text("Tiles of each colour by symbol", 10.0, Mm(counts_x_mm), Mm(heading_y_mm), &fonts.heading);
+
+            row_top_mm = page.content_top_mm() - header_hgt_mm;
+            layer.set_outline_color(black.clone());
+            layer.set_outline_thickness(1.0);
+            layer.add_shape(Line {
+                points: get_points_for_line(Mm(x0_mm), Mm(row_top_mm), Mm(x0_mm + page.content_width_mm()), Mm(row_top_mm)),
+                is_closed: false,
+                has_fill: false,
+                has_stroke: true,
+                is_clipping_path: false,
+            });
+            current_layer = Some(layer);
+        }
+        let layer = match &current_layer {
+            Some(layer) => layer,
+            None => break,
+        };
+
+        let text_y_mm = row_top_mm - 5.0;
+        let tile_count: i32 = counts.iter().map(|c| c.1).sum();
+        layer.set_fill_color(black.clone());
+        layer.use_text(format!("{}", pane_idx + 1), 11.0, Mm(x0_mm), Mm(text_y_mm), &fonts.heading);
+        layer.use_text(format!("{}", tile_count), 10.0, Mm(x0_mm + COL_TILES_MM), Mm(text_y_mm), &fonts.regular);
+        layer.use_text(format!("{}", counts.len()), 10.0, Mm(x0_mm + COL_COLOURS_MM), Mm(text_y_mm), &fonts.regular);
+        for i in 0..CHECKLIST_STEPS.len() {
+            draw_checkbox(layer, x0_mm + COL_FIRST_STEP_MM + i as f64 * COL_STEP_WID_MM + 2.0, text_y_mm - 0.5);
+        }
+        layer.set_fill_color(black.clone());
+        for (i, line) in counts_lines.iter().enumerate() {
+            layer.use_text(line.as_str(), 8.0, Mm(counts_x_mm), Mm(text_y_mm - i as f64 * counts_line_mm), &fonts.regular);
+        }
+
+        // thin rule under the row
+        row_top_mm -= row_hgt_mm;
+        layer.set_outline_color(gray.clone());
+        layer.set_outline_thickness(0.3);
+        layer.add_shape(Line {
+            points: get_points_for_line(Mm(x0_mm), Mm(row_top_mm), Mm(x0_mm + page.content_width_mm()), Mm(row_top_mm)),
+            is_closed: false,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+    }
+
+    page_count
+} // draw_progress_table
+
+// A pick list for every pane, the pane heading followed by a line per colour with a tick box,
+// the tile symbol, the number of tiles and the colour name. The lists flow down PICK_LIST_COLUMNS
+// columns per page, a pane split over two columns repeats its heading. Returns the pages added.
+fn draw_pick_lists(doc: &PdfDocumentReference,
+                   fonts: &PdfFonts,
+                   page: &PageLayout,
+                   all_colors: &modtile::AllColors,
+                   symbols: &TileSymbols,
+                   pane_counts: &[Vec<(RGB, i32)>],
+                   pane_label: &str) -> usize {
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let col_wid_mm = page.content_width_mm() / PICK_LIST_COLUMNS as f64;
+    let heading_hgt_mm = 10.0;
+    let entry_hgt_mm = 6.5;
+    let col_top_mm = page.content_top_mm() - 4.0;
+    let max_name_chars = ((col_wid_mm - 30.0) / 1.9).floor().max(4.0) as usize;
+
+    let mut page_count = 0;
+    let mut current_layer: Option<PdfLayerReference> = None;
+    let mut col = PICK_LIST_COLUMNS;   // forces a new page for the first list
+    let mut y_mm = 0.0;
+    let mut new_col = true;             // nothing drawn in the current column yet
+
+    for (pane_idx, counts) in pane_counts.iter().enumerate() {
+        let tile_count: i32 = counts.iter().map(|c| c.1).sum();
+        for (i, (rgb, count)) in counts.iter().enumerate() {
+            // the heading goes with the first entry so a heading is never left alone at the bottom of a column
+            let need_mm = entry_hgt_mm + if i == 0 { heading_hgt_mm } else { 0.0 };
+            if current_layer.is_none() || y_mm - need_mm < page.margin_bottom_mm {
+                col += 1;
+                if col >= PICK_LIST_COLUMNS {
+                    let title = if page_count == 0 { "Pick Lists".to_owned() } else { "Pick Lists (continued)".to_owned() };
+                    current_layer = Some(add_checklist_page(doc, fonts, page, &title));
+                    page_count += 1;
+                    col = 0;
+                }
+                y_mm = col_top_mm;
+                new_col = true;
+            }
+            let layer = match &current_layer {
+                Some(layer) => layer,
+                None => break,
+            };
+            let x_mm = page.margin_left_mm + col as f64 * col_wid_mm;
+
+            if i == 0 || new_col {
+                let heading = if i == 0 {
+                    format!("{} {}  -  {} tiles, {} colours", pane_label, pane_idx + 1, tile_count, counts.len())
+                } else {
+                    format!("{} {} (continued)", pane_label, pane_idx + 1)
+                };
+                layer.set_fill_color(black.clone());
+                layer.use_text(heading, 11.0, Mm(x_mm), Mm(y_mm - 6.0), &fonts.heading);
+                y_mm -= heading_hgt_mm;
+                new_col = false;
+            }
+
+            let name = all_colors.colors.iter().find(|tc| tc.rgb == *rgb).map(|tc| tc.name.to_owned()).unwrap_or_else(|| rgb.to_string());
+            let name: String = name.chars().take(max_name_chars).collect();
+            draw_checkbox(layer, x_mm, y_mm - 4.5);
+            tile_symbols::draw_symbol_circle(layer, fonts, symbols, rgb, (x_mm + 9.0, y_mm - 2.5, 2.6));
+            layer.set_fill_color(black.clone());
+            layer.use_text(format!("{}", count), 10.0, Mm(x_mm + 13.0), Mm(y_mm - 3.8), &fonts.heading);
+            layer.use_text(name, 10.0, Mm(x_mm + 24.0), Mm(y_mm - 3.8), &fonts.regular);
+            y_mm -= entry_hgt_mm;
+        }
+    }
+
+    page_count
+} // draw_pick_lists
@@ -0,0 +1,368 @@
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+use crate::{config_file, dither, ldraw_export, logging, page_layout, preset, swatch};
+
+// Command line of img_tile, one subcommand per job
+//   render    make the mosaic image, instructions pdf and any other outputs from a config (the default)
+//   validate  check a config without making anything
+//   inspect   print the tile grid, panes and sizes a config gives without rendering
+//   pdf       remake the instructions pdf from the tiles saved by render, e.g. after changing page settings
+//   swatch    print a colour swatch pdf of a palette
+//   palette   list or check a palette file
+//   schema    write the JSON Schema of the config file
+//   batch     render many configs and sweeps of palettes, tile sizes and dither modes, see batch.rs
+// The render arguments are also accepted without a subcommand so "img_tile -c config.json" still renders.
+
+pub fn build_cli() -> Command {
+    Command::new("img_tile")
+        .version("0.1")
+        .author("bitbangr <mgj000@hotmail.com>")
+        .about("Turn an image into a tile mosaic with printed assembly instructions")
+        .args(log_args())
+        .args(render_args())
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("render")
+                .about("Make the mosaic image, instructions pdf, tile json and any other outputs asked for")
+                .args(render_args()),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check a config and its command line overrides, listing every problem found")
+                .args(config_args()),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Print the tile grid, pane layout and sizes a config gives without rendering")
+                .args(config_args()),
+        )
+        .subcommand(
+            Command::new("pdf")
+                .about("Remake the instructions pdf from the tile json and mosaic image saved by render")
+                .args(config_args())
+                .arg(
+                    Arg::new("tiles")
+                        .long("tiles")
+                        .value_name("FILE")
+                        .help("Tile json written by render, <output>.json when not given"),
+                )
+                .arg(ldraw_piece_arg()),
+        )
+        .subcommand(
+            Command::new("swatch")
+                .about("Print a colour swatch pdf of a palette")
+                .arg(
+                    Arg::new("palette")
+                        .value_name("PALETTE")
+                        .help("Tile colour palette json file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Swatch pdf or directory to write it to, <palette name>.pdf in the working directory when not given"),
+                )
+                .arg(
+                    Arg::new("config")
+                        .short('c')
+                        .long("config")
+                        .value_name("FILE")
+                        .help("Config to take the fonts and page settings from"),
+                )
+                .args(swatch_option_args(false)),
+        )
+        .subcommand(
+            Command::new("palette")
+                .about("Palette file tools")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("List the colours of a palette")
+                        .arg(Arg::new("palette").value_name("PALETTE").help("Tile colour palette json file").required(true))
+                        .arg(
+                            Arg::new("sort")
+                                .long("sort")
+                                .value_name("ORDER")
+                                .help("Order of the colours, none keeps the palette file order")
+                                .value_parser(clap::builder::PossibleValuesParser::new(swatch::swatch_sort_names()))
+                                .default_value("none"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Check a palette for repeated colours, numbers and names and missing details")
+                        .arg(Arg::new("palette").value_name("PALETTE").help("Tile colour palette json file").required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Write the JSON Schema of the config file for editor autocompletion")
+                .arg(Arg::new("file").value_name("FILE").help("Schema file to write, stdout when not given")),
+        )
+        .subcommand(
+            Command::new("batch")
+                .about("Render many configs and sweeps of palettes, tile sizes and dither modes in parallel, with a contact sheet and index")
+                .arg(
+                    Arg::new("configs")
+                        .value_name("CONFIG")
+                        .help("Configs or glob patterns such as 'config/*.json', each is rendered with every palette, tile size and dither mode given")
+                        .num_args(1..)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("palettes")
+                        .long("palettes")
+                        .value_name("PALETTES")
+                        .help("Comma separated palette files or glob patterns such as 'tile_json/*.json', the config's palette when not given")
+                        .value_delimiter(',')
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("tile_sizes")
+                        .long("tile-sizes")
+                        .value_name("SIZES")
+                        .help("Comma separated tile sizes, in the config units unless given with a unit, e.g. 8,10,12 or 1cm,1.5cm")
+                        .value_delimiter(',')
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("dither")
+                        .long("dither")
+                        .value_name("MODES")
+                        .help("Comma separated dither modes")
+                        .value_parser(clap::builder::PossibleValuesParser::new(dither::dither_mode_names()))
+                        .value_delimiter(',')
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("out_dir")
+                        .short('o')
+                        .long("out-dir")
+                        .value_name("DIR")
+                        .help("Directory for the variants, contact sheet and index")
+                        .default_value("batch"),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_name("N")
+                        .help("Variants rendered at once, 0 for one per processor")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("no_pdf")
+                        .long("no-pdf")
+                        .help("Only write the mosaic images and tile json, not the instructions pdfs")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("preset")
+                        .short('p')
+                        .long("preset")
+                        .value_name("PRESET")
+                        .help("Board preset for every variant")
+                        .value_parser(clap::builder::PossibleValuesParser::new(preset::preset_names())),
+                )
+                .arg(
+                    Arg::new("set")
+                        .long("set")
+                        .value_name("KEY=VALUE")
+                        .help("Override a config setting of every variant. Can be repeated")
+                        .value_parser(config_file::parse_setting)
+                        .action(ArgAction::Append),
+                ),
+        )
+} // build_cli
+
+/// Parse the command line, exiting with a usage error as clap does for any problem.
+/// The render arguments accepted without a subcommand cannot be given with one, e.g. "img_tile --dxf validate".
+/// This is checked here as clap's args_conflicts_with_subcommands would also reject the global -q, -v and --log-format
+pub fn get_matches() -> ArgMatches {
+    let mut cli = build_cli();
+    let matches = cli.get_matches_mut();
+    if let Some((name, _)) = matches.subcommand() {
+        let log_ids: Vec<String> = log_args().iter().map(|arg| arg.get_id().to_string()).collect();
+        let given = matches.ids()
+            .map(|id| id.as_str())
+            .find(|id| !log_ids.iter().any(|log_id| log_id == id) && matches.value_source(id) == Some(ValueSource::CommandLine));
+        if let Some(id) = given {
+            let arg = cli.get_arguments().find(|arg| arg.get_id() == id).and_then(|arg| arg.get_long()).unwrap_or(id);
+            cli.error(ErrorKind::ArgumentConflict, format!("the argument '--{}' cannot be used with the subcommand '{}'", arg, name)).exit();
+        }
+    }
+    matches
+}
+
+// How much is logged and how, taken by every subcommand, see logging.rs
+fn log_args() -> Vec<Arg> {
+    vec![
+        Arg::new("quiet")
+            .short('q')
+            .long("quiet")
+            .help("Only log errors and warnings")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("verbose")
+            .global(true),
+        Arg::new("verbose")
+            .short('v')
+            .long("verbose")
+            .help("Log more detail, -v for debug and -vv for trace")
+            .action(ArgAction::Count)
+            .global(true),
+        Arg::new("log_format")
+            .long("log-format")
+            .value_name("FORMAT")
+            .help("Log as text or as one JSON object per line")
+            .value_parser(clap::builder::PossibleValuesParser::new(logging::log_format_names()))
+            .default_value("text")
+            .global(true),
+    ]
+}
+
+// The 1 x 1 LEGO piece of the LDraw model, also named in the board parts lists of the instructions pdf
+fn ldraw_piece_arg() -> Arg {
+    Arg::new("ldraw_piece")
+        .long("ldraw-piece")
+        .value_name("PIECE")
+        .help("1 x 1 piece used for each tile in the LDraw model and the board parts lists")
+        .value_parser(clap::builder::PossibleValuesParser::new(ldraw_export::ldraw_piece_names()))
+        .default_value("round-plate")
+}
+
+// The config file, a board preset and the settings overriding the config, see config_file::CONFIG_ARGS
+fn config_args() -> Vec<Arg> {
+    let mut args = vec![
+        Arg::new("config")
+            .short('c')
+            .long("config")
+            .value_name("FILE")
+            .help("config settings for tiling, json, toml or yaml")
+            .required(true),
+        Arg::new("preset")
+            .short('p')
+            .long("preset")
+            .value_name("PRESET")
+            .help("Board preset, fixes pane size and tile pitch to a peg board or baseplate. Overrides the config preset")
+            .value_parser(clap::builder::PossibleValuesParser::new(preset::preset_names())),
+    ];
+    args.extend(config_file::CONFIG_ARGS.iter().map(|(id, _, help)| {
+        Arg::new(*id).long(*id).value_name("VALUE").help(*help)
+    }));
+    args.push(
+        Arg::new("set")
+            .long("set")
+            .value_name("KEY=VALUE")
+            .help("Override any config setting, e.g. --set print_mode=mono or --set page.size=a4. Can be repeated")
+            .value_parser(config_file::parse_setting)
+            .action(ArgAction::Append),
+    );
+    args
+}
+
+// Swatch pdf layout, "--swatch-size" etc. when rendering and "--size" etc. for the swatch subcommand
+fn swatch_option_args(in_render: bool) -> Vec<Arg> {
+    let long = |render: &'static str, swatch: &'static str| if in_render { render } else { swatch };
+    vec![
+        Arg::new("swatch_size")
+            .long(long("swatch-size", "size"))
+            .value_name("MM")
+            .help("Size of each printed colour swatch")
+            .value_parser(clap::value_parser!(f64))
+            .default_value("30"),
+        Arg::new("swatch_columns")
+            .long(long("swatch-columns", "columns"))
+            .value_name("N")
+            .help("Swatches per row, by default as many as fit across the page")
+            .value_parser(clap::value_parser!(usize)),
+        Arg::new("swatch_labels")
+            .long(long("swatch-labels", "labels"))
+            .value_name("LABELS")
+            .help("Comma separated text printed under each swatch")
+            .value_parser(clap::builder::PossibleValuesParser::new(swatch::swatch_label_names()))
+            .value_delimiter(',')
+            .num_args(1..)
+            .default_value("rgb,name,number"),
+        Arg::new("swatch_sort")
+            .long(long("swatch-sort", "sort"))
+            .value_name("ORDER")
+            .help("Order of the swatches, none keeps the palette file order")
+            .value_parser(clap::builder::PossibleValuesParser::new(swatch::swatch_sort_names()))
+            .default_value("none"),
+    ]
+}
+
+// Everything the render subcommand takes, the config and the optional outputs
+fn render_args() -> Vec<Arg> {
+    let mut args = config_args();
+    args.push(
+        Arg::new("swatch")
+            .short('s')
+            .long("swatch")
+            .value_name("FILE")
+            .help("Also write a color swatch pdf of the palette. Written next to the output image unless FILE (a pdf or directory) is given")
+            .num_args(0..=1)
+            .required(false),
+    );
+    args.push(
+        Arg::new("report")
+            .long("report")
+            .value_name("FILE")
+            .help("Write the run report (inputs, outputs, counts and timings) to FILE rather than <output>.report.json"),
+    );
+    args.push(
+        Arg::new("watch")
+            .short('w')
+            .long("watch")
+            .help("Keep running and render again when the config, palette, input image or a font file changes")
+            .action(ArgAction::SetTrue),
+    );
+    args.extend(swatch_option_args(true));
+    args.extend([
+        Arg::new("dxf")
+            .long("dxf")
+            .help("Also write the tile outlines as a DXF cut file")
+            .action(ArgAction::SetTrue),
+        Arg::new("dxf_split")
+            .long("dxf-split")
+            .help("Write one DXF cut file per window pane (implies --dxf)")
+            .action(ArgAction::SetTrue),
+        Arg::new("cross_stitch")
+            .long("cross-stitch")
+            .help("Also write a cross stitch symbol chart pdf and OXS pattern, one stitch per tile")
+            .action(ArgAction::SetTrue),
+        Arg::new("stitch_overlap")
+            .long("stitch-overlap")
+            .value_name("ROWS")
+            .help("Number of rows/columns of neighbouring panes repeated on each cross stitch chart page")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("2"),
+        Arg::new("ldraw")
+            .long("ldraw")
+            .help("Also write the mosaic as an LDraw model of 1 x 1 pieces, on baseplates when using a LEGO preset")
+            .action(ArgAction::SetTrue),
+        ldraw_piece_arg(),
+        Arg::new("poster")
+            .long("poster")
+            .help("Also write the mosaic at actual size as a tiled poster pdf with an assembly map")
+            .action(ArgAction::SetTrue),
+        Arg::new("poster_paper")
+            .long("poster-paper")
+            .value_name("PAPER")
+            .help("Paper size of the poster sheets")
+            .value_parser(clap::builder::PossibleValuesParser::new(page_layout::paper_names()))
+            .default_value("letter"),
+        Arg::new("poster_overlap")
+            .long("poster-overlap")
+            .value_name("MM")
+            .help("Overlap in mm between neighbouring poster sheets")
+            .value_parser(clap::value_parser!(f64))
+            .default_value("10"),
+    ]);
+    args
+}
@@ -0,0 +1,365 @@
+use serde_json::{Map, Value};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config_schema;
+use crate::error::{ConfigProblem, TileError};
+use crate::fonts;
+use crate::modtile::{self, Config};
+
+// Reading config files, JSON (.json), TOML (.toml) or YAML (.yaml or .yml) chosen by the file extension.
+// A config can name a base config with "extends" and only give the settings that differ, the base
+// can extend another config in turn. Settings are merged key by key so "page": { "size": "a4" } in a
+// config keeps the base's page margins.
+// Relative file paths are relative to the config file that sets them.
+// Settings given on the command line override the config, see CONFIG_ARGS and --set, and the merged
+// config is saved next to the outputs so the run can be repeated.
+
+// Settings holding file paths, fonts only when they are not a standard PDF font name and the palette
+// only when it is not built in
+pub const PATH_KEYS: [&str; 5] = ["tile_colors", "input", "output", "font", "heading_font"];
+
+// Command line options that override config settings, (arg id and long option, settings set, help).
+// Values are read as in the config, "--tile-size 12" is 12 in the config units and "--tile-size 1.2cm" a length with its unit.
+// Any other setting can be given with --set key=value
+pub const CONFIG_ARGS: [(&str, &[&str], &str); 12] = [
+    ("palette", &["tile_colors"], "Tile colour palette file, overrides \"tile_colors\""),
+    ("input", &["input"], "Input image, overrides \"input\""),
+    ("output", &["output"], "Output mosaic image, the pdf and other outputs are written next to it"),
+    ("width", &["output_width"], "Largest mosaic width, overrides \"output_width\""),
+    ("height", &["output_height"], "Largest mosaic height, overrides \"output_height\""),
+    ("tile-size", &["tile_size_x", "tile_size_y"], "Tile width and height, overrides \"tile_size_x\" and \"tile_size_y\""),
+    ("tile-space", &["tile_space_x", "tile_space_y"], "Space between tiles, overrides \"tile_space_x\" and \"tile_space_y\""),
+    ("pane-size", &["tiles_per_pane_width", "tiles_per_pane_height"], "Tiles across and down each pane, overrides \"tiles_per_pane_width\" and \"tiles_per_pane_height\""),
+    ("units", &["units"], "Unit of lengths given as plain numbers: mm, cm, in or px"),
+    ("preview-dpi", &["preview_dpi"], "Pixels per inch of the rendered mosaic image"),
+    ("title", &["title"], "Project title on the instructions cover page"),
+    ("author", &["author"], "Author on the instructions cover page"),
+];
+
+/// `--set key=value` argument, split at the first '='
+pub(crate) fn parse_setting(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_owned(), value.to_owned())),
+        _ => Err(format!("expected key=value, e.g. tile_size_x=12, not {}", arg)),
+    }
+}
+
+/// Set the command line `overrides` (setting, value) in the config read from `path`.
+/// A setting inside a section is named with a dot, e.g. "page.size". Values that parse as JSON
+/// such as 12 or true are used as such, anything else is text. Paths stay relative to the working directory.
+pub(crate) fn apply_overrides(value: &mut Value, overrides: &[(String, String)], path: &Path) -> Result<(), TileError> {
+    let mut problems: Vec<ConfigProblem> = Vec::new();
+    for (key, text) in overrides {
+        let mut parts = key.split('.');
+        let top = parts.next().unwrap_or_default();
+        if let Some(suggestion) = config_schema::check_key(top) {
+            problems.push(ConfigProblem { key: key.to_owned(), message: "unknown setting given on the command line".to_owned(), suggestion });
+            continue;
+        }
+        let setting: Value = if PATH_KEYS.contains(&top) {
+            Value::String(text.to_owned())
+        } else {
+            serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_owned()))
+        };
+
+        // walk down to the section holding the setting, making sections as needed
+        let mut target: &mut Value = value;
+        for part in key.split('.') {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            target = target.as_object_mut().expect("section is an object").entry(part.to_owned()).or_insert(Value::Null);
+        }
+        *target = setting;
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(TileError::Invalid { path: path.to_path_buf(), problems })
+    }
+} // apply_overrides
+
+/// Save the merged config, after command line overrides and any board preset, to `path`.
+/// Paths are made absolute so the saved config can be run from anywhere, unset settings are left out.
+pub(crate) fn save_run_config(cfg: &Config, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut value = serde_json::to_value(cfg)?;
+    remove_nulls(&mut value);
+    if let Some(map) = value.as_object_mut() {
+        resolve_paths(map, &std::env::current_dir()?);
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&value)?)?;
+    Ok(())
+}
+
+/// Read the config at `path` and every config it extends, merged into one JSON object.
+/// Each file is migrated to the current config version and its paths resolved before merging.
+pub(crate) fn read_config_value(path: &Path) -> Result<Value, TileError> {
+    let mut chain: Vec<PathBuf> = Vec::new();
+    read_extended(path, &mut chain)
+}
+
+/// The config at `path` and the configs it extends, the base last.
+/// Stops at a config that cannot be read, the problem is reported when the config is loaded.
+pub(crate) fn config_files(path: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = vec![path.to_path_buf()];
+    while let Some(Value::String(base)) = parse_config_file(&files[files.len() - 1]).ok().and_then(|mut v| v.get_mut("extends").map(Value::take)) {
+        let dir = files[files.len() - 1].parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+        let base_path = dir.join(base);
+        if files.contains(&base_path) {
+            break;
+        }
+        files.push(base_path);
+    }
+    files
+}
+
+// Config files already reported as migrated, watch and batch read the same files again and again
+static MIGRATED_CONFIGS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+// Read one config file and merge it over its base, `chain` holds the configs that extend this one
+fn read_extended(path: &Path, chain: &mut Vec<PathBuf>) -> Result<Value, TileError> {
+
+    let id = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&id) {
+        let cycle: Vec<String> = chain.iter().chain(std::iter::once(&id)).map(|p| p.display().to_string()).collect();
+        return Err(TileError::Invalid {
+            path: chain[0].clone(),
+            problems: vec![ConfigProblem { key: "extends".to_owned(),
+                                           message: format!("configs extend each other {}", cycle.join(" -> ")),
+                                           suggestion: None }],
+        });
+    }
+    chain.push(id.clone());
+
+    let mut value = parse_config_file(path)?;
+    let notes = config_schema::migrate_config(&mut value, path)?;
+    if !notes.is_empty() {
+        // warn once per file, later loads only log at debug
+        let mut migrated = MIGRATED_CONFIGS.lock().unwrap_or_else(|e| e.into_inner());
+        let first = !migrated.contains(&id);
+        if first {
+            migrated.push(id);
+        }
+        for note in notes {
+            if first {
+                log::warn!("{}", note);
+            } else {
+                log::debug!("{}", note);
+            }
+        }
+    }
+    for (key, suggestion) in config_schema::unknown_keys(&value) {
+        log::warn!("unknown setting \"{}\" in config {} is ignored{}", key, path.display(),
+                  suggestion.map(|s| format!(" - {}", s)).unwrap_or_default());
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let base = match value.as_object_mut() {
+        Some(cfg) => {
+            resolve_paths(cfg, dir);
+            cfg.remove("extends")
+        }
+        None => None,
+    };
+
+    match base {
+        Some(Value::String(base_path)) => {
+            let mut merged = read_extended(&dir.join(base_path), chain)?;
+            merge(&mut merged, value);
+            Ok(merged)
+        }
+        Some(other) => Err(TileError::Invalid {
+            path: path.to_path_buf(),
+            problems: vec![ConfigProblem { key: "extends".to_owned(),
+                                           message: format!("{} is not a file name", other),
+                                           suggestion: Some("give the path of the base config as a string".to_owned()) }],
+        }),
+        None => Ok(value),
+    }
+} // read_extended
+
+// Parse a config file in the format given by its extension
+fn parse_config_file(path: &Path) -> Result<Value, TileError> {
+    let buf = std::fs::read(path).map_err(|e| TileError::ConfigRead { path: path.to_path_buf(), source: e })?;
+    log::debug!("Config File {} was successfully opened", path.display());
+
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    let parsed: Result<Value, Box<dyn std::error::Error>> = match extension.as_str() {
+        "toml" => std::str::from_utf8(&buf).map_err(|e| e.into())
+                                           .and_then(|text| toml::from_str(text).map_err(|e| e.into())),
+        "yaml" | "yml" => serde_yaml::from_slice(&buf).map_err(|e| e.into()),
+        _ => serde_json::from_slice(&buf).map_err(|e| e.into()),
+    };
+    parsed.map_err(|e| TileError::ConfigParse { path: path.to_path_buf(), source: e })
+}
+
+/// Make the relative file paths in a config relative to `dir` instead, the directory of the config file
+pub(crate) fn resolve_paths(cfg: &mut Map<String, Value>, dir: &Path) {
+    for key in PATH_KEYS.iter() {
+        if let Some(Value::String(file)) = cfg.get_mut(*key) {
+            let is_font = key.ends_with("font");
+            if file.trim().is_empty() || Path::new(file.as_str()).is_absolute() || (is_font && fonts::is_builtin_font(file))
+               || (*key == "tile_colors" && modtile::is_builtin_palette(file)) {
+                continue;
+            }
+            let resolved: PathBuf = dir.join(file.as_str()).components().filter(|c| *c != Component::CurDir).collect();
+            *file = resolved.to_string_lossy().into_owned();
+        }
+    }
+}
+
+// Drop the settings that are not set, in sections too
+fn remove_nulls(value: &mut Value) {
+    if let Value::Object(map) = value {
+        map.retain(|_, v| !v.is_null());
+        map.values_mut().for_each(remove_nulls);
+    }
+}
+
+// Merge `over` into `base`, objects key by key and anything else replaced
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Object(base), Value::Object(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => { base.insert(key, value); }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // A fresh directory for the config files of one test
+    fn config_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("img_tile_{}_{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn merges_sections_key_by_key() {
+        let mut base = json!({ "title": "base", "page": { "size": "letter", "margin": 10 }, "dither": "none" });
+        merge(&mut base, json!({ "title": "over", "page": { "size": "a4" }, "dither": null, "author": "me" }));
+        assert_eq!(base, json!({ "title": "over", "page": { "size": "a4", "margin": 10 }, "dither": null, "author": "me" }));
+
+        remove_nulls(&mut base);
+        assert_eq!(base.get("dither"), None);
+    }
+
+    #[test]
+    fn extends_a_base_config_in_another_format() {
+        let dir = config_dir("extends");
+        std::fs::create_dir_all(dir.join("base")).unwrap();
+        std::fs::write(dir.join("base/base.toml"), "version = 2\ntile_colors = \"colors.json\"\ntitle = \"base\"\n[page]\nsize = \"letter\"\nmargin = 10\n").unwrap();
+        std::fs::write(dir.join("config.yaml"), "version: 2\nextends: base/base.toml\ninput: image.png\npage:\n  size: a4\n").unwrap();
+
+        let value = read_config_value(&dir.join("config.yaml")).unwrap();
+        assert_eq!(value["tile_colors"], json!(dir.join("base/colors.json").to_string_lossy()));
+        assert_eq!(value["input"], json!(dir.join("image.png").to_string_lossy()));
+        assert_eq!(value["title"], json!("base"));
+        assert_eq!(value["page"], json!({ "size": "a4", "margin": 10 }));
+        assert_eq!(value.get("extends"), None);
+        assert_eq!(config_files(&dir.join("config.yaml")), vec![dir.join("config.yaml"), dir.join("base/base.toml")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migration_is_reported_once_per_file() {
+        let dir = config_dir("migrated");
+        std::fs::write(dir.join("old.json"), "{ \"title\": \"old\" }").unwrap();
+        let id = dir.join("old.json").canonicalize().unwrap();
+
+        for _ in 0..2 {
+            let value = read_config_value(&dir.join("old.json")).unwrap();
+            assert_eq!(value["version"], json!(config_schema::CONFIG_VERSION));
+        }
+        assert_eq!(MIGRATED_CONFIGS.lock().unwrap().iter().filter(|p| **p == id).count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn configs_extending_each_other_are_an_error() {
+        let dir = config_dir("extends_cycle");
+        std::fs::write(dir.join("a.json"), r#"{ "version": 2, "extends": "b.json" }"#).unwrap();
+        std::fs::write(dir.join("b.json"), r#"{ "version": 2, "extends": "./a.json" }"#).unwrap();
+
+        match read_config_value(&dir.join("a.json")) {
+            Err(TileError::Invalid { problems, .. }) => {
+                assert_eq!(problems[0].key, "extends");
+                assert!(problems[0].message.starts_with("configs extend each other"), "{}", problems[0].message);
+            }
+            other => panic!("expected the extends cycle to be reported, got {:?}", other.map(|_| ())),
+        }
+        // the files to watch stop at the first config seen again
+        assert_eq!(config_files(&dir.join("a.json")), vec![dir.join("a.json"), dir.join("b.json")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extends_must_name_a_file() {
+        let dir = config_dir("extends_number");
+        std::fs::write(dir.join("a.json"), r#"{ "version": 2, "extends": 12 }"#).unwrap();
+        match read_config_value(&dir.join("a.json")) {
+            Err(TileError::Invalid { problems, .. }) => assert_eq!(problems[0].key, "extends"),
+            other => panic!("expected a bad extends to be reported, got {:?}", other.map(|_| ())),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_set_arguments() {
+        assert_eq!(parse_setting("page.size=a4"), Ok(("page.size".to_owned(), "a4".to_owned())));
+        assert_eq!(parse_setting(" title =a=b"), Ok(("title".to_owned(), "a=b".to_owned())));
+        assert_eq!(parse_setting("author="), Ok(("author".to_owned(), "".to_owned())));
+        assert!(parse_setting("tile_size_x").is_err());
+        assert!(parse_setting("=12").is_err());
+    }
+
+    #[test]
+    fn overrides_settings_and_sections() {
+        let overrides: Vec<(String, String)> = [("tile_size_x", "12"), ("checklist", "true"), ("title", "My mosaic"),
+                                                ("page.size", "a4"), ("input", "12"), ("output_width", "\"60cm\"")]
+            .iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+        let mut value = json!({ "tile_size_x": 8, "page": { "size": "letter", "margin": 10 } });
+        apply_overrides(&mut value, &overrides, Path::new("config.json")).unwrap();
+        assert_eq!(value, json!({ "tile_size_x": 12, "checklist": true, "title": "My mosaic",
+                                  "page": { "size": "a4", "margin": 10 }, "input": "12", "output_width": "60cm" }));
+
+        // a section that was not an object is replaced
+        let mut value = json!({ "page": "a4" });
+        apply_overrides(&mut value, &[("page.size".to_owned(), "a5".to_owned())], Path::new("config.json")).unwrap();
+        assert_eq!(value, json!({ "page": { "size": "a5" } }));
+    }
+
+    #[test]
+    fn unknown_overrides_are_an_error() {
+        let mut value = json!({});
+        let overrides = [("tile_sise_x".to_owned(), "12".to_owned()), ("title".to_owned(), "kept".to_owned())];
+        match apply_overrides(&mut value, &overrides, Path::new("config.json")) {
+            Err(TileError::Invalid { problems, .. }) => {
+                assert_eq!(problems.len(), 1);
+                assert_eq!(problems[0].key, "tile_sise_x");
+            }
+            other => panic!("expected the unknown setting to be reported, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn built_in_names_are_not_paths() {
+        let mut cfg = json!({ "tile_colors": "builtin:lego", "font": "Helvetica", "heading_font": "fonts/bold.ttf", "input": "/images/in.png" })
+            .as_object().unwrap().clone();
+        resolve_paths(&mut cfg, Path::new("config"));
+        assert_eq!(Value::Object(cfg), json!({ "tile_colors": "builtin:lego", "font": "Helvetica",
+                                               "heading_font": Path::new("config/fonts/bold.ttf").to_string_lossy(), "input": "/images/in.png" }));
+    }
+}
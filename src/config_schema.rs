@@ -0,0 +1,234 @@
+use serde_json::{json, Map, Value};
+use std::path::Path;
+
+use crate::error::{ConfigProblem, TileError};
+use crate::{config_file, dither, page_layout, pdf_util, preset, tile_symbols, units, validate};
+
+// Versions of the config file format, defaults for the settings that can be left out and the
+// JSON Schema of the config used by editors for autocompletion ("img_tile schema").
+// A config without a "version" key is version 0, older versions are migrated when loaded.
+//   0 - configs written before "version", lengths always in mm
+//   1 - "units" sets the unit of plain number lengths, settings with defaults can be left out
+//   2 - relative file paths are relative to the config file rather than the working directory
+pub const CONFIG_VERSION: u64 = 2;
+
+// Settings filled from their partner when left out, e.g. square tiles need only tile_size_x
+pub const PAIRED_DEFAULTS: [(&str, &str); 4] = [("output_height", "output_width"),
+                                                ("tile_size_y", "tile_size_x"),
+                                                ("tile_space_y", "tile_space_x"),
+                                                ("tiles_per_pane_height", "tiles_per_pane_width")];
+
+// Tiles per pane side when "tiles_per_pane_width" is left out
+pub const DEFAULT_TILES_PER_PANE: usize = 10;
+
+// Changes the config's JSON object to the next version
+type Migration = fn(&mut Map<String, Value>);
+
+// Steps that bring a config up to the next version, applied in order from the config's own version
+const MIGRATIONS: [(u64, Migration); 2] = [(0, migrate_v0), (1, migrate_v1)];
+
+// Version 0 lengths were always mm, pin the units so a different default can never rescale them
+fn migrate_v0(cfg: &mut Map<String, Value>) {
+    cfg.entry("units").or_insert_with(|| json!("mm"));
+}
+
+// Version 1 paths were relative to the working directory, fix them to it
+fn migrate_v1(cfg: &mut Map<String, Value>) {
+    if let Ok(cwd) = std::env::current_dir() {
+        config_file::resolve_paths(cfg, &cwd);
+    }
+}
+
+/// Bring a config read from `path` up to CONFIG_VERSION.
+/// Returns a note for each migration applied, a version newer than this program is an error.
+pub(crate) fn migrate_config(value: &mut Value, path: &Path) -> Result<Vec<String>, TileError> {
+    let problem = |message: String, suggestion: Option<String>| TileError::Invalid {
+        path: path.to_path_buf(),
+        problems: vec![ConfigProblem { key: "version".to_owned(), message, suggestion }],
+    };
+    let cfg = match value.as_object_mut() {
+        Some(cfg) => cfg,
+        None => return Ok(Vec::new()),   // not an object, reported when the config is read
+    };
+
+    let version = match cfg.get("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| problem(format!("{} is not a version number", v),
+                                                     Some(format!("use {}", CONFIG_VERSION))))?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(problem(format!("config version {} is newer than this program supports", version),
+                           Some(format!("use version {} or update img_tile", CONFIG_VERSION))));
+    }
+
+    let mut notes: Vec<String> = Vec::new();
+    for (from, migrate) in MIGRATIONS.iter() {
+        if *from >= version {
+            migrate(cfg);
+            notes.push(format!("config {} migrated from version {} to {}", path.display(), from, from + 1));
+        }
+    }
+    cfg.insert("version".to_owned(), json!(CONFIG_VERSION));
+    Ok(notes)
+}
+
+/// Copy each setting of PAIRED_DEFAULTS left out of the config from its partner
+pub(crate) fn fill_paired_defaults(value: &mut Value) {
+    if let Some(cfg) = value.as_object_mut() {
+        for (key, partner) in PAIRED_DEFAULTS.iter() {
+            if !cfg.contains_key(*key) {
+                if let Some(v) = cfg.get(*partner).cloned() {
+                    cfg.insert(key.to_string(), v);
+                }
+            }
+        }
+    }
+}
+
+/// Names of all the config settings
+pub(crate) fn known_keys() -> Vec<String> {
+    config_schema()["properties"].as_object().map(|p| p.keys().cloned().collect()).unwrap_or_default()
+}
+
+/// "did you mean" suggestion for a key that is not a setting, None when it is one
+pub(crate) fn check_key(key: &str) -> Option<Option<String>> {
+    let known = known_keys();
+    let known: Vec<&str> = known.iter().map(|k| k.as_str()).collect();
+    if known.contains(&key) {
+        None
+    } else {
+        Some(validate::closest_name(key, &known).map(|name| format!("did you mean {}?", name)))
+    }
+}
+
+/// Keys in the config that are not settings, each with a "did you mean" suggestion when one is close
+pub(crate) fn unknown_keys(value: &Value) -> Vec<(String, Option<String>)> {
+    match value.as_object() {
+        Some(cfg) => cfg.keys().filter_map(|key| check_key(key).map(|suggestion| (key.to_owned(), suggestion))).collect(),
+        None => Vec::new(),
+    }
+}
+
+// A length setting, a number in "units" or a string with its own unit
+fn length(description: &str) -> Value {
+    json!({
+        "description": description,
+        "oneOf": [
+            { "type": "number" },
+            { "type": "string", "pattern": format!("^\\s*[0-9.]+\\s*({})?\\s*$", units::unit_names().join("|")) }
+        ]
+    })
+}
+
+// A setting taking one of a list of names
+fn named(description: &str, names: Vec<&str>) -> Value {
+    json!({ "description": description, "type": "string", "enum": names })
+}
+
+/// JSON Schema (draft 07) of the config file
+pub fn config_schema() -> Value {
+    let mut paper: Vec<&str> = page_layout::paper_names();
+    paper.push("custom");
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "img_tile config",
+        "type": "object",
+        "required": ["input", "output", "output_width", "tile_size_x"],
+        "properties": {
+            "$schema": { "description": "schema file for editors, ignored by img_tile", "type": "string" },
+            "version": { "description": "config file format version", "type": "integer", "minimum": 0, "maximum": CONFIG_VERSION },
+            "extends": { "description": "config file (json, toml or yaml) this config overrides settings of", "type": "string" },
+            "tile_colors": { "description": "tile colour palette json file relative to this config, builtin:lego or builtin:perler for the palettes built in, the preset's palette when empty", "type": "string" },
+            "input": { "description": "image to make the mosaic from, relative to this config", "type": "string" },
+            "output": { "description": "mosaic image to write relative to this config, the pdf and json are written next to it", "type": "string" },
+            "output_width": length("largest width of the mosaic"),
+            "output_height": length("largest height of the mosaic, output_width when not set"),
+            "tile_size_x": length("tile width"),
+            "tile_size_y": length("tile height, tile_size_x when not set"),
+            "tile_space_x": length("space between tiles across, 0 when not set"),
+            "tile_space_y": length("space between tiles down, tile_space_x when not set"),
+            "tiles_per_pane_width": { "description": "tiles across each pane", "type": "integer", "minimum": 1, "default": DEFAULT_TILES_PER_PANE },
+            "tiles_per_pane_height": { "description": "tiles down each pane, tiles_per_pane_width when not set", "type": "integer", "minimum": 1 },
+            "preset": named("board preset, fixes the pane and tile size", preset::preset_names()),
+            "font": { "description": "font file or standard pdf font name", "type": "string" },
+            "heading_font": { "description": "font for page titles", "type": "string" },
+            "page": {
+                "description": "instruction pdf page size, orientation and margins in mm",
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "size": named("paper size", paper),
+                    "width": { "type": "number", "description": "custom page width" },
+                    "height": { "type": "number", "description": "custom page height" },
+                    "orientation": named("page orientation", vec!["landscape", "portrait"]),
+                    "margin": { "type": "number", "description": "all four margins" },
+                    "margin_top": { "type": "number" },
+                    "margin_right": { "type": "number" },
+                    "margin_bottom": { "type": "number" },
+                    "margin_left": { "type": "number" }
+                }
+            },
+            "summary_order": named("order of the tile colour summary", pdf_util::summary_order_names()),
+            "tile_symbols": named("symbol drawn on each tile", tile_symbols::symbol_scheme_names()),
+            "print_mode": named("colour or black and white pane pages", tile_symbols::print_mode_names()),
+            "title": { "description": "project title on the cover page", "type": "string" },
+            "author": { "description": "author on the cover page", "type": "string" },
+            "checklist": { "description": "add assembly checklist and pick list pages", "type": "boolean" },
+            "units": named("unit of lengths given as plain numbers", units::unit_names()),
+            "preview_dpi": { "description": "pixels per inch of the rendered mosaic image", "type": "number", "exclusiveMinimum": 0, "default": units::DEFAULT_PREVIEW_DPI },
+            "dither": named("how tile colours are matched to the palette, error diffusion mixes palette colours", dither::dither_mode_names())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_old_configs_to_the_current_version() {
+        let cwd = std::env::current_dir().unwrap();
+        let mut value = json!({ "tile_colors": "tile_json/colors.json", "font": "Helvetica", "output_width": 600 });
+        let notes = migrate_config(&mut value, Path::new("old.json")).unwrap();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(value, json!({ "version": CONFIG_VERSION, "units": "mm", "font": "Helvetica", "output_width": 600,
+                                  "tile_colors": cwd.join("tile_json/colors.json").to_string_lossy() }));
+
+        // version 1 already has its units, only the paths are fixed
+        let mut value = json!({ "version": 1, "units": "cm", "input": "/images/in.png" });
+        assert_eq!(migrate_config(&mut value, Path::new("v1.json")).unwrap().len(), 1);
+        assert_eq!(value, json!({ "version": CONFIG_VERSION, "units": "cm", "input": "/images/in.png" }));
+    }
+
+    #[test]
+    fn current_configs_are_unchanged() {
+        let mut value = json!({ "version": CONFIG_VERSION, "input": "in.png" });
+        assert!(migrate_config(&mut value, Path::new("new.json")).unwrap().is_empty());
+        assert_eq!(value, json!({ "version": CONFIG_VERSION, "input": "in.png" }));
+    }
+
+    #[test]
+    fn newer_or_bad_versions_are_an_error() {
+        for version in [json!(CONFIG_VERSION + 1), json!("2"), json!(-1)] {
+            let mut value = json!({ "version": version });
+            match migrate_config(&mut value, Path::new("config.json")) {
+                Err(TileError::Invalid { problems, .. }) => assert_eq!(problems[0].key, "version"),
+                other => panic!("expected version {} to be reported, got {:?}", version, other),
+            }
+        }
+    }
+
+    #[test]
+    fn fills_paired_settings_left_out() {
+        let mut value = json!({ "tile_size_x": 8, "tile_space_x": 1, "tile_space_y": 2 });
+        fill_paired_defaults(&mut value);
+        assert_eq!(value, json!({ "tile_size_x": 8, "tile_size_y": 8, "tile_space_x": 1, "tile_space_y": 2 }));
+    }
+
+    #[test]
+    fn suggests_known_settings() {
+        assert_eq!(check_key("tile_size_x"), None);
+        assert_eq!(check_key("tile_sise_x"), Some(Some("did you mean tile_size_x?".to_owned())));
+        assert_eq!(unknown_keys(&json!({ "version": 2, "titel": "x" })), vec![("titel".to_owned(), Some("did you mean title?".to_owned()))]);
+    }
+}
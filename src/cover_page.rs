@@ -0,0 +1,163 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use printpdf::*;
+
+use crate::fonts::PdfFonts;
+use crate::modtile;
+use crate::page_layout::PageLayout;
+use crate::units;
+
+// Cover page of the instructions pdf, the first page before the overview.
+// Shows the finished mosaic next to the original image with the project title and author from
+// the config, the palette used and the size of the build.
+
+// Largest side in pixels of an image embedded on the cover, larger images are scaled down
+// so a big mosaic does not blow up the size of the pdf
+const COVER_IMAGE_MAX_PX: u32 = 800;
+
+// Resolution the cover images are embedded at before being scaled to fit their box
+const COVER_IMAGE_DPI: f64 = 300.0;
+
+/// Size of the build shown on the cover
+pub(crate) struct CoverStats {
+    pub(crate) tile_cols: usize,
+    pub(crate) tile_rows: usize,
+    pub(crate) pane_cols: usize,
+    pub(crate) pane_rows: usize,
+    pub(crate) pane_label: &'static str,   // "Pane" or "Board" when built with a board preset
+}
+
+/// Draw the cover page on `current_layer`. `images` are the rendered mosaic preview and the
+/// original input image, drawn side by side above the project details.
+pub(crate) fn draw_cover_page(current_layer: &PdfLayerReference,
+                              fonts: &PdfFonts,
+                              page: &PageLayout,
+                              all_colors: &modtile::AllColors,
+                              cfg: &modtile::Config,
+                              (preview_img, input_img): (&DynamicImage, &DynamicImage),
+                              stats: &CoverStats) {
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let gray = Color::Rgb(Rgb::new(0.4, 0.4, 0.4, None));
+    let mm_per_pt = 25.4 / 72.0;
+
+    // title in the top margin and the author just below it
+    let title = cover_title(cfg);
+    current_layer.set_fill_color(black.clone());
+    current_layer.use_text(title, 24.0, Mm(page.margin_left_mm), Mm(page.header_y_mm()), &fonts.heading);
+    let mut text_top_mm = page.content_top_mm();
+    if let Some(author) = cfg.author.as_deref().filter(|a| !a.trim().is_empty()) {
+        text_top_mm -= 14.0 * mm_per_pt + 2.0;
+        current_layer.use_text(format!("by {}", author), 14.0, Mm(page.margin_left_mm), Mm(text_top_mm), &fonts.regular);
+    }
+
+    // mosaic preview on the left and the original on the right, each in half the content width
+    let gap_mm = 10.0;
+    let caption_mm = 8.0;
+    let box_wid_mm = (page.content_width_mm() - gap_mm) / 2.0;
+    let box_hgt_mm = (page.content_height_mm() * 0.55 - (page.content_top_mm() - text_top_mm)).max(20.0);
+    let box_top_mm = text_top_mm - 4.0;
+    let captioned = [(preview_img, "Mosaic", FilterType::Nearest), (input_img, "Original image", FilterType::Triangle)];
+    for (i, (img, caption, filter)) in captioned.iter().enumerate() {
+        let box_x_mm = page.margin_left_mm + i as f64 * (box_wid_mm + gap_mm);
+        let (img_wid_mm, img_hgt_mm) = draw_fitted_image(current_layer, img, *filter,
+                                                         (box_x_mm, box_top_mm - caption_mm, box_wid_mm, box_hgt_mm - caption_mm));
+        current_layer.set_fill_color(gray.clone());
+        current_layer.use_text(*caption, 10.0, Mm(box_x_mm + (box_wid_mm - img_wid_mm) / 2.0),
+                               Mm(box_top_mm - caption_mm - img_hgt_mm - 5.0), &fonts.regular);
+    }
+
+    // project details below the images, one line each
+    let tile_total = stats.tile_cols * stats.tile_rows;
+    let (build_wid, build_hgt) = units::finished_size_mm(cfg, stats.tile_cols, stats.tile_rows);
+    let mut details: Vec<(&str, String)> = vec![("Palette", all_colors.name.to_owned())];
+    if !all_colors.url.trim().is_empty() {
+        details.push(("", all_colors.url.to_owned()));
+    }
+    let desc_chars = ((page.content_width_mm() - 40.0) / 2.0).floor().max(20.0) as usize;
+    for line in wrap_words(&all_colors.description, desc_chars) {
+        details.push(("", line));
+    }
+    details.push(("Finished size", format!("{} x {}", units::format_length(build_wid, cfg), units::format_length(build_hgt, cfg))));
+    details.push(("Tile size", format!("{} x {}", units::format_length(cfg.tile_size_x.mm, cfg), units::format_length(cfg.tile_size_y.mm, cfg))));
+    details.push(("Tiles", format!("{} ({} across, {} down)", tile_total, stats.tile_cols, stats.tile_rows)));
+    details.push((if stats.pane_label == "Board" { "Boards" } else { "Panes" },
+                  format!("{} ({} across, {} down)", stats.pane_cols * stats.pane_rows, stats.pane_cols, stats.pane_rows)));
+
+    let line_hgt_mm = 6.0;
+    let mut line_y_mm = box_top_mm - box_hgt_mm - 10.0;
+    current_layer.set_fill_color(black);
+    for (label, value) in details {
+        if line_y_mm < page.margin_bottom_mm {
+            break;
+        }
+        current_layer.use_text(label, 11.0, Mm(page.margin_left_mm), Mm(line_y_mm), &fonts.heading);
+        current_layer.use_text(value, 11.0, Mm(page.margin_left_mm + 40.0), Mm(line_y_mm), &fonts.regular);
+        line_y_mm -= line_hgt_mm;
+    }
+} // draw_cover_page
+
+// Title from the config, or one made from the input image file name
+fn cover_title(cfg: &modtile::Config) -> String {
+    match cfg.title.as_deref().filter(|t| !t.trim().is_empty()) {
+        Some(title) => title.to_owned(),
+        None => {
+            let stem = std::path::Path::new(&cfg.input).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+            format!("Mosaic of {}", stem)
+        }
+    }
+}
+
+// Scale `img` to fit the box (x, top, width, height) keeping its aspect ratio and draw it centred
+// at the top of the box. Returns the drawn width and height in mm.
+fn draw_fitted_image(current_layer: &PdfLayerReference,
+                     img: &DynamicImage,
+                     filter: FilterType,
+                     (box_x_mm, box_top_mm, box_wid_mm, box_hgt_mm): (f64, f64, f64, f64)) -> (f64, f64) {
+
+    // embed rgb without alpha, shrunk to a sensible size
+    let (wid_px, hgt_px) = img.dimensions();
+    let small = if wid_px.max(hgt_px) > COVER_IMAGE_MAX_PX {
+        img.resize(COVER_IMAGE_MAX_PX, COVER_IMAGE_MAX_PX, filter)
+    } else {
+        img.clone()
+    };
+    let small = DynamicImage::ImageRgb8(small.to_rgb8());
+    let (wid_px, hgt_px) = small.dimensions();
+    if wid_px == 0 || hgt_px == 0 {
+        return (0.0, 0.0);
+    }
+
+    let natural_wid_mm = wid_px as f64 * 25.4 / COVER_IMAGE_DPI;
+    let natural_hgt_mm = hgt_px as f64 * 25.4 / COVER_IMAGE_DPI;
+    let scale = (box_wid_mm / natural_wid_mm).min(box_hgt_mm / natural_hgt_mm);
+    let img_wid_mm = natural_wid_mm * scale;
+    let img_hgt_mm = natural_hgt_mm * scale;
+
+    Image::from_dynamic_image(&small).add_to_layer(current_layer.clone(),
+                                                   Some(Mm(box_x_mm + (box_wid_mm - img_wid_mm) / 2.0)),
+                                                   Some(Mm(box_top_mm - img_hgt_mm)),
+                                                   None,
+                                                   Some(scale),
+                                                   Some(scale),
+                                                   Some(COVER_IMAGE_DPI));
+    (img_wid_mm, img_hgt_mm)
+} // draw_fitted_image
+
+// Break text into lines of at most `max_chars` characters at spaces
+fn wrap_words(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,434 @@
+use printpdf::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use euclid::Box2D;
+
+use crate::modtile::{self, RGB};
+use crate::page_layout::PageLayout;
+use crate::fonts::{self, PdfFonts};
+use crate::pdf_util::{add_pane_pages, get_points_for_line, get_points_for_rect};
+
+// Chart symbols in the order they are handed out (most used colour first).
+// Characters that are easily confused when printed small (I l 1 0 o O c x v) are left out.
+const STITCH_SYMBOLS: &str = "X+#*=%@&$/?<>^~ABCDEFGHJKLMNPQRSTUVWYZabdefghkmnpqrstuwyz23456789";
+
+// Range of rows and columns shown on a chart page.
+// `core_rows` and `core_cols` are the stitches of the pane itself, the rest is overlap with neighbouring panes.
+// All ranges are (start, end) with end exclusive.
+struct ChartWindow {
+    rows: (usize, usize),
+    cols: (usize, usize),
+    core_rows: (usize, usize),
+    core_cols: (usize, usize),
+}
+
+/// One entry in the stitch key. Every colour used in the design gets its own symbol.
+pub(crate) struct StitchKey {
+    pub(crate) rgb: RGB,
+    pub(crate) name: String,
+    pub(crate) number: String,
+    pub(crate) symbol: String,
+    pub(crate) count: usize,
+}
+
+// Return the chart symbol for the n'th colour.
+// Once the single character symbols run out two characters are combined.
+pub(crate) fn stitch_symbol(n: usize) -> String {
+    let symbols: Vec<char> = STITCH_SYMBOLS.chars().collect();
+    if n < symbols.len() {
+        symbols[n].to_string()
+    } else {
+        format!("{}{}", symbols[n % symbols.len()], symbols[(n / symbols.len() - 1) % symbols.len()])
+    }
+}
+
+/// Write a cross stitch / needlepoint pattern where every tile of the mosaic is a single stitch.
+///
+/// Two files are written next to `save_path`
+///   `<name>_stitch.pdf` - a symbol chart. The first page(s) hold the stitch key followed by one
+///                         chart page per window pane. Each chart page repeats `overlap` rows and
+///                         columns of the neighbouring panes (greyed out) to make it easy to line up pages.
+///   `<name>.oxs`        - the same pattern in Open Cross Stitch XML format
+///
+/// Returns the list of files written.
+pub fn write_cross_stitch(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>],
+                          all_colors: &modtile::AllColors,
+                          cfg: &modtile::Config,
+                          overlap: usize,
+                          save_path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+
+    let grid: Vec<Vec<RGB>> = modtile::get_tile_grid(output_window);
+    let key: Vec<StitchKey> = build_stitch_key(&grid, all_colors);
+
+    let stem = save_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+
+    let pdf_path = save_path.with_file_name(format!("{}_stitch.pdf", stem));
+    write_chart_pdf(&grid, &key, &stem, cfg, overlap, &pdf_path)?;
+
+    let oxs_path = save_path.with_extension("oxs");
+    write_oxs(&grid, &key, &stem, &oxs_path)?;
+
+    Ok(vec![pdf_path, oxs_path])
+} // write_cross_stitch
+
+// Count the stitches of each colour and assign symbols, most used colour first.
+// Ties are broken by position in the palette so the key is the same on every run.
+fn build_stitch_key(grid: &[Vec<RGB>], all_colors: &modtile::AllColors) -> Vec<StitchKey> {
+
+    let mut stitch_count: HashMap<RGB, usize> = HashMap::new();
+    for rgb in grid.iter().flatten() {
+        *stitch_count.entry(*rgb).or_insert(0) += 1;
+    }
+
+    let palette_pos = |rgb: &RGB| all_colors.colors.iter().position(|tc| tc.rgb == *rgb).unwrap_or(usize::MAX);
+    let mut counts: Vec<(RGB, usize)> = stitch_count.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(palette_pos(&a.0).cmp(&palette_pos(&b.0))));
+
+    counts.iter()
+          .enumerate()
+          .map(|(i, (rgb, count))| {
+              let tc = all_colors.colors.iter().find(|tc| tc.rgb == *rgb);
+              StitchKey {
+                  rgb: *rgb,
+                  name: tc.map(|tc| tc.name.to_owned()).unwrap_or_else(|| rgb.to_string()),
+                  number: tc.map(|tc| tc.number.to_owned()).unwrap_or_default(),
+                  symbol: stitch_symbol(i),
+                  count: *count,
+              }
+          })
+          .collect()
+} // build_stitch_key
+
+fn write_chart_pdf(grid: &[Vec<RGB>],
+                   key: &[StitchKey],
+                   title: &str,
+                   cfg: &modtile::Config,
+                   overlap: usize,
+                   pdf_path: &Path) -> Result<(), Box<dyn Error>> {
+
+    let page_layout: PageLayout = PageLayout::from_config(cfg.page.as_ref())?;
+    let (doc, page1, layer1) =
+        PdfDocument::new(title, Mm(page_layout.width_mm), Mm(page_layout.height_mm), "Layer 1");
+
+    let pdf_fonts: PdfFonts = fonts::load_pdf_fonts(&doc, cfg)?;
+
+    let grid_rows = grid.len();
+    let grid_cols = grid.first().map(|r| r.len()).unwrap_or(0);
+
+    construct_stitch_key_pages(&doc, doc.get_page(page1).get_layer(layer1), &pdf_fonts, key, (grid_cols, grid_rows), &page_layout);
+
+    let symbols: HashMap<RGB, &str> = key.iter().map(|k| (k.rgb, k.symbol.as_str())).collect();
+
+    // one chart page per pane, same order as the pane detail pages
+    let pane_count = (grid_cols / cfg.tiles_per_pane_width, grid_rows / cfg.tiles_per_pane_height);
+    add_pane_pages(&doc, &page_layout, pane_count, |pane_page| {
+        let current_layer = &pane_page.layer;
+
+        // core pane rows and columns
+        let core_rows = (pane_page.pane_row * cfg.tiles_per_pane_height, (pane_page.pane_row + 1) * cfg.tiles_per_pane_height);
+        let core_cols = (pane_page.pane_col * cfg.tiles_per_pane_width, (pane_page.pane_col + 1) * cfg.tiles_per_pane_width);
+
+        // extend by the overlap, limited to the edge of the design
+        let chart = ChartWindow {
+            rows: (core_rows.0.saturating_sub(overlap), (core_rows.1 + overlap).min(grid_rows)),
+            cols: (core_cols.0.saturating_sub(overlap), (core_cols.1 + overlap).min(grid_cols)),
+            core_rows,
+            core_cols,
+        };
+
+        draw_chart_page(current_layer, &pdf_fonts, grid, &symbols, &chart, &page_layout);
+
+        let fill_color = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+        current_layer.set_fill_color(fill_color);
+        let pn: String = format!("Pane {} - rows {} to {}, columns {} to {}", pane_page.pane_no, core_rows.0 + 1, core_rows.1, core_cols.0 + 1, core_cols.1);
+        current_layer.use_text(pn, 14.0, Mm(page_layout.margin_left_mm), Mm(page_layout.footer_y_mm()), &pdf_fonts.regular);
+    });
+
+    doc.save(&mut BufWriter::new(File::create(pdf_path)?))?;
+    Ok(())
+} // write_chart_pdf
+
+// List every symbol with its colour, name, number and stitch count.
+// Two columns of entries per page, extra pages are added as needed.
+fn construct_stitch_key_pages(doc: &PdfDocumentReference,
+                              first_layer: PdfLayerReference,
+                              fonts: &PdfFonts,
+                              key: &[StitchKey],
+                              (grid_cols, grid_rows): (usize, usize),
+                              page_layout: &PageLayout) {
+
+    let entry_height_mm = 8.0;
+    let box_mm = 6.0;
+    let col_width_mm = page_layout.content_width_mm() / 2.0;
+    let first_entry_y_mm = page_layout.content_top_mm() - 20.0;
+    let entries_per_col = ((first_entry_y_mm - page_layout.margin_bottom_mm) / entry_height_mm).floor().max(1.0) as usize;
+    let entries_per_page = 2 * entries_per_col;
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let mut current_layer = first_layer;
+    for (i, entry) in key.iter().enumerate() {
+        if i > 0 && i % entries_per_page == 0 {
+            let (page, layer) = doc.add_page(Mm(page_layout.width_mm), Mm(page_layout.height_mm), "Layer 1");
+            current_layer = doc.get_page(page).get_layer(layer);
+        }
+        if i % entries_per_page == 0 {
+            current_layer.set_fill_color(black.clone());
+            current_layer.use_text("Stitch Key", 24.0, Mm(page_layout.margin_left_mm), Mm(page_layout.content_top_mm()), &fonts.heading);
+            let info = format!("{} x {} stitches, {} colours", grid_cols, grid_rows, key.len());
+            current_layer.use_text(info, 12.0, Mm(page_layout.margin_left_mm), Mm(page_layout.content_top_mm() - 8.0), &fonts.regular);
+        }
+
+        let pos = i % entries_per_page;
+        let x_mm = page_layout.margin_left_mm + (pos / entries_per_col) as f64 * col_width_mm;
+        let y_mm = first_entry_y_mm - (pos % entries_per_col) as f64 * entry_height_mm;
+
+        // symbol as it appears on the chart
+        current_layer.set_outline_color(black.clone());
+        current_layer.set_outline_thickness(0.5);
+        current_layer.add_shape(Line {
+            points: get_points_for_rect(Mm(box_mm), Mm(box_mm), Mm(x_mm), Mm(y_mm)),
+            is_closed: true,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+        current_layer.set_fill_color(black.clone());
+        draw_symbol(&current_layer, &fonts.regular, &entry.symbol, x_mm + box_mm / 2.0, y_mm + box_mm / 2.0, box_mm);
+
+        // colour swatch
+        current_layer.set_fill_color(Color::Rgb(Rgb::new(entry.rgb.0 as f64 / 255.0, entry.rgb.1 as f64 / 255.0, entry.rgb.2 as f64 / 255.0, None)));
+        current_layer.add_shape(Line {
+            points: get_points_for_rect(Mm(box_mm), Mm(box_mm), Mm(x_mm + box_mm + 2.0), Mm(y_mm)),
+            is_closed: true,
+            has_fill: true,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+
+        current_layer.set_fill_color(black.clone());
+        let text = format!("{} {} - {} stitches", entry.name, entry.number, entry.count);
+        current_layer.use_text(text, 11.0, Mm(x_mm + 2.0 * box_mm + 5.0), Mm(y_mm + 1.5), &fonts.regular);
+    }
+} // construct_stitch_key_pages
+
+// Draw the chart for the given range of rows and columns.
+// Cells outside of the core pane range are the overlap with neighbouring panes and are greyed out.
+// Grid lines are drawn for every stitch with a bold line every 10 stitches counted from the top left of the design.
+fn draw_chart_page(current_layer: &PdfLayerReference,
+                   fonts: &PdfFonts,
+                   grid: &[Vec<RGB>],
+                   symbols: &HashMap<RGB, &str>,
+                   chart: &ChartWindow,
+                   page_layout: &PageLayout) {
+
+    let (rows, cols) = (chart.rows, chart.cols);
+
+    let column_numbers_mm = 5.0;   // leave room for the column numbers above the chart
+
+    let row_count = rows.1 - rows.0;
+    let col_count = cols.1 - cols.0;
+    let cell_mm = (page_layout.content_width_mm() / col_count as f64)
+                      .min((page_layout.content_height_mm() - column_numbers_mm) / row_count as f64);
+
+    // top left corner of the chart. PDF origin is the lower left of the page.
+    let chart_left_mm = page_layout.margin_left_mm;
+    let chart_top_mm = page_layout.content_top_mm() - column_numbers_mm;
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let gray = Color::Rgb(Rgb::new(0.6, 0.6, 0.6, None));
+    let light_gray = Color::Rgb(Rgb::new(0.9, 0.9, 0.9, None));
+
+    // symbols
+    for (row, grid_row) in grid.iter().enumerate().take(rows.1).skip(rows.0) {
+        for (col, rgb) in grid_row.iter().enumerate().take(cols.1).skip(cols.0) {
+            let x_mm = chart_left_mm + (col - cols.0) as f64 * cell_mm;
+            let y_mm = chart_top_mm - (row - rows.0 + 1) as f64 * cell_mm;
+            let in_core = row >= chart.core_rows.0 && row < chart.core_rows.1 && col >= chart.core_cols.0 && col < chart.core_cols.1;
+
+            if !in_core {
+                current_layer.set_fill_color(light_gray.clone());
+                current_layer.add_shape(Line {
+                    points: get_points_for_rect(Mm(cell_mm), Mm(cell_mm), Mm(x_mm), Mm(y_mm)),
+                    is_closed: true,
+                    has_fill: true,
+                    has_stroke: false,
+                    is_clipping_path: false,
+                });
+            }
+
+            current_layer.set_fill_color(if in_core { black.clone() } else { gray.clone() });
+            if let Some(symbol) = symbols.get(rgb) {
+                draw_symbol(current_layer, &fonts.regular, symbol, x_mm + cell_mm / 2.0, y_mm + cell_mm / 2.0, cell_mm);
+            }
+        }
+    }
+
+    // grid lines, vertical then horizontal
+    current_layer.set_outline_color(black.clone());
+    current_layer.set_fill_color(black.clone());
+    let chart_bottom_mm = chart_top_mm - row_count as f64 * cell_mm;
+    let chart_right_mm = chart_left_mm + col_count as f64 * cell_mm;
+    for col in cols.0..=cols.1 {
+        let x_mm = chart_left_mm + (col - cols.0) as f64 * cell_mm;
+        current_layer.set_outline_thickness(if col % 10 == 0 { 1.5 } else { 0.3 });
+        current_layer.add_shape(Line {
+            points: get_points_for_line(Mm(x_mm), Mm(chart_bottom_mm), Mm(x_mm), Mm(chart_top_mm)),
+            is_closed: false,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+        if col % 10 == 0 && col > 0 {
+            current_layer.use_text(col.to_string(), 9.0, Mm(x_mm - 2.0), Mm(chart_top_mm + 2.0), &fonts.regular);
+        }
+    }
+    for row in rows.0..=rows.1 {
+        let y_mm = chart_top_mm - (row - rows.0) as f64 * cell_mm;
+        current_layer.set_outline_thickness(if row % 10 == 0 { 1.5 } else { 0.3 });
+        current_layer.add_shape(Line {
+            points: get_points_for_line(Mm(chart_left_mm), Mm(y_mm), Mm(chart_right_mm), Mm(y_mm)),
+            is_closed: false,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+        if row % 10 == 0 && row > 0 {
+            current_layer.use_text(row.to_string(), 9.0, Mm(chart_left_mm - 8.0), Mm(y_mm - 1.0), &fonts.regular);
+        }
+    }
+} // draw_chart_page
+
+// Write a chart symbol centered on (center_x_mm, center_y_mm) scaled to fit a cell of cell_mm.
+// Uses the current fill colour of the layer.
+pub(crate) fn draw_symbol(current_layer: &PdfLayerReference,
+                          font: &IndirectFontRef,
+                          symbol: &str,
+                          center_x_mm: f64,
+                          center_y_mm: f64,
+                          cell_mm: f64) {
+
+    // 1 mm is 2.835 pt. Glyphs are roughly 0.6 of the font size wide and 0.7 high.
+    let char_count = symbol.chars().count().max(1) as f64;
+    let font_size_pt = (cell_mm * 2.835 * 0.8 / char_count.sqrt()).max(2.0);
+    let font_size_mm = font_size_pt / 2.835;
+    let x_mm = center_x_mm - 0.3 * font_size_mm * char_count;
+    let y_mm = center_y_mm - 0.35 * font_size_mm;
+    current_layer.use_text(symbol, font_size_pt, Mm(x_mm), Mm(y_mm), font);
+} // draw_symbol
+
+// Write the pattern in Open Cross Stitch (OXS) format.
+// Palette index 0 is reserved for the fabric, stitch x/y are zero based from the top left.
+fn write_oxs(grid: &[Vec<RGB>], key: &[StitchKey], title: &str, oxs_path: &Path) -> Result<(), Box<dyn Error>> {
+
+    let grid_rows = grid.len();
+    let grid_cols = grid.first().map(|r| r.len()).unwrap_or(0);
+    let hex = |rgb: &RGB| format!("{:02X}{:02X}{:02X}", rgb.0, rgb.1, rgb.2);
+
+    let mut buf = String::new();
+    buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    buf.push_str("<chart>\n");
+    buf.push_str("<format comments01=\"Designed to allow interchange of basic pattern data between any cross stitch style software\" />\n");
+    buf.push_str(&format!("<properties oxsversion=\"1.0\" software=\"img_tile\" software_version=\"{}\" chartheight=\"{}\" chartwidth=\"{}\" charttitle=\"{}\" author=\"\" copyright=\"\" instructions=\"\" stitchesperinch=\"14\" stitchesperinch_y=\"14\" palettecount=\"{}\" />\n",
+                          env!("CARGO_PKG_VERSION"), grid_rows, grid_cols, xml_escape(title), key.len()));
+
+    buf.push_str("<palette>\n");
+    buf.push_str("<palette_item index=\"0\" number=\"cloth\" name=\"cloth\" color=\"FFFFFF\" printcolor=\"FFFFFF\" blendcolor=\"nil\" comments=\"aida\" strands=\"2\" symbol=\"0\" dashpattern=\"\" bsstrands=\"0\" bscolor=\"nil\" />\n");
+    for (i, entry) in key.iter().enumerate() {
+        buf.push_str(&format!("<palette_item index=\"{}\" number=\"{}\" name=\"{}\" color=\"{}\" printcolor=\"{}\" blendcolor=\"nil\" comments=\"\" strands=\"2\" symbol=\"{}\" dashpattern=\"\" bsstrands=\"0\" bscolor=\"nil\" />\n",
+                              i + 1, xml_escape(&entry.number), xml_escape(&entry.name), hex(&entry.rgb), hex(&entry.rgb), xml_escape(&entry.symbol)));
+    }
+    buf.push_str("</palette>\n");
+
+    let pal_index: HashMap<RGB, usize> = key.iter().enumerate().map(|(i, k)| (k.rgb, i + 1)).collect();
+    buf.push_str("<fullstitches>\n");
+    for (y, row) in grid.iter().enumerate() {
+        for (x, rgb) in row.iter().enumerate() {
+            buf.push_str(&format!("<stitch x=\"{}\" y=\"{}\" palindex=\"{}\" />\n", x, y, pal_index[rgb]));
+        }
+    }
+    buf.push_str("</fullstitches>\n");
+    buf.push_str("<partstitches>\n</partstitches>\n");
+    buf.push_str("<backstitches>\n</backstitches>\n");
+    buf.push_str("<ornaments_inc_knots_and_beads>\n</ornaments_inc_knots_and_beads>\n");
+    buf.push_str("<commentboxes>\n</commentboxes>\n");
+    buf.push_str("</chart>\n");
+
+    let mut file = File::create(oxs_path)?;
+    file.write_all(buf.as_bytes())?;
+    Ok(())
+} // write_oxs
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+     .replace('"', "&quot;")
+     .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modtile::{AllColors, TileColor};
+    use std::collections::HashSet;
+
+    const RED: RGB = RGB(255, 0, 0);
+    const BLUE: RGB = RGB(0, 0, 255);
+
+    fn palette() -> AllColors {
+        AllColors {
+            name: "test".to_owned(),
+            url: String::new(),
+            description: String::new(),
+            colors: vec![TileColor { rgb: BLUE, name: "Blue".to_owned(), number: "7".to_owned(), ldraw: None },
+                         TileColor { rgb: RED, name: "Red & Rose".to_owned(), number: "3".to_owned(), ldraw: None }],
+        }
+    }
+
+    #[test]
+    fn stitch_symbols_are_unique() {
+        let single = STITCH_SYMBOLS.chars().count();
+        let symbols: Vec<String> = (0..single * (single + 1)).map(stitch_symbol).collect();
+        let unique: HashSet<&String> = symbols.iter().collect();
+        assert_eq!(unique.len(), symbols.len());
+        assert_eq!(stitch_symbol(0), "X");
+        assert_eq!(stitch_symbol(single - 1).chars().count(), 1);
+        assert_eq!(stitch_symbol(single), "XX");
+        assert_eq!(stitch_symbol(single + 1), "+X");
+    }
+
+    #[test]
+    fn stitch_key_puts_the_most_used_colour_first() {
+        // a tie in count is broken by palette order, colours not in the palette are named by their rgb
+        let grid = vec![vec![RED, BLUE, RGB(1, 2, 3)], vec![RED, BLUE, RED]];
+        let key = build_stitch_key(&grid, &palette());
+        let entries: Vec<(&str, &str, &str, usize)> = key.iter().map(|k| (k.name.as_str(), k.number.as_str(), k.symbol.as_str(), k.count)).collect();
+        assert_eq!(entries[0], ("Red & Rose", "3", "X", 3));
+        assert_eq!(entries[1], ("Blue", "7", "+", 2));
+        assert_eq!((entries[2].1, entries[2].2, entries[2].3), ("", "#", 1));
+    }
+
+    #[test]
+    fn writes_every_stitch_to_the_oxs_file() {
+        let grid = vec![vec![RED, BLUE], vec![RED, RED]];
+        let key = build_stitch_key(&grid, &palette());
+        let path = std::env::temp_dir().join(format!("img_tile_stitch_{}.oxs", std::process::id()));
+        write_oxs(&grid, &key, "Cat <1>", &path).unwrap();
+        let oxs = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(oxs.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<chart>\n"));
+        assert!(oxs.contains("chartheight=\"2\" chartwidth=\"2\" charttitle=\"Cat &lt;1&gt;\""));
+        assert!(oxs.contains("palettecount=\"2\""));
+        assert!(oxs.contains("<palette_item index=\"1\" number=\"3\" name=\"Red &amp; Rose\" color=\"FF0000\" printcolor=\"FF0000\""));
+        assert!(oxs.contains("<palette_item index=\"2\" number=\"7\" name=\"Blue\" color=\"0000FF\""));
+        let stitches: Vec<&str> = oxs.lines().filter(|line| line.starts_with("<stitch ")).collect();
+        assert_eq!(stitches, ["<stitch x=\"0\" y=\"0\" palindex=\"1\" />", "<stitch x=\"1\" y=\"0\" palindex=\"2\" />",
+                              "<stitch x=\"0\" y=\"1\" palindex=\"1\" />", "<stitch x=\"1\" y=\"1\" palindex=\"1\" />"]);
+        assert!(oxs.ends_with("</chart>\n"));
+    }
+}
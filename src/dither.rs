@@ -0,0 +1,124 @@
+use ego_tree::Tree;
+use std::convert::TryFrom;
+
+use crate::kd_tree::{construct_kd_tree, query_nearest_neighbor};
+use crate::modtile::RGB;
+
+// How tile colours are matched to the palette, set with "dither" in the config
+//   none             each tile gets the palette colour closest to its average colour (default)
+//   floyd-steinberg  the difference between a tile and its palette colour is passed on to the tiles
+//                    right and below it, so areas between two palette colours mix them
+//   atkinson         passes on 3/4 of the difference over a wider area, keeps more contrast than floyd-steinberg
+// Dithering raises the error of single tiles but keeps the tone of larger areas, useful with small palettes.
+pub const DITHER_MODES: [&str; 3] = ["none", "floyd-steinberg", "atkinson"];
+
+// (column offset, row offset, share of the difference) passed on to the tiles not matched yet
+const FLOYD_STEINBERG: [(i64, i64, f64); 4] = [(1, 0, 7.0 / 16.0), (-1, 1, 3.0 / 16.0), (0, 1, 5.0 / 16.0), (1, 1, 1.0 / 16.0)];
+const ATKINSON: [(i64, i64, f64); 6] = [(1, 0, 0.125), (2, 0, 0.125), (-1, 1, 0.125), (0, 1, 0.125), (1, 1, 0.125), (0, 2, 0.125)];
+
+// Names of the dither modes, used for command line help and error messages
+pub fn dither_mode_names() -> Vec<&'static str> {
+    DITHER_MODES.to_vec()
+}
+
+/// Match each tile's average colour in `averages` (rows top to bottom) to a colour of `palette`
+/// using the dither `mode`, one of DITHER_MODES. Returns the palette colour of every tile.
+pub fn match_tiles(averages: &[Vec<RGB>], palette: &mut [Vec<u8>], mode: &str) -> Vec<Vec<RGB>> {
+
+    let kd_tree: Tree<Vec<u8>> = construct_kd_tree(palette, 3);
+    let spread: &[(i64, i64, f64)] = match mode {
+        "floyd-steinberg" => &FLOYD_STEINBERG,
+        "atkinson" => &ATKINSON,
+        _ => &[],
+    };
+
+    // colour each tile should have, its average plus the difference passed on by the tiles before it
+    let mut wanted: Vec<Vec<[f64; 3]>> = averages.iter()
+        .map(|row| row.iter().map(|c| [c.0 as f64, c.1 as f64, c.2 as f64]).collect())
+        .collect();
+    let mut matched: Vec<Vec<RGB>> = averages.iter().map(|row| vec![RGB(0, 0, 0); row.len()]).collect();
+
+    for row in 0..wanted.len() {
+        for col in 0..wanted[row].len() {
+            let want = wanted[row][col];
+            let query: Vec<u8> = want.iter().map(|v| v.round().clamp(0.0, 255.0) as u8).collect();
+            let closest = query_nearest_neighbor(&query, &kd_tree, 3, kd_tree.root()).value();
+            matched[row][col] = RGB(closest[0], closest[1], closest[2]);
+
+            for (dx, dy, share) in spread.iter() {
+                let neighbour = usize::try_from(row as i64 + dy).ok()
+                    .and_then(|r| wanted.get_mut(r))
+                    .and_then(|r| usize::try_from(col as i64 + dx).ok().and_then(move |c| r.get_mut(c)));
+                if let Some(neighbour) = neighbour {
+                    for i in 0..3 {
+                        neighbour[i] += (want[i] - closest[i] as f64) * share;
+                    }
+                }
+            }
+        }
+    }
+    matched
+} // match_tiles
+
+/// Distance between two colours in RGB space, 0 for the same colour up to 441 for black and white
+pub fn color_error(a: &RGB, b: &RGB) -> f64 {
+    let d = |x: u8, y: u8| (x as f64 - y as f64).powi(2);
+    (d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: RGB = RGB(0, 0, 0);
+    const WHITE: RGB = RGB(255, 255, 255);
+
+    fn black_and_white() -> Vec<Vec<u8>> {
+        vec![vec![0, 0, 0], vec![255, 255, 255]]
+    }
+
+    fn grey_field(cols: usize, rows: usize) -> Vec<Vec<RGB>> {
+        vec![vec![RGB(128, 128, 128); cols]; rows]
+    }
+
+    fn white_share(matched: &[Vec<RGB>]) -> f64 {
+        let tiles: Vec<&RGB> = matched.iter().flatten().collect();
+        tiles.iter().filter(|c| ***c == WHITE).count() as f64 / tiles.len() as f64
+    }
+
+    #[test]
+    fn none_matches_each_tile_to_the_closest_colour() {
+        let averages = vec![vec![RGB(10, 20, 30), RGB(250, 200, 240)], vec![RGB(128, 128, 128), RGB(127, 127, 127)]];
+        let matched = match_tiles(&averages, &mut black_and_white(), "none");
+        assert_eq!(matched, vec![vec![BLACK, WHITE], vec![WHITE, BLACK]]);
+        assert_eq!(white_share(&match_tiles(&grey_field(10, 10), &mut black_and_white(), "none")), 1.0);
+    }
+
+    #[test]
+    fn dithering_mixes_palette_colours_to_keep_the_tone() {
+        for mode in ["floyd-steinberg", "atkinson"] {
+            let matched = match_tiles(&grey_field(20, 20), &mut black_and_white(), mode);
+            assert_eq!(matched.len(), 20);
+            assert!(matched.iter().all(|row| row.len() == 20));
+            let share = white_share(&matched);
+            assert!(share > 0.4 && share < 0.6, "{} made {} of a mid grey white", mode, share);
+        }
+    }
+
+    #[test]
+    fn matches_single_rows_columns_and_colours() {
+        for mode in DITHER_MODES {
+            assert_eq!(match_tiles(&grey_field(1, 3), &mut black_and_white(), mode).len(), 3);
+            assert_eq!(match_tiles(&grey_field(3, 1), &mut black_and_white(), mode)[0].len(), 3);
+            assert!(match_tiles(&[], &mut black_and_white(), mode).is_empty());
+            assert_eq!(match_tiles(&grey_field(2, 2), &mut [vec![255, 0, 0]], mode), vec![vec![RGB(255, 0, 0); 2]; 2]);
+        }
+    }
+
+    #[test]
+    fn colour_error_is_the_rgb_distance() {
+        assert_eq!(color_error(&BLACK, &BLACK), 0.0);
+        assert_eq!(color_error(&RGB(0, 3, 0), &RGB(4, 0, 0)), 5.0);
+        assert_eq!(color_error(&BLACK, &WHITE).round(), 442.0);
+    }
+}
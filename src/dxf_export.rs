@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use euclid::Box2D;
+
+use crate::modtile::{self, RGB};
+use crate::pdf_util;
+
+// AutoCAD Color Index values for the basic colours. Used to give each layer a colour
+// close to the tile colour so the cut file is easier to read in a CAD viewer.
+const ACI_COLORS: [(i32, RGB); 9] = [
+    (1, RGB(255, 0, 0)),
+    (2, RGB(255, 255, 0)),
+    (3, RGB(0, 255, 0)),
+    (4, RGB(0, 255, 255)),
+    (5, RGB(0, 0, 255)),
+    (6, RGB(255, 0, 255)),
+    (7, RGB(255, 255, 255)),
+    (8, RGB(128, 128, 128)),
+    (9, RGB(192, 192, 192)),
+];
+
+/// A single tile outline in physical units (same units as the config file, normally mm)
+/// The origin is the lower left corner of the mosaic, y increases upwards as in the PDF.
+struct DxfTile {
+    pane_no: usize,
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+    rgb: RGB,
+}
+
+/// Write the tile outlines of the mosaic to DXF (R12/ASCII) cut files.
+///
+/// Tile positions are taken from the same PDF space tile geometry used by `draw_tiles` and
+/// scaled so that each tile is `cfg.tile_size_x` by `cfg.tile_size_y` with `cfg.tile_space_x` and
+/// `cfg.tile_space_y` between neighbouring tiles. Each tile colour is written to its own layer named after `TileColor.name`.
+///
+/// When `split_panes` is set one file per pane is written next to `save_path` (`<name>_pane_<n>.dxf`)
+/// with the pane's lower left tile at the origin, otherwise the whole mosaic goes into `<name>.dxf`.
+///
+/// Returns the list of files written.
+pub fn write_dxf(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>],
+                 all_colors: &modtile::AllColors,
+                 cfg: &modtile::Config,
+                 split_panes: bool,
+                 save_path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+
+    let tiles = get_dxf_tiles(output_window, cfg.tile_size_x.mm, cfg.tile_size_y.mm, cfg.tile_space_x.mm, cfg.tile_space_y.mm);
+    let layers = get_layer_names(&tiles, all_colors);
+
+    let mut written: Vec<PathBuf> = Vec::new();
+    if split_panes {
+        for pane_no in 1..=output_window.len() {
+            let pane_tiles: Vec<&DxfTile> = tiles.iter().filter(|t| t.pane_no == pane_no).collect();
+
+            // move the lower left corner of the pane to the origin
+            let x_off = pane_tiles.iter().map(|t| t.min_x).fold(f64::MAX, f64::min);
+            let y_off = pane_tiles.iter().map(|t| t.min_y).fold(f64::MAX, f64::min);
+
+            let stem = save_path.file_stem().unwrap_or_default().to_string_lossy();
+            let file_path = save_path.with_file_name(format!("{}_pane_{}.dxf", stem, pane_no));
+            write_dxf_file(&file_path, &pane_tiles, &layers, x_off, y_off)?;
+            written.push(file_path);
+        }
+    } else {
+        let all_tiles: Vec<&DxfTile> = tiles.iter().collect();
+        let file_path = save_path.with_extension("dxf");
+        write_dxf_file(&file_path, &all_tiles, &layers, 0.0, 0.0)?;
+        written.push(file_path);
+    }
+
+    Ok(written)
+} // write_dxf
+
+// Translate the image space tile boxes into physical tile outlines.
+// The boxes are first converted to PDF coord space (see pdf_util::get_pane_pdf_coords) so that the
+// tile row count starts at the bottom of the mosaic, which matches the DXF coordinate system.
+fn get_dxf_tiles(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>],
+                 tile_size_x: f64,
+                 tile_size_y: f64,
+                 tile_space_x: f64,
+                 tile_space_y: f64) -> Vec<DxfTile> {
+
+    let p_cfg: pdf_util::PanePdfConfig = pdf_util::get_pane_pdf_coords(output_window);
+    let pdf_output_window = pdf_util::get_pdf_coords(output_window, p_cfg.max_pane_y_px);
+
+    // size of a single tile in image px, see construct_window_panes
+    let imgtile_wid_px: f64 = (p_cfg.max_pane_x_px as f64 + 1.0) / p_cfg.pane_col_count as f64 / p_cfg.pane_tile_col_count as f64;
+    let imgtile_hgt_px: f64 = (p_cfg.max_pane_y_px as f64 + 1.0) / p_cfg.pane_row_count as f64 / p_cfg.pane_tile_row_count as f64;
+
+    let pitch_x = tile_size_x + tile_space_x;
+    let pitch_y = tile_size_y + tile_space_y;
+
+    let mut tiles: Vec<DxfTile> = Vec::new();
+    for (i, pane) in pdf_output_window.iter().enumerate() {
+        for tile in pane {
+            let col = (tile.0.min.x as f64 / imgtile_wid_px).round();
+            let row = (tile.0.min.y as f64 / imgtile_hgt_px).round();
+
+            tiles.push(DxfTile {
+                pane_no: i + 1,
+                min_x: col * pitch_x,
+                min_y: row * pitch_y,
+                max_x: col * pitch_x + tile_size_x,
+                max_y: row * pitch_y + tile_size_y,
+                rgb: tile.1,
+            });
+        }
+    }
+    tiles
+} // get_dxf_tiles
+
+// Build a DXF safe, unique layer name for every tile colour used in the mosaic
+fn get_layer_names(tiles: &[DxfTile], all_colors: &modtile::AllColors) -> Vec<(RGB, String)> {
+
+    let mut layers: Vec<(RGB, String)> = Vec::new();
+    for tile in tiles {
+        if layers.iter().any(|(rgb, _)| *rgb == tile.rgb) {
+            continue;
+        }
+
+        let base = match all_colors.colors.iter().find(|tc| tc.rgb == tile.rgb) {
+            Some(tc) => sanitize_layer_name(&tc.name),
+            None => format!("RGB_{}_{}_{}", tile.rgb.0, tile.rgb.1, tile.rgb.2),
+        };
+
+        // palettes can contain the same name more than once, number it past any name already taken
+        // keeping within the 31 characters
+        let mut name = base.clone();
+        let mut n = 1;
+        while layers.iter().any(|(_, used)| *used == name) {
+            n += 1;
+            let suffix = format!("_{}", n);
+            name = format!("{}{}", base.chars().take(31 - suffix.len()).collect::<String>(), suffix);
+        }
+        layers.push((tile.rgb, name));
+    }
+    layers
+} // get_layer_names
+
+// R12 layer names may only contain letters, digits, '$', '-' and '_' and are limited to 31 characters
+fn sanitize_layer_name(name: &str) -> String {
+    let clean: String = name.trim()
+                            .chars()
+                            .map(|c| if c.is_ascii_alphanumeric() || c == '$' || c == '-' || c == '_' { c.to_ascii_uppercase() } else { '_' })
+                            .take(31)
+                            .collect();
+    if clean.is_empty() { "TILE".to_owned() } else { clean }
+}
+
+// Pick the closest of the basic AutoCAD colours for a tile colour
+fn nearest_aci(rgb: &RGB) -> i32 {
+    let dist = |c: &RGB| -> i32 {
+        let dr = rgb.0 as i32 - c.0 as i32;
+        let dg = rgb.1 as i32 - c.1 as i32;
+        let db = rgb.2 as i32 - c.2 as i32;
+        dr * dr + dg * dg + db * db
+    };
+    ACI_COLORS.iter().min_by_key(|(_, c)| dist(c)).map(|(aci, _)| *aci).unwrap_or(7)
+}
+
+// Write a DXF group code and value pair
+fn pair<V: Display>(buf: &mut String, code: i32, value: V) {
+    buf.push_str(&format!("{:>3}\n{}\n", code, value));
+}
+
+fn write_dxf_file(file_path: &Path,
+                  tiles: &[&DxfTile],
+                  layers: &[(RGB, String)],
+                  x_off: f64,
+                  y_off: f64) -> Result<(), Box<dyn Error>> {
+
+    let mut buf = String::new();
+
+    // HEADER
+    pair(&mut buf, 0, "SECTION");
+    pair(&mut buf, 2, "HEADER");
+    pair(&mut buf, 9, "$ACADVER");
+    pair(&mut buf, 1, "AC1009");
+    pair(&mut buf, 0, "ENDSEC");
+
+    // TABLES - one layer per tile colour
+    pair(&mut buf, 0, "SECTION");
+    pair(&mut buf, 2, "TABLES");
+    pair(&mut buf, 0, "TABLE");
+    pair(&mut buf, 2, "LAYER");
+    pair(&mut buf, 70, layers.len());
+    for (rgb, name) in layers {
+        pair(&mut buf, 0, "LAYER");
+        pair(&mut buf, 2, name);
+        pair(&mut buf, 70, 0);
+        pair(&mut buf, 62, nearest_aci(rgb));
+        pair(&mut buf, 6, "CONTINUOUS");
+    }
+    pair(&mut buf, 0, "ENDTAB");
+    pair(&mut buf, 0, "ENDSEC");
+
+    // ENTITIES - a closed polyline for every tile outline
+    let layer_names: HashMap<RGB, &String> = layers.iter().map(|(rgb, name)| (*rgb, name)).collect();
+    pair(&mut buf, 0, "SECTION");
+    pair(&mut buf, 2, "ENTITIES");
+    for tile in tiles {
+        let layer = layer_names[&tile.rgb];
+        pair(&mut buf, 0, "POLYLINE");
+        pair(&mut buf, 8, layer);
+        pair(&mut buf, 66, 1);
+        pair(&mut buf, 10, "0.0");
+        pair(&mut buf, 20, "0.0");
+        pair(&mut buf, 30, "0.0");
+        pair(&mut buf, 70, 1); // closed polyline
+
+        let corners = [(tile.min_x, tile.min_y),
+                       (tile.max_x, tile.min_y),
+                       (tile.max_x, tile.max_y),
+                       (tile.min_x, tile.max_y)];
+        for (x, y) in corners.iter() {
+            pair(&mut buf, 0, "VERTEX");
+            pair(&mut buf, 8, layer);
+            pair(&mut buf, 10, format!("{:.4}", x - x_off));
+            pair(&mut buf, 20, format!("{:.4}", y - y_off));
+            pair(&mut buf, 30, "0.0");
+        }
+        pair(&mut buf, 0, "SEQEND");
+        pair(&mut buf, 8, layer);
+    }
+    pair(&mut buf, 0, "ENDSEC");
+    pair(&mut buf, 0, "EOF");
+
+    let mut file = File::create(file_path)?;
+    file.write_all(buf.as_bytes())?;
+
+    Ok(())
+} // write_dxf_file
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modtile::{AllColors, TileColor};
+
+    fn palette(names: &[(&str, RGB)]) -> AllColors {
+        AllColors {
+            name: "test".to_owned(),
+            url: String::new(),
+            description: String::new(),
+            colors: names.iter().map(|(name, rgb)| TileColor { rgb: *rgb, name: name.to_string(), number: String::new(), ldraw: None }).collect(),
+        }
+    }
+
+    fn tile(rgb: RGB) -> DxfTile {
+        DxfTile { pane_no: 1, min_x: 0.0, min_y: 0.0, max_x: 1.0, max_y: 1.0, rgb }
+    }
+
+    #[test]
+    fn nearest_aci_picks_the_closest_basic_colour() {
+        assert_eq!(nearest_aci(&RGB(250, 10, 10)), 1);
+        assert_eq!(nearest_aci(&RGB(0, 0, 140)), 5);
+        assert_eq!(nearest_aci(&RGB(255, 255, 255)), 7);
+        assert_eq!(nearest_aci(&RGB(120, 120, 130)), 8);
+        assert_eq!(nearest_aci(&RGB(200, 200, 190)), 9);
+        // black is nearest the dark grey
+        assert_eq!(nearest_aci(&RGB(0, 0, 0)), 8);
+    }
+
+    #[test]
+    fn layer_names_are_r12_safe() {
+        assert_eq!(sanitize_layer_name(" Bright Light Orange "), "BRIGHT_LIGHT_ORANGE");
+        assert_eq!(sanitize_layer_name("Tan/Beige (#2)"), "TAN_BEIGE___2_");
+        assert_eq!(sanitize_layer_name("$dark-grey_1"), "$DARK-GREY_1");
+        assert_eq!(sanitize_layer_name("   "), "TILE");
+        assert_eq!(sanitize_layer_name(&"x".repeat(40)).len(), 31);
+    }
+
+    #[test]
+    fn layer_names_are_unique() {
+        let long = "a very long colour name that goes on";
+        let all_colors = palette(&[("Red", RGB(255, 0, 0)), ("red", RGB(250, 0, 0)), ("Red_2", RGB(240, 0, 0)),
+                                   (long, RGB(1, 1, 1)), (long, RGB(2, 2, 2))]);
+        let tiles: Vec<DxfTile> = [RGB(255, 0, 0), RGB(250, 0, 0), RGB(255, 0, 0), RGB(240, 0, 0), RGB(1, 1, 1), RGB(2, 2, 2), RGB(3, 4, 5)]
+            .iter().map(|rgb| tile(*rgb)).collect();
+        let names: Vec<String> = get_layer_names(&tiles, &all_colors).into_iter().map(|(_, name)| name).collect();
+        assert_eq!(names, ["RED", "RED_2", "RED_2_2", "A_VERY_LONG_COLOUR_NAME_THAT_GO", "A_VERY_LONG_COLOUR_NAME_THAT__2", "RGB_3_4_5"]);
+    }
+
+    #[test]
+    fn tiles_are_placed_at_the_tile_pitch() {
+        // 4 x 2 tiles of 10 px in two panes of 2 x 2, the top left tile red
+        let mut output_window = crate::create_out_panes(40.0, 20.0, 4, 2, 2, 2);
+        let top_left = output_window[0].iter_mut().find(|(b, _)| b.min.x == 0 && b.min.y == 0).unwrap();
+        top_left.1 = RGB(255, 0, 0);
+
+        let tiles = get_dxf_tiles(&output_window, 5.0, 4.0, 1.0, 2.0);
+        assert_eq!(tiles.len(), 8);
+        let mut corners: Vec<(usize, f64, f64)> = tiles.iter().map(|t| (t.pane_no, t.min_x, t.min_y)).collect();
+        corners.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(corners, [(1, 0.0, 0.0), (1, 0.0, 6.0), (1, 6.0, 0.0), (1, 6.0, 6.0),
+                             (2, 12.0, 0.0), (2, 12.0, 6.0), (2, 18.0, 0.0), (2, 18.0, 6.0)]);
+        assert!(tiles.iter().all(|t| t.max_x - t.min_x == 5.0 && t.max_y - t.min_y == 4.0));
+
+        // the image's top row is the top of the cut file, y increases upwards
+        let red = tiles.iter().find(|t| t.rgb == RGB(255, 0, 0)).unwrap();
+        assert_eq!((red.min_x, red.min_y), (0.0, 6.0));
+    }
+}
@@ -0,0 +1,159 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+// Errors that stop a mosaic being made, JSON, TOML and YAML parse errors give the line and column of the problem.
+// main prints the error and exits with its exit code so scripts can tell a bad config from a missing image or a full disk.
+//   3 - config file (or a config it extends) missing or not valid JSON, TOML or YAML
+//   4 - tile colour palette file missing or not valid JSON, or failing the palette check
+//   5 - input image missing or could not be decoded
+//   6 - settings that cannot be laid out, e.g. an unknown preset or panes larger than the mosaic,
+//       the config checks in validate.rs report all such problems at once
+//   7 - an output file could not be written
+//   8 - render --watch could not watch the input files
+// A batch with failed variants exits with the code of the first failure, 101 for a variant that crashed.
+// Exit code 2 is left to clap for command line errors.
+#[derive(Debug)]
+pub enum TileError {
+    ConfigRead { path: PathBuf, source: std::io::Error },
+    ConfigParse { path: PathBuf, source: Box<dyn Error> },
+    PaletteRead { path: PathBuf, source: std::io::Error },
+    PaletteParse { path: PathBuf, source: serde_json::Error },
+    PaletteInvalid { path: PathBuf, problems: Vec<ConfigProblem> },
+    Image { path: PathBuf, source: image::ImageError },
+    Layout(String),
+    Invalid { path: PathBuf, problems: Vec<ConfigProblem> },
+    Output { path: PathBuf, source: Box<dyn Error> },
+    Export { what: &'static str, source: Box<dyn Error> },
+    Batch { failed: usize, total: usize, index: PathBuf, code: i32 },
+    Watch(notify::Error),
+}
+
+impl TileError {
+
+    /// Process exit code for the error, see the list above
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TileError::ConfigRead { .. } | TileError::ConfigParse { .. } => 3,
+            TileError::PaletteRead { .. } | TileError::PaletteParse { .. } | TileError::PaletteInvalid { .. } => 4,
+            TileError::Image { .. } => 5,
+            TileError::Layout(_) | TileError::Invalid { .. } => 6,
+            TileError::Output { .. } | TileError::Export { .. } => 7,
+            TileError::Batch { code, .. } => *code,
+            TileError::Watch(_) => 8,
+        }
+    }
+
+    /// An output file at `path` could not be written
+    pub fn output<E: Into<Box<dyn Error>>>(path: &Path, source: E) -> TileError {
+        TileError::Output { path: path.to_path_buf(), source: source.into() }
+    }
+}
+
+impl Display for TileError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TileError::ConfigRead { path, source } =>
+                write!(f, "Could not read config file {} - {}", path.display(), source),
+            TileError::ConfigParse { path, source } =>
+                write!(f, "Invalid config file {} - {}", path.display(), source),
+            TileError::PaletteRead { path, source } =>
+                write!(f, "Could not read tile colour file {} - {}", path.display(), source),
+            TileError::PaletteParse { path, source } =>
+                write!(f, "Invalid tile colour file {} - {}", path.display(), source),
+            TileError::PaletteInvalid { path, problems } => {
+                write!(f, "{} problem{} in tile colour file {}", problems.len(), if problems.len() == 1 { "" } else { "s" }, path.display())?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+            TileError::Image { path, source } =>
+                write!(f, "Could not load input image {} - {}", path.display(), source),
+            TileError::Layout(msg) =>
+                write!(f, "Invalid mosaic settings - {}", msg),
+            TileError::Invalid { path, problems } => {
+                write!(f, "{} problem{} in config file {}", problems.len(), if problems.len() == 1 { "" } else { "s" }, path.display())?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+            TileError::Output { path, source } =>
+                write!(f, "Could not write {} - {}", path.display(), source),
+            TileError::Export { what, source } =>
+                write!(f, "Could not create {} - {}", what, source),
+            TileError::Batch { failed, total, index, .. } =>
+                write!(f, "{} of {} batch variants failed, see {}", failed, total, index.display()),
+            TileError::Watch(source) =>
+                write!(f, "Could not watch the input files for changes - {}", source),
+        }
+    }
+}
+
+impl Error for TileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TileError::ConfigRead { source, .. } | TileError::PaletteRead { source, .. } => Some(source),
+            TileError::ConfigParse { source, .. } => Some(source.as_ref()),
+            TileError::PaletteParse { source, .. } => Some(source),
+            TileError::Image { source, .. } => Some(source),
+            TileError::Watch(source) => Some(source),
+            TileError::Layout(_) | TileError::Invalid { .. } | TileError::PaletteInvalid { .. } | TileError::Batch { .. } => None,
+            TileError::Output { source, .. } | TileError::Export { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+/// A problem with one config setting found by the config checks, `key` is the JSON key at fault.
+/// Also used for palette problems found by "palette check", `key` is then the palette entry
+#[derive(Debug)]
+pub struct ConfigProblem {
+    pub key: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl Display for ConfigProblem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "\"{}\": {}", self.key, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " - {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn io_error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "missing")
+    }
+
+    // the exit codes are part of the command line interface, scripts rely on them
+    #[test]
+    fn each_error_has_its_exit_code() {
+        let path = PathBuf::from("file");
+        let json_error = || serde_json::from_str::<u8>("x").unwrap_err();
+        let errors: Vec<(TileError, i32)> = vec![
+            (TileError::ConfigRead { path: path.clone(), source: io_error() }, 3),
+            (TileError::ConfigParse { path: path.clone(), source: json_error().into() }, 3),
+            (TileError::PaletteRead { path: path.clone(), source: io_error() }, 4),
+            (TileError::PaletteParse { path: path.clone(), source: json_error() }, 4),
+            (TileError::PaletteInvalid { path: path.clone(), problems: Vec::new() }, 4),
+            (TileError::Image { path: path.clone(), source: image::ImageError::IoError(io_error()) }, 5),
+            (TileError::Layout("panes".to_owned()), 6),
+            (TileError::Invalid { path: path.clone(), problems: Vec::new() }, 6),
+            (TileError::output(&path, io_error()), 7),
+            (TileError::Export { what: "dxf", source: "failed".into() }, 7),
+            (TileError::Watch(notify::Error::generic("failed")), 8),
+            (TileError::Batch { failed: 1, total: 2, index: path.clone(), code: 5 }, 5),
+            (TileError::Batch { failed: 1, total: 2, index: path, code: crate::batch::PANIC_EXIT_CODE }, 101),
+        ];
+        for (error, code) in errors {
+            assert_eq!(error.exit_code(), code, "{}", error);
+        }
+    }
+}
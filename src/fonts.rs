@@ -0,0 +1,83 @@
+use printpdf::*;
+use std::fs::File;
+
+use crate::modtile;
+
+// Fonts built into the binary so PDFs can be created on any machine. See fonts/LICENSE-DejaVu.txt
+const DEFAULT_FONT: &[u8] = include_bytes!("../fonts/DejaVuSerif.ttf");
+const DEFAULT_HEADING_FONT: &[u8] = include_bytes!("../fonts/DejaVuSerif-Bold.ttf");
+
+// The standard PDF fonts, these can be named in the config instead of a font file
+const BUILTIN_FONTS: [BuiltinFont; 14] = [
+    BuiltinFont::TimesRoman,
+    BuiltinFont::TimesBold,
+    BuiltinFont::TimesItalic,
+    BuiltinFont::TimesBoldItalic,
+    BuiltinFont::Helvetica,
+    BuiltinFont::HelveticaBold,
+    BuiltinFont::HelveticaOblique,
+    BuiltinFont::HelveticaBoldOblique,
+    BuiltinFont::Courier,
+    BuiltinFont::CourierOblique,
+    BuiltinFont::CourierBold,
+    BuiltinFont::CourierBoldOblique,
+    BuiltinFont::Symbol,
+    BuiltinFont::ZapfDingbats,
+];
+
+/// Fonts used for all text in a PDF document.
+/// `regular` is used for body text, labels and tile numbers, `heading` for page titles and pane numbers.
+pub(crate) struct PdfFonts {
+    pub(crate) regular: IndirectFontRef,
+    pub(crate) heading: IndirectFontRef,
+}
+
+/// True for the names of the standard PDF fonts, any other font setting is a file
+pub(crate) fn is_builtin_font(name: &str) -> bool {
+    BUILTIN_FONTS.iter().any(|f| name == Into::<&'static str>::into(*f))
+}
+
+/// Add the fonts named in the config to the document.
+///
+/// `cfg.font` and `cfg.heading_font` are either the path to a TrueType/OpenType font file or the name
+/// of one of the standard PDF fonts (e.g. "Helvetica", "Times-Bold"). When not set the embedded
+/// DejaVu Serif fonts are used. If a font cannot be loaded a warning is printed and the
+/// built-in Helvetica (Helvetica-Bold for headings) is used instead.
+pub(crate) fn load_pdf_fonts(doc: &PdfDocumentReference, cfg: &modtile::Config) -> Result<PdfFonts, Error> {
+
+    let regular = load_font(doc, cfg.font.as_deref(), DEFAULT_FONT, BuiltinFont::Helvetica)?;
+    let heading = load_font(doc, cfg.heading_font.as_deref(), DEFAULT_HEADING_FONT, BuiltinFont::HelveticaBold)?;
+
+    Ok(PdfFonts { regular, heading })
+} // load_pdf_fonts
+
+// Load a single font from the config setting, the embedded font data or the built-in fallback
+fn load_font(doc: &PdfDocumentReference,
+             setting: Option<&str>,
+             embedded: &[u8],
+             fallback: BuiltinFont) -> Result<IndirectFontRef, Error> {
+
+    let loaded = match setting {
+        Some(name) => match BUILTIN_FONTS.iter().find(|f| name == Into::<&'static str>::into(**f)) {
+            Some(builtin) => doc.add_builtin_font(*builtin),
+            None => match File::open(name) {
+                Ok(file) => doc.add_external_font(file),
+                Err(e) => {
+                    let fallback_name: &'static str = fallback.into();
+                    log::warn!("Could not open font file {} - using {} - {}", name, fallback_name, e);
+                    return doc.add_builtin_font(fallback);
+                }
+            },
+        },
+        None => doc.add_external_font(embedded),
+    };
+
+    match loaded {
+        Ok(font) => Ok(font),
+        Err(e) => {
+            let fallback_name: &'static str = fallback.into();
+            log::warn!("Could not load font {} - using {} - {}", setting.unwrap_or("(embedded)"), fallback_name, e);
+            doc.add_builtin_font(fallback)
+        }
+    }
+} // load_font
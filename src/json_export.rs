@@ -5,10 +5,25 @@ use std::fs::File;
 use std::io::Write;
 
 use crate::modtile::{self, RGB};
+use crate::units;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct Config {
     tiles: Vec<Vec<RGB>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<PhysicalSize>,
+}
+
+// True size of the finished mosaic and its tiles so the tile array can be drawn to scale
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PhysicalSize {
+    width_mm: f64,
+    height_mm: f64,
+    tile_width_mm: f64,
+    tile_height_mm: f64,
+    tile_space_x_mm: f64,
+    tile_space_y_mm: f64,
+    preview_dpi: f64,      // pixels per inch of the rendered mosaic image
 }
 
 // #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -44,6 +59,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let config = Config {
         tiles: output_tiles,
+        size: None,
     };
 
     let serialized = serde_json::to_string(&config)?;
@@ -54,38 +70,36 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 /// Save the RGB values into an output_width_tile_count x output_height_tile_count array
-/// That can be read in by hack-svg or other program to construct input data
+/// That can be read in by hack-svg or other program to construct input data,
+/// along with the finished size of the mosaic and its tiles in mm
 pub fn dump_rgb_json(output_window: &Vec<Vec<(euclid::Box2D<i32, i32>, modtile::RGB)>>, 
             output_width_tile_count: usize, 
             output_height_tile_count: usize, 
             tiles_per_pane_width: usize, 
             tiles_per_pane_height: usize,
+            cfg: &modtile::Config,
             save_path: &Path) -> Result <(), Box<dyn Error>> 
 {
-    println!("\n dump_rbg_json *********");
-    println!(" output_width_tile_count {} ", output_width_tile_count);
-    println!("output_height_tile_count {} ", output_height_tile_count);
-    println!(" tiles_per_pane_width {} ", tiles_per_pane_width);
-    println!("tiles_per_pane_height {} ", tiles_per_pane_height);
-
-    println!("input_window.len {} ", output_window.len());
-
-    let mut output_tiles = vec![vec![RGB { 0: 0, 1: 0, 2: 0 }; output_width_tile_count ]; output_height_tile_count];
+    log::debug!("dump_rgb_json {} x {} tiles, panes of {} x {} tiles", output_width_tile_count, output_height_tile_count,
+                tiles_per_pane_width, tiles_per_pane_height);
 
-    // grab first pane
-    let first_pane = &output_window[0];
+    log::trace!("input_window.len {} ", output_window.len());
 
-    println!("first pane len = {:?}", &first_pane.len());
-
-    for (i, color) in first_pane.iter().enumerate() {
-        let row_idx = i / output_width_tile_count  ;
-        let col_idx = i % output_width_tile_count ;
-        // println!("i: {} , row_idx:{:?} , col_idx:{:?}",&i,  &row_idx,&col_idx);
-        output_tiles[row_idx][col_idx] = color.1;
-    }
+    // tiles of every pane in grid order, the pane tiles are in pane order
+    let output_tiles: Vec<Vec<RGB>> = modtile::get_tile_grid(output_window);
 
+    let (width_mm, height_mm) = units::finished_size_mm(cfg, output_width_tile_count, output_height_tile_count);
     let config = Config {
         tiles: output_tiles,
+        size: Some(PhysicalSize {
+            width_mm,
+            height_mm,
+            tile_width_mm: cfg.tile_size_x.mm,
+            tile_height_mm: cfg.tile_size_y.mm,
+            tile_space_x_mm: cfg.tile_space_x.mm,
+            tile_space_y_mm: cfg.tile_space_y.mm,
+            preview_dpi: units::preview_px_per_mm(cfg) * 25.4,
+        }),
     };
 
     let file_path = save_path.with_extension("json");
@@ -96,9 +110,16 @@ pub fn dump_rgb_json(output_window: &Vec<Vec<(euclid::Box2D<i32, i32>, modtile::
     Ok(())
 }
 
+/// Read back the tile colours saved by dump_rgb_json, one row of tiles per entry
+pub fn load_tile_grid(path: &Path) -> Result<Vec<Vec<RGB>>, Box<dyn Error>> {
+    let buf = std::fs::read(path)?;
+    let config: Config = serde_json::from_slice(&buf)?;
+    Ok(config.tiles)
+}
+
 fn _dump_info(input_window: &Vec<Vec<(euclid::Box2D<i32, i32>, modtile::RGB)>>)
  {
-    println!("\n YO ********* \n\n {:?} \n ********* \n\n", input_window);
+    log::trace!("input window {:?}", input_window);
 }
 
 
@@ -1,13 +1,13 @@
 // The KDTree implimentation CODE below is from forked github project
 // https://github.com/z2oh/chromatic_confinement
 use ego_tree::{NodeMut, NodeRef, Tree};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-static mut DIST_CALLS: u64 = 0;
+// atomic as batch runs match colours on several threads at once
+static DIST_CALLS: AtomicU64 = AtomicU64::new(0);
 
 pub fn dist_sq(a: &[u8], b: &[u8]) -> u32 {
-    unsafe {
-        DIST_CALLS += 1;
-    }
+    DIST_CALLS.fetch_add(1, Ordering::Relaxed);
     let mut sum: u32 = 0;
     for (aa, bb) in a.iter().zip(b.iter()) {
         let val: i32 = i32::from(*aa) - i32::from(*bb);
@@ -42,6 +42,9 @@ pub fn construct_kd_tree_recursive(
     } else if v.len() == 1 {
         node.append(v[0].clone());
         return;
+    } else if v.is_empty() {
+        // the other side of a one or two colour palette
+        return;
     }
     v.sort_unstable_by_key(|k| k[current_dim]);
     let middle: usize = v.len() / 2;
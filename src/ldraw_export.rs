@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use euclid::Box2D;
+
+use crate::modtile::{self, RGB};
+use crate::preset::BoardPreset;
+
+// LDraw units. One stud is 20 LDU and a plate is 8 LDU high. -Y is up.
+const LDU_PER_STUD: i32 = 20;
+const LDU_PLATE_HEIGHT: i32 = 8;
+
+// Pieces that can be used for the mosaic, name used on the command line, LDraw part file and
+// description for the board parts list
+pub const LDRAW_PIECES: [(&str, &str, &str); 4] = [
+    ("round-plate", "4073.dat", "LEGO Plate Round 1 x 1 (4073)"),
+    ("plate", "3024.dat", "LEGO Plate 1 x 1 (3024)"),
+    ("round-tile", "98138.dat", "LEGO Tile Round 1 x 1 (98138)"),
+    ("tile", "3070b.dat", "LEGO Tile 1 x 1 (3070b)"),
+];
+
+// Names of all the pieces, used for command line help
+pub fn ldraw_piece_names() -> Vec<&'static str> {
+    LDRAW_PIECES.iter().map(|(name, _, _)| *name).collect()
+}
+
+/// Write the tile plan as an LDraw model (`<name>.ldr` next to `save_path`).
+///
+/// Every tile becomes a 1 x 1 `piece` (see LDRAW_PIECES) laid flat with the top of the image at the back.
+/// When `board` names an LDraw board part each window pane gets its own board under the pieces.
+/// Each board is a separate building step so viewers show the model board by board.
+///
+/// Tile colours are mapped to LDraw colour codes using the `ldraw` field of the palette.
+/// Colours without a code are written as LDraw direct colours (0x2RRGGBB).
+pub fn write_ldraw(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>],
+                   all_colors: &modtile::AllColors,
+                   cfg: &modtile::Config,
+                   board: Option<&BoardPreset>,
+                   piece: &str,
+                   save_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+
+    let piece_file = match LDRAW_PIECES.iter().find(|(name, _, _)| *name == piece) {
+        Some((_, file, _)) => *file,
+        None => return Err(format!("Unknown LDraw piece {} - expected one of {:?}", piece, ldraw_piece_names()).into()),
+    };
+
+    let grid: Vec<Vec<RGB>> = modtile::get_tile_grid(output_window);
+    let grid_rows = grid.len();
+    let grid_cols = grid.first().map(|r| r.len()).unwrap_or(0);
+
+    let stem = save_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let file_path = save_path.with_extension("ldr");
+
+    let mut missing_codes: Vec<String> = Vec::new();
+    let mut colour_code = |rgb: &RGB| -> String {
+        match all_colors.colors.iter().find(|tc| tc.rgb == *rgb) {
+            Some(modtile::TileColor { ldraw: Some(code), .. }) => code.to_string(),
+            tc => {
+                let name = tc.map(|tc| tc.name.to_owned()).unwrap_or_else(|| rgb.to_string());
+                if !missing_codes.contains(&name) {
+                    missing_codes.push(name);
+                }
+                format!("0x2{:02X}{:02X}{:02X}", rgb.0, rgb.1, rgb.2)
+            }
+        }
+    };
+
+    let mut buf = String::new();
+    buf.push_str(&format!("0 {}\n", stem));
+    buf.push_str(&format!("0 Name: {}.ldr\n", stem));
+    buf.push_str("0 Author: img_tile\n");
+    buf.push_str("0 !LDRAW_ORG Unofficial_Model\n");
+    buf.push_str(&format!("0 // {} x {} mosaic using {}\n", grid_cols, grid_rows, all_colors.name));
+    buf.push('\n');
+
+    // one building step per pane (board), same order as the pane detail pages
+    let pane_rows = grid_rows / cfg.tiles_per_pane_height;
+    let pane_cols = grid_cols / cfg.tiles_per_pane_width;
+    for pane_row in 0..pane_rows {
+        for pane_col in 0..pane_cols {
+            let pane_no = pane_row * pane_cols + pane_col + 1;
+            let first_row = pane_row * cfg.tiles_per_pane_height;
+            let first_col = pane_col * cfg.tiles_per_pane_width;
+            buf.push_str(&format!("0 // Pane {}\n", pane_no));
+
+            // board centered under the pane, top surface at y = 0
+            if let Some(board_file) = board.and_then(|b| b.ldraw_board) {
+                let x = (first_col as i32 * 2 + cfg.tiles_per_pane_width as i32) * LDU_PER_STUD / 2;
+                let z = (first_row as i32 * 2 + cfg.tiles_per_pane_height as i32) * LDU_PER_STUD / 2;
+                buf.push_str(&format!("1 71 {} 0 {} 1 0 0 0 1 0 0 0 1 {}\n", x, z, board_file));
+            }
+
+            for (row, grid_row) in grid.iter().enumerate().skip(first_row).take(cfg.tiles_per_pane_height) {
+                for (col, rgb) in grid_row.iter().enumerate().skip(first_col).take(cfg.tiles_per_pane_width) {
+                    let x = col as i32 * LDU_PER_STUD + LDU_PER_STUD / 2;
+                    let z = row as i32 * LDU_PER_STUD + LDU_PER_STUD / 2;
+                    buf.push_str(&format!("1 {} {} {} {} 1 0 0 0 1 0 0 0 1 {}\n", colour_code(rgb), x, -LDU_PLATE_HEIGHT, z, piece_file));
+                }
+            }
+            buf.push_str("0 STEP\n");
+        }
+    }
+
+    if !missing_codes.is_empty() {
+        log::info!("No ldraw colour code in palette for {:?}, using direct colours", missing_codes);
+    }
+
+    let mut file = File::create(&file_path)?;
+    file.write_all(buf.as_bytes())?;
+
+    Ok(file_path)
+} // write_ldraw
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modtile::{AllColors, Config, TileColor};
+    use crate::preset;
+
+    const RED: RGB = RGB(255, 0, 0);
+    const ORANGE: RGB = RGB(255, 128, 0);
+
+    // 2 x 1 panes of 2 x 2 tiles, red but for an orange tile at the top right
+    fn write_model(board: Option<&BoardPreset>, piece: &str) -> Vec<String> {
+        let mut output_window = crate::create_out_panes(40.0, 20.0, 4, 2, 2, 2);
+        output_window.iter_mut().flatten().for_each(|tile| tile.1 = if tile.0.min.x == 30 && tile.0.min.y == 0 { ORANGE } else { RED });
+        let all_colors = AllColors {
+            name: "test".to_owned(),
+            url: String::new(),
+            description: String::new(),
+            colors: vec![TileColor { rgb: RED, name: "Red".to_owned(), number: "1".to_owned(), ldraw: Some(4) },
+                         TileColor { rgb: ORANGE, name: "Orange".to_owned(), number: "2".to_owned(), ldraw: None }],
+        };
+        let cfg = Config { tiles_per_pane_width: 2, tiles_per_pane_height: 2, ..Default::default() };
+        let save_path = std::env::temp_dir().join(format!("img_tile_ldraw_{}_{}.png", piece, std::process::id()));
+        let path = write_ldraw(&output_window, &all_colors, &cfg, board, piece, &save_path).unwrap();
+        let model = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        model.lines().map(str::to_owned).collect()
+    }
+
+    fn parts(model: &[String]) -> Vec<&str> {
+        model.iter().filter(|line| line.starts_with("1 ")).map(String::as_str).collect()
+    }
+
+    #[test]
+    fn pieces_take_the_palette_code_or_a_direct_colour() {
+        let model = write_model(None, "round-plate");
+        let parts = parts(&model);
+        assert_eq!(parts.len(), 8);
+        assert!(parts.contains(&"1 4 10 -8 10 1 0 0 0 1 0 0 0 1 4073.dat"));
+        assert!(parts.contains(&"1 0x2FF8000 70 -8 10 1 0 0 0 1 0 0 0 1 4073.dat"));
+        assert_eq!(parts.iter().filter(|line| line.starts_with("1 4 ")).count(), 7);
+        assert_eq!(model.iter().filter(|line| *line == "0 STEP").count(), 2);
+    }
+
+    #[test]
+    fn boards_lie_under_their_pane() {
+        let model = write_model(preset::get_preset("lego_16"), "tile");
+        let parts = parts(&model);
+        let boards: Vec<&&str> = parts.iter().filter(|line| line.ends_with("91405.dat")).collect();
+        assert_eq!(boards, [&"1 71 20 0 20 1 0 0 0 1 0 0 0 1 91405.dat", &"1 71 60 0 20 1 0 0 0 1 0 0 0 1 91405.dat"]);
+        // each board comes before its own pieces, which sit one plate up
+        assert!(parts[0].ends_with("91405.dat") && parts[5].ends_with("91405.dat"));
+        assert!(parts.iter().filter(|line| !line.ends_with("91405.dat")).all(|line| line.split(' ').nth(3) == Some("-8") && line.ends_with("3070b.dat")));
+    }
+
+    #[test]
+    fn boards_without_an_ldraw_part_are_left_out() {
+        let model = write_model(preset::get_preset("perler_29"), "plate");
+        assert!(parts(&model).iter().all(|line| line.ends_with("3024.dat")));
+    }
+}
@@ -0,0 +1,71 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde_json::json;
+use std::io::Write;
+use std::time::Instant;
+
+// Log messages go to stderr so stdout only carries command output such as "inspect" or "schema".
+//   -q    errors and warnings only
+//         progress and the run summary (default)
+//   -v    debug, the sizes and counts worked out along the way
+//   -vv   trace, everything including per tile and per pane detail
+// "--log-format json" writes one JSON object per line with the level, module, message and seconds since start.
+pub const LOG_FORMATS: [&str; 2] = ["text", "json"];
+
+// Names of the log formats, used for command line help
+pub fn log_format_names() -> Vec<&'static str> {
+    LOG_FORMATS.to_vec()
+}
+
+struct Logger {
+    level: LevelFilter,
+    json: bool,
+    started: Instant,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // only this program's messages, not those of the pdf and image libraries
+        metadata.level() <= self.level && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let module = record.target().rsplit("::").next().unwrap_or_default();
+        let line = if self.json {
+            json!({
+                "seconds": (self.started.elapsed().as_secs_f64() * 1000.0).round() / 1000.0,
+                "level": record.level().as_str().to_lowercase(),
+                "module": module,
+                "message": record.args().to_string(),
+            }).to_string()
+        } else {
+            match record.level() {
+                Level::Error => format!("Error: {}", record.args()),
+                Level::Warn => format!("Warning: {}", record.args()),
+                Level::Info => record.args().to_string(),
+                Level::Debug | Level::Trace => format!("[{}] {}", module, record.args()),
+            }
+        };
+        let _ = writeln!(std::io::stderr().lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// Start logging at `verbosity`, -1 for -q up to 2 for -vv, in `format`, one of LOG_FORMATS
+pub fn init(verbosity: i8, format: &str) {
+    let level = match verbosity {
+        i8::MIN..=-1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    let logger = Logger { level, json: format == "json", started: Instant::now() };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
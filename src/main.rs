@@ -1,9 +1,33 @@
 mod kd_tree;
+mod fonts;
 mod pdf_util;
 mod modtile;
 mod json_export;
-
-use clap::{Arg, Command};
+mod dxf_export;
+mod cross_stitch;
+mod preset;
+mod ldraw_export;
+mod page_layout;
+mod poster;
+mod swatch;
+mod tile_symbols;
+mod pdf_nav;
+mod cover_page;
+mod checklist;
+mod error;
+mod validate;
+mod units;
+mod config_schema;
+mod config_file;
+mod cli;
+mod palette_tools;
+mod dither;
+mod batch;
+mod logging;
+mod run_report;
+mod watch;
+
+use clap::ArgMatches;
 use euclid::{Point2D,Box2D};
 use image::{GenericImage, GenericImageView, RgbImage,Rgb};
 use image::DynamicImage;
@@ -11,79 +35,292 @@ use image::DynamicImage;
 use std::path::Path;
 use std::collections::HashMap;
 
-use kd_tree::{construct_kd_tree,query_nearest_neighbor};
 
 use crate::json_export::dump_rgb_json;
+use crate::error::TileError;
+use crate::run_report::RunReport;
 
+// Exit with the error's exit code instead of panicking so scripts can tell what went wrong, see error.rs
 fn main() {
-    let matches = Command::new("Image Play")
-        .version("0.1")
-        .author("bitbangr <mgj000@hotmail.com>")
-        .arg(
-            Arg::new("config")
-                .short('c')
-                .long("config")
-                .value_name("FILE")
-                .help("config settings for tiling")
-                .required(true),
-        )
-        .arg(
-            Arg::new("swatch")
-                .short('s')
-                .long("swatch")
-                .value_name("SWATCH")
-                .help("Used to generate a color swatch pdf")
-                .required(false),
-        )
-        .get_matches();
-
-    // load all the config settings from JSON file
-    let cfg: modtile::Config = modtile::load_configs(
-        matches.get_one::<String>("config").expect("config argument missing")
-    );
+    if let Err(e) = run() {
+        log::error!("{}", e);
+        std::process::exit(e.exit_code());
+    }
+}
 
-    println!();
-    println!("Successfully Loaded Config File -> {:?}", cfg);
+fn run() -> Result<(), TileError> {
+    let matches = cli::get_matches();
+    let verbosity: i8 = if matches.get_flag("quiet") { -1 } else { matches.get_count("verbose").min(2) as i8 };
+    logging::init(verbosity, matches.get_one::<String>("log_format").map(|s| s.as_str()).unwrap_or("text"));
+
+    match matches.subcommand() {
+        Some(("render", m)) => render(m),
+        Some(("validate", m)) => validate_command(m),
+        Some(("inspect", m)) => inspect(m),
+        Some(("pdf", m)) => remake_pdf(m),
+        Some(("swatch", m)) => swatch_command(m),
+        Some(("palette", m)) => palette_tools::palette_command(m),
+        Some(("schema", m)) => write_schema(m.get_one::<String>("file")),
+        Some(("batch", m)) => batch::batch_command(m),
+        // no subcommand, the render arguments given on their own
+        _ => render(&matches),
+    }
+}
 
-    // Grab the input image
-    // TODO Get some proper error handling here or in function for missing image
-    let input_img: DynamicImage = get_image(cfg.input).unwrap();
-    let (img_width, img_height): (u32, u32) = input_img.dimensions();
-    let input_img_width: f64 = img_width as f64;
-    let input_img_height: f64 = img_height as f64;
+// Load the config named on the command line with its overrides and board preset applied,
+// lengths resolved to mm and every setting checked
+fn load_run_config(matches: &ArgMatches) -> Result<RunConfig, TileError> {
 
-    println!();
-    println!("input image width: {}\ninput image height: {}", &input_img_width,&input_img_height );
+    // command line settings override the config, in the order given with --set last
+    let mut overrides: Vec<(String, String)> = Vec::new();
+    for (id, keys, _) in config_file::CONFIG_ARGS.iter() {
+        if let Some(value) = matches.get_one::<String>(id) {
+            overrides.extend(keys.iter().map(|key| (key.to_string(), value.to_owned())));
+        }
+    }
+    if let Some(settings) = matches.get_many::<(String, String)>("set") {
+        overrides.extend(settings.cloned());
+    }
+
+    let config_path: &str = matches.get_one::<String>("config").expect("config argument missing");
+    load_checked_config(config_path, &overrides, matches.get_one::<String>("preset").map(|s| s.as_str()))
+}
+
+// A checked config and the board preset it uses
+type RunConfig = (modtile::Config, Option<&'static preset::BoardPreset>);
+
+// Load the config at `config_path` with `overrides` and the board preset named `preset_name` applied,
+// lengths resolved to mm and every setting checked
+fn load_checked_config(config_path: &str, overrides: &[(String, String)], preset_name: Option<&str>) -> Result<RunConfig, TileError> {
+
+    // load all the config settings from the config file
+    let mut cfg: modtile::Config = modtile::load_configs(config_path, overrides)?;
+
+    // a board preset fixes the pane size and tile pitch to the board
+    if let Some(name) = preset_name {
+        cfg.preset = Some(name.to_owned());
+    }
+    let board: Option<&preset::BoardPreset> = cfg.preset.as_deref().and_then(preset::get_preset);
+    if let Some(board) = board {
+        preset::apply_preset(&mut cfg, board);
+    }
+    // lengths may be in cm, inches or pixels, from here on everything is in mm
+    units::resolve_lengths(&mut cfg);
+
+    // check every setting before doing any work, reporting all the problems at once.
+    // Only the image header is read here, the image itself is loaded later.
+    validate::validate_config(&cfg, config_path, image::image_dimensions(&cfg.input).ok())?;
+
+    Ok((cfg, board))
+} // load_checked_config
+
+// Size of the mosaic and its tile grid worked out from the config and the input image size
+struct MosaicGrid {
+    width_mm: f64,              // mosaic size keeping the input image aspect ratio
+    height_mm: f64,
+    width_px: f64,              // size of the rendered mosaic image
+    height_px: f64,
+    width_tile_count: usize,
+    height_tile_count: usize,
+}
+
+fn mosaic_grid(cfg: &modtile::Config, input_img_width: f64, input_img_height: f64) -> Result<MosaicGrid, TileError> {
 
     // ********** todo May 28th currently does not handle all cases of bad aspect ratio
     // determine the largest output box dimensions that will maintain the input image aspect ratio.
     // and resize the output image accordingly
     // strange behaviour noted so use with care.  Best to make sure op is some close ratio to input
-    let (output_width , output_height): (f64, f64) =  get_max_box(input_img_width
+    let (output_width_mm , output_height_mm): (f64, f64) =  get_max_box(input_img_width
                                                                ,input_img_height 
-                                                                ,cfg.output_width
-                                                               ,cfg.output_height);
-    println!();
-    println!("output image width: {}\noutput image height: {}", &output_width,&output_height );
+                                                                ,cfg.output_width.mm
+                                                               ,cfg.output_height.mm)?;
+    log::debug!("output size: {} x {} mm", &output_width_mm,&output_height_mm );
 
     // round to closest integer.
     // Less than .5 rounds down, More than .5 rounds up
     // so if less than half a tile it is left out
     // if more than half a tile it is included
-    let output_width_tile_count : usize = (output_width/(cfg.tile_size_x )).round() as usize; // Should account for spacing of tiles
-    let output_height_tile_count : usize = (output_height/(cfg.tile_size_y)).round() as usize;
-    println!();
-    println!("tile size x:{}\ntile size y:{}", cfg.tile_size_x, cfg.tile_size_y);
-    println!();
-    println!("output image width: {} , width tile count: {}\noutput image height: {} , height tile count: {}", output_width,
-                                                                                                        output_width_tile_count,
-                                                                                                        output_height,
-                                                                                                        output_height_tile_count);
+    let output_width_tile_count : usize = (output_width_mm/(cfg.tile_size_x.mm )).round() as usize; // Should account for spacing of tiles
+    let output_height_tile_count : usize = (output_height_mm/(cfg.tile_size_y.mm)).round() as usize;
+
+    // the rendered mosaic image has about preview_dpi pixels per inch of the finished mosaic,
+    // rounded to a whole number of pixels per tile so create_out_panes fills it exactly
+    let px_per_mm = units::preview_px_per_mm(cfg);
+    let output_width: f64 = image_px(output_width_mm * px_per_mm, output_width_tile_count);
+    let output_height: f64 = image_px(output_height_mm * px_per_mm, output_height_tile_count);
+
+    log::debug!("tile size: {} x {} mm", cfg.tile_size_x.mm, cfg.tile_size_y.mm);
+    log::debug!("output image: {} x {} px, tile count: {} x {}", output_width, output_height,
+                                                                  output_width_tile_count, output_height_tile_count);
+    Ok(MosaicGrid {
+        width_mm: output_width_mm,
+        height_mm: output_height_mm,
+        width_px: output_width,
+        height_px: output_height,
+        width_tile_count: output_width_tile_count,
+        height_tile_count: output_height_tile_count,
+    })
+} // mosaic_grid
+
+// Mosaic image size closest to `px` that gives each of `tile_count` tiles the same whole number of pixels
+fn image_px(px: f64, tile_count: usize) -> f64 {
+    let tile_count = tile_count.max(1) as f64;
+    (px / tile_count).round().max(1.0) * tile_count
+}
+
+// A mosaic made from a config, everything its outputs are written from
+struct Mosaic {
+    all_colors: modtile::AllColors,
+    input_img: DynamicImage,
+    out_img: DynamicImage,
+    input_window: Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>>,    // tile boxes in input image pixels, the pdf and exports use these
+    tile_color_count_vec: Vec<(Vec<u8>, i32)>,                // tile colours ordered by the number of tiles of each
+    grid: MosaicGrid,
+    mean_error: f64,            // average colour distance between a tile and its part of the image, see dither::color_error
+    max_error: f64,             // largest colour distance of any tile
+}
+
+// render subcommand, the mosaic image, instructions pdf and the outputs asked for on the command line
+fn render(matches: &ArgMatches) -> Result<(), TileError> {
+
+    if matches.get_flag("watch") {
+        return watch::watch_command(matches);
+    }
+
+    let mut report = RunReport::new("render");
+    let (cfg, board) = report.time("load config", |_| load_run_config(matches))?;
+    report_inputs(&mut report, matches, &cfg);
+
+    let mosaic: Mosaic = report.time("match tile colours", |_| make_mosaic(&cfg))?;
+    report_counts(&mut report, &mosaic, &cfg);
+    log_tile_list(&mosaic);
+
+    report.time("save mosaic image and json", |report| save_mosaic(&mosaic, &cfg, report))?;
+    report.time("instructions pdf", |report| write_pdf(&mosaic, &cfg, parts_board(matches, board).as_ref(), report))?;
+    write_printed_extras(matches, &mosaic, &cfg, &mut report)?;
+    write_mosaic_extras(matches, &mosaic, &cfg, board, &mut report)?;
+
+    // what was read, written and counted, for scripts
+    write_report(matches, &cfg, report)
+} // render
+
+// The config settings used and the files they name, for the run report
+fn report_inputs(report: &mut RunReport, matches: &ArgMatches, cfg: &modtile::Config) {
+    log::debug!("config settings ->\n{}", serde_json::to_string_pretty(cfg).unwrap_or_default());
+    report.input("config", matches.get_one::<String>("config").expect("config argument missing"));
+    report.input("image", &cfg.input);
+    report.input("palette", &cfg.tile_colors);
+    if let Some(font) = &cfg.font {
+        report.input("font", font);
+    }
+    if let Some(font) = &cfg.heading_font {
+        report.input("heading_font", font);
+    }
+}
+
+// detailed TileColor info (not just rgb value and count) of the tiles used, most used first
+fn log_tile_list(mosaic: &Mosaic) {
+    log::debug!("List of tiles used in this mosaic ordered by count of tiles");
+    for bc in &mosaic.tile_color_count_vec {
+        let var_rgb : modtile::RGB = modtile::RGB(bc.0[0],bc.0[1],bc.0[2]);
+        for tc in mosaic.all_colors.colors.iter().filter(|tc| tc.rgb == var_rgb) {
+            log::debug!("Count: {}, \t {:?}", bc.1, tc);
+        }
+    }
+}
+
+// The optional printed outputs asked for on the command line, these also use the page or font settings
+fn write_printed_extras(matches: &ArgMatches, mosaic: &Mosaic, cfg: &modtile::Config, report: &mut RunReport) -> Result<(), TileError> {
+    let all_colors = &mosaic.all_colors;
+    let save_path = Path::new(&cfg.output);
+
+    // if swatch flag present on command line then generate color swatch file
+    // next to the output image, or as the file or in the directory given
+    if matches.contains_id("swatch") {
+        let default_path = swatch::default_swatch_path(all_colors, cfg);
+        let path = report.time("colour swatch", |_| write_swatch(matches, all_colors, cfg, matches.get_one::<String>("swatch"), default_path))?;
+        report.output("colour swatch", &path);
+    }
+
+    // Create the cross stitch chart and pattern files
+    if matches.get_flag("cross_stitch") {
+        let overlap: usize = *matches.get_one::<usize>("stitch_overlap").unwrap_or(&2);
+        let paths = report.time("cross stitch", |_| cross_stitch::write_cross_stitch(&mosaic.input_window, all_colors, cfg, overlap, save_path))
+            .map_err(|v| TileError::Export { what: "cross stitch pattern", source: v })?;
+        paths.iter().for_each(|path| report.output("cross stitch", path));
+    }
+    // Create the actual size poster
+    if matches.get_flag("poster") {
+        let paper: &str = matches.get_one::<String>("poster_paper").map(|s| s.as_str()).unwrap_or("letter");
+        let overlap: f64 = *matches.get_one::<f64>("poster_overlap").unwrap_or(&10.0);
+        let path = report.time("poster", |_| poster::write_poster(&mosaic.input_window, cfg, paper, overlap, save_path))
+            .map_err(|v| TileError::Export { what: "poster", source: v })?;
+        report.output("poster", &path);
+    }
+    Ok(())
+} // write_printed_extras
+
+// The optional outputs made from the tile grid alone asked for on the command line
+fn write_mosaic_extras(matches: &ArgMatches,
+                       mosaic: &Mosaic,
+                       cfg: &modtile::Config,
+                       board: Option<&preset::BoardPreset>,
+                       report: &mut RunReport) -> Result<(), TileError> {
+    let all_colors = &mosaic.all_colors;
+    let input_window = &mosaic.input_window;
+    let save_path = Path::new(&cfg.output);
+
+    // Create the DXF cut files for the tile outlines
+    if matches.get_flag("dxf") || matches.get_flag("dxf_split") {
+        let paths = report.time("dxf", |_| dxf_export::write_dxf(input_window, all_colors, cfg, matches.get_flag("dxf_split"), save_path))
+            .map_err(|v| TileError::Export { what: "DXF file", source: v })?;
+        paths.iter().for_each(|path| report.output("dxf", path));
+    }
+
+    // Create the LDraw model
+    if matches.get_flag("ldraw") {
+        let piece: &str = matches.get_one::<String>("ldraw_piece").map(|s| s.as_str()).unwrap_or("round-plate");
+        let path = report.time("ldraw", |_| ldraw_export::write_ldraw(input_window, all_colors, cfg, board, piece, save_path))
+            .map_err(|v| TileError::Export { what: "LDraw model", source: v })?;
+        report.output("ldraw model", &path);
+    }
+    Ok(())
+} // write_mosaic_extras
+
+// Save the run report to --report or next to the output image and log the run summary
+fn write_report(matches: &ArgMatches, cfg: &modtile::Config, mut report: RunReport) -> Result<(), TileError> {
+    let report_path = match matches.get_one::<String>("report") {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => Path::new(&cfg.output).with_extension("report.json"),
+    };
+    report.write(&report_path).map_err(|e| TileError::output(&report_path, e))?;
+    report.log_summary();
+    Ok(())
+}
+
+// Match the tile colours of the mosaic a checked config describes and draw the mosaic image
+fn make_mosaic(cfg: &modtile::Config) -> Result<Mosaic, TileError> {
+    let input_img: DynamicImage = load_input_image(cfg)?;
+    let grid: MosaicGrid = mosaic_grid(cfg, input_img.width() as f64, input_img.height() as f64)?;
+    let input_window = tile_averages(&input_img, &grid, cfg);
+    match_mosaic(cfg, input_img, grid, input_window)
+}
+
+// Grab the input image
+fn load_input_image(cfg: &modtile::Config) -> Result<DynamicImage, TileError> {
+    let input_img: DynamicImage = get_image(cfg.input.clone())
+        .map_err(|e| TileError::Image { path: Path::new(&cfg.input).to_path_buf(), source: e })?;
+    log::debug!("input image: {} x {} px", input_img.width(), input_img.height());
+    Ok(input_img)
+}
+
+// The input image divided into the tile grid, each tile box with the average colour of its pixels.
+// These only change with the image and the tile grid, watch mode keeps them between runs
+fn tile_averages(input_img: &DynamicImage, grid: &MosaicGrid, cfg: &modtile::Config) -> Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>> {
 
     // create the input image buffer for use later
     let input_image_buffer = &input_img.to_rgb8();
 
-    // TODO - Now that we have all the info lets start processing!
     // divide input image into output_width_tile_count X output_height_tile_count boxes
     // ***
     // *** mgj TODO need to figure out what to do if aspect ratio of input image is not the same as output
@@ -92,28 +329,29 @@ fn main() {
     // Each window pane consists of tiles - a vector of Box2D co-ords (TopLeft Corner, BottomRight Corner) again stored left to right top to bottom ordered
     //                                    - and and RGB for storing the color of the tile. Defaults to black for newly created window
     //
-    let mut input_window: Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>> = create_out_panes (input_img_width,
-                                                            input_img_height,
-                                                            output_width_tile_count,
-                                                            output_height_tile_count,
+    let mut input_window: Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>> = create_out_panes (input_img.width() as f64,
+                                                            input_img.height() as f64,
+                                                            grid.width_tile_count,
+                                                            grid.height_tile_count,
                                                             cfg.tiles_per_pane_width,
                                                             cfg.tiles_per_pane_height);
 
+    // get the average color of each tile
+    for tile in input_window.iter_mut().flatten() {
+        let avg_col: Rgb<u8> = get_avg_col(input_image_buffer, &tile.0);
+        tile.1 = modtile::RGB(avg_col[0], avg_col[1], avg_col[2]);
+    }
+    input_window
+} // tile_averages
+
+// Match the tile averages from tile_averages to the tile colours and draw the mosaic image
+fn match_mosaic(cfg: &modtile::Config,
+                input_img: DynamicImage,
+                grid: MosaicGrid,
+                mut input_window: Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>>) -> Result<Mosaic, TileError> {
 
-    // println!("************************");
-    // println!("***** Input Window *****");
-    // println!("************************\n");
-    // println!("number of tiles in first window pane: {:?}", &input_window[0].len());
-    // println!("number of window panes: {:?}", &input_window.len());
-    // println!("number of tiles in first window pane: {:?}", &input_window[0].len());
-    // println!("************************\n");
-    // println!("Tile Coords in each window pane");
-    // for (i, pane) in input_window.iter().enumerate() {
-    //     println!("**** Window pane {} ****", i+1);
-    //     for (j, tile_coords) in pane.iter().enumerate(){
-    //         println!("Tile {} Coords: {:?}", j+1, tile_coords);
-    //     }
-    // }
+    let (output_width, output_height) = (grid.width_px, grid.height_px);
+    let (output_width_tile_count, output_height_tile_count) = (grid.width_tile_count, grid.height_tile_count);
 
     // this holds all the info necesary to build the output image
     let mut output_window: Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>> = create_out_panes (output_width,
@@ -123,168 +361,255 @@ fn main() {
                                                             cfg.tiles_per_pane_width,
                                                             cfg.tiles_per_pane_height);
 
-    // println!("************************");
-    // println!("***** Output Window ****");
-    // println!("************************\n");
-    //
-    // println!("Output - window panes: {:?}", &output_window.len());
-    // println!("Output - number of tiles in first window pane: {:?}", &output_window[0].len());
-    // println!("Output Tile Coords in each output window pane");
-    // println!("************************\n");
-    // for (i, pane) in output_window.iter().enumerate() {
-    //     println!("**** Output Window pane {} ****", i+1);
-    //     for (j, tile_coords) in pane.iter().enumerate(){
-    //         println!("Output Tile {} Coords: {:?}", &j+1, &tile_coords.0);
-    //         println!("Output Tile {} rgb: {:?}",&j+1, &tile_coords.1);
-    //     }
-    // }
-    // println!("************************\n");
-    // println!("************************\n");
-
     // load the tile colors_path
-    let all_colors: modtile::AllColors = modtile::load_all_colors(&cfg.tile_colors.to_string().to_owned());
-    // for (j, tile_color) in all_colors.colors.iter().enumerate() {
-    //     println!("tile color:{}, {:?}",j+1, tile_color);
-    // }
-
-    // if swatch flag present on command line then generate color swatch file
-    if matches.contains_id("swatch") {
-        // generate a color swatch file
-        match pdf_util::generate_color_swatch(&all_colors) {
-            Err(v) => panic!(
-                "Could not create color swatch file: {}",
-                v.to_string()
-            ),
-            Ok(r) => println!("generate_color_swatch() Success {:?}", r),    // return a bufReader
-        };
-    }
-
+    let all_colors: modtile::AllColors = modtile::load_all_colors(&cfg.tile_colors)?;
     let mut color_vec: Vec<Vec<u8>> = build_color_vec(&all_colors);  // Create a Vector Array of elements of type u8
 
-    // construct our KD tree with the desired color vec
-    let kd_tree = construct_kd_tree(&mut color_vec[..], 3);
+    // match the whole grid of averages to the tile colours at once so dithering
+    // can pass the difference on to the neighbouring tiles
+    let averages: Vec<Vec<modtile::RGB>> = modtile::get_tile_grid(&input_window);
+    let matched: Vec<Vec<modtile::RGB>> = dither::match_tiles(&averages, &mut color_vec[..], cfg.dither.as_deref().unwrap_or("none"));
+    modtile::set_tile_grid(&mut input_window, &matched);
 
-    // keep count for number of times each color used as a tile
-    // let mut tile_color_count: HashMap<&Vec<u8>, i32> = HashMap::new();
+    // keep count for number of times each color used as a tile and how far each tile is from its average
     let mut tile_color_count: HashMap<Vec<u8>, i32> = HashMap::new();
-
-    // save a window pane ordered list of output tile colours
-    // top left hand corner is first tile.
-    // Progesses from left to to right
-    // The from top to bottom
-    // let mut pane_colours : Vec<(u8,u8,u8)> = Vec::new();
-    let mut window_pane_colors : Vec<Vec<(u8,u8,u8)>> = Vec::new();
-
-    // mgj New TODO
-    // interate through each window pane
-    //     iterate though each tile in the pane
-    //
-    //     get the average color for tile
-    //         build the output image with closest color match to the average color of that tile
-    //         ___ or ___
-    //         store that average color into output "window" struct.
-    //
-    //      Output window struct can then be used to
-    //         create the output image
-    //         create the output pdf instructions doc
-    for (_i, pane) in input_window.iter_mut().enumerate() {
-        let mut pane_colours : Vec<(u8,u8,u8)> = Vec::new();
-        // println!("**** Window pane {} ****", i+1);
-        for (_j, mut tile) in pane.iter_mut().enumerate(){
-            // println!("Tile {}: {:?}", j+1, tile);
-
-            let avg_col: Rgb<u8> = get_avg_col(&input_image_buffer, &tile.0);
-
-            // get the closes color match
-            let color_tup :Vec<u8> = query_nearest_neighbor(&[avg_col[0],avg_col[1],avg_col[2]], &kd_tree, 3, kd_tree.root()).value().clone();
-            // println!("color_tup {:?}", &color_tup);
-
-            // keep running count of each tile color used
-            *tile_color_count.entry(color_tup.clone()).or_insert(0) += 1;
-
-            let cm_red: u8 = color_tup[0] as u8;
-            let cm_yellow: u8 = color_tup[1] as u8;
-            let cm_green: u8 = color_tup[2] as u8;
-
-            pane_colours.push((cm_red.clone(),cm_yellow.clone(),cm_green.clone()));
-
-            // let closest_match = Rgb([cm_red,cm_yellow,cm_green]);
-
-            // store the closest match in the tile
-            tile.1 = modtile::RGB(cm_red.clone(),cm_yellow.clone(),cm_green.clone());
-            // println!("Closest Match ---> {:?} \n", tile);
-
-        }
-        window_pane_colors.push(pane_colours);
+    let (mut error_sum, mut max_error) = (0.0, 0.0_f64);
+    for (avg, rgb) in averages.iter().flatten().zip(matched.iter().flatten()) {
+        *tile_color_count.entry(vec![rgb.0, rgb.1, rgb.2]).or_insert(0) += 1;
+        let error = dither::color_error(avg, rgb);
+        error_sum += error;
+        max_error = max_error.max(error);
     }
-
-
-    // see if we can zip window_pane_colors and window with transform function on window coords`.
-    // let it = window.iter().zip(window_pane_colors.iter());
-    //
-    // for (i, (x, y)) in it.enumerate() {
-    //    println!("w {:#?}: (wpc {:?}, wpc{:?})", i, x, y);
-    // }
+    let tile_count = (output_width_tile_count * output_height_tile_count).max(1);
 
     // zip input_window and output_window and copy input rgb value to output
-    let wit = input_window.iter().zip(output_window.iter_mut());
-    for (_i, (ip,op)) in wit.enumerate() {
-       // println!("Pane {:?}: \ninput: {:?} \noutput: {:?})", i, ip,op);
-       // println!{"\n"};
-
-       let pit = ip.iter().zip(op.iter_mut());
-       for (_j, (itp,otp)) in pit.enumerate() {
-          // println!("Tile {:?}: \ninput: {:?} \noutput: {:?})", j, itp,otp);
-          // println!{"\n"};
-
+    for (ip, op) in input_window.iter().zip(output_window.iter_mut()) {
+       for (itp, otp) in ip.iter().zip(op.iter_mut()) {
           // set the output tile color to be the same as the imput tile color
           otp.1 = itp.1;
       }
     }
-    // println!("******/n *** Output Window a fter Zipped iterator ***\n******/n");
-    // println!("output window {:?}", &output_window);
 
     // create the output image
     let out_img : DynamicImage = create_output_image(&output_window, output_width, output_height);
 
+    // create a vector of output colors and sort it by usage count
+    let mut tile_color_count_vec: Vec<(Vec<u8>, i32)> = tile_color_count.into_iter().collect();
+    tile_color_count_vec.sort_by_key(|c| std::cmp::Reverse(c.1));
+
+    Ok(Mosaic {
+        all_colors,
+        input_img,
+        out_img,
+        input_window,
+        tile_color_count_vec,
+        grid,
+        mean_error: error_sum / tile_count as f64,
+        max_error,
+    })
+} // match_mosaic
+
+// Save the mosaic image, its tile json and the run config next to it, adding them to `report`
+fn save_mosaic(mosaic: &Mosaic, cfg: &modtile::Config, report: &mut RunReport) -> Result<(), TileError> {
+
     // Save the resulting image.  We'll also want to use this to create our ouptput PDF instructions doc
-    // Add proper error handling for image
     let save_path = Path::new(&cfg.output);
-    println!("save_path {:#?}", &save_path.to_str() );
-    out_img.save(save_path).unwrap();
+    mosaic.out_img.save(save_path).map_err(|e| TileError::output(save_path, e))?;
+    report.output("mosaic image", save_path);
+
+    // save the tile colours as a json file that can be used to create an svg
+    let json_path = save_path.with_extension("json");
+    dump_rgb_json(&mosaic.input_window,
+        mosaic.grid.width_tile_count,
+        mosaic.grid.height_tile_count,
+        cfg.tiles_per_pane_width,
+        cfg.tiles_per_pane_height,
+        cfg,
+        save_path ).map_err(|e| TileError::output(&json_path, e))?;
+    report.output("tile json", &json_path);
+    save_run_config(cfg, report)
+} // save_mosaic
+
+// Save the merged config this mosaic was made with next to it, to make it again
+fn save_run_config(cfg: &modtile::Config, report: &mut RunReport) -> Result<(), TileError> {
+    let run_config_path = Path::new(&cfg.output).with_extension("config.json");
+    config_file::save_run_config(cfg, &run_config_path).map_err(|e| TileError::output(&run_config_path, e))?;
+    report.output("run config", &run_config_path);
+    Ok(())
+}
 
-    // create a vector of output colors and sort it by usage count
-    let mut tile_color_count_vec: Vec<(Vec<u8>, i32)> = tile_color_count.into_iter().collect();
-    tile_color_count_vec.sort_by(|a, b| b.1.cmp(&a.1));
+// Create the output instructions doc next to the mosaic image
+// Changed from output window to input window to simplify PDF to image space cooridinates translation
+fn write_pdf(mosaic: &Mosaic, cfg: &modtile::Config, board: Option<&preset::BoardPreset>, report: &mut RunReport) -> Result<(), TileError> {
+    let pdf_path = pdf_util::build_output_pdf(Path::new(&cfg.output), &mosaic.all_colors, mosaic.tile_color_count_vec.clone(), &mosaic.input_window,
+                                              board, cfg, (&mosaic.out_img, &mosaic.input_img))?;
+    report.output("instructions pdf", &pdf_path);
+    Ok(())
+}
+
+// The board for the instructions pdf, its parts lists naming the piece chosen for the LDraw model
+fn parts_board(matches: &ArgMatches, board: Option<&preset::BoardPreset>) -> Option<preset::BoardPreset> {
+    let piece: &str = matches.get_one::<String>("ldraw_piece").map(|s| s.as_str()).unwrap_or("round-plate");
+    board.map(|board| preset::with_ldraw_piece(board, piece))
+}
 
-    // we want to print out detailed TileColor info (not just rgb value and count)
+// Tile, pane and colour counts of the mosaic for the run report
+fn report_counts(report: &mut RunReport, mosaic: &Mosaic, cfg: &modtile::Config) {
+    let (cols, rows) = (mosaic.grid.width_tile_count, mosaic.grid.height_tile_count);
+    report.count("input_width_px", mosaic.input_img.width());
+    report.count("input_height_px", mosaic.input_img.height());
+    report.count("tiles_across", cols);
+    report.count("tiles_down", rows);
+    report.count("tiles", cols * rows);
+    report.count("panes", (cols / cfg.tiles_per_pane_width) * (rows / cfg.tiles_per_pane_height));
+    report.count("palette_name", mosaic.all_colors.name.as_str());
+    report.count("palette_colors", mosaic.all_colors.colors.len());
+    report.count("colors_used", mosaic.tile_color_count_vec.len());
+    report.count("mean_error", (mosaic.mean_error * 10.0).round() / 10.0);
+    report.count("max_error", (mosaic.max_error * 10.0).round() / 10.0);
+}
+
+// validate subcommand, check the config and that its input image and palette can be read
+fn validate_command(matches: &ArgMatches) -> Result<(), TileError> {
+    let (cfg, _) = load_run_config(matches)?;
+    let (img_width, img_height) = image::image_dimensions(&cfg.input)
+        .map_err(|e| TileError::Image { path: Path::new(&cfg.input).to_path_buf(), source: e })?;
+    let all_colors: modtile::AllColors = modtile::load_all_colors(&cfg.tile_colors)?;
     println!();
-    println!("List of tiles used in this mosaic ordered by count of tiles") ;
-    for bc in &tile_color_count_vec {
-        let var_rgb : modtile::RGB = modtile::RGB(bc.0[0],bc.0[1],bc.0[2]);
+    println!("Config {} is valid - input image {} x {} px, palette {} with {} colours",
+             matches.get_one::<String>("config").expect("config argument missing"),
+             img_width, img_height, all_colors.name, all_colors.colors.len());
+    Ok(())
+}
 
-        for tc in &all_colors.colors {
-            if var_rgb == tc.rgb {
-                println!("Count: {}, \t {:?}, ", bc.1,  tc );
-            }
-        };
-     }
+// inspect subcommand, the grid, panes and sizes the config gives without matching any colours
+fn inspect(matches: &ArgMatches) -> Result<(), TileError> {
+    let (cfg, board) = load_run_config(matches)?;
+    let (img_width, img_height) = image::image_dimensions(&cfg.input)
+        .map_err(|e| TileError::Image { path: Path::new(&cfg.input).to_path_buf(), source: e })?;
+    let grid: MosaicGrid = mosaic_grid(&cfg, img_width as f64, img_height as f64)?;
+    let all_colors: modtile::AllColors = modtile::load_all_colors(&cfg.tile_colors)?;
 
-     // save the input window data as a json file that can be used to create an svg
-     dump_rgb_json(&output_window, 
-        output_width_tile_count,
-        output_height_tile_count,
-        cfg.tiles_per_pane_width,
-        cfg.tiles_per_pane_height,
-        save_path );
+    let pane_label = if board.is_some() { "Boards" } else { "Panes" };
+    let pane_cols = grid.width_tile_count / cfg.tiles_per_pane_width;
+    let pane_rows = grid.height_tile_count / cfg.tiles_per_pane_height;
+    let (finished_wid, finished_hgt) = units::finished_size_mm(&cfg, grid.width_tile_count, grid.height_tile_count);
+    let length = |mm: f64| units::format_length(mm, &cfg);
+
+    println!();
+    println!("Config           {}", matches.get_one::<String>("config").expect("config argument missing"));
+    println!("Input image      {} ({} x {} px)", cfg.input, img_width, img_height);
+    println!("Palette          {} ({} colours) {}", all_colors.name, all_colors.colors.len(), cfg.tile_colors);
+    if let Some(board) = board {
+        println!("Board preset     {} - {}", board.name, board.board_part);
+    }
+    println!("Mosaic box       {} x {}", length(grid.width_mm), length(grid.height_mm));
+    println!("Finished size    {} x {}", length(finished_wid), length(finished_hgt));
+    println!("Tile size        {} x {}, spacing {} x {}", length(cfg.tile_size_x.mm), length(cfg.tile_size_y.mm),
+             length(cfg.tile_space_x.mm), length(cfg.tile_space_y.mm));
+    println!("Tiles            {} ({} across, {} down)", grid.width_tile_count * grid.height_tile_count,
+             grid.width_tile_count, grid.height_tile_count);
+    println!("{:<17}{} ({} across, {} down) of {} x {} tiles", pane_label, pane_cols * pane_rows, pane_cols, pane_rows,
+             cfg.tiles_per_pane_width, cfg.tiles_per_pane_height);
+    println!("Input per tile   {} x {} px", (img_width as f64 / grid.width_tile_count as f64).round(),
+             (img_height as f64 / grid.height_tile_count as f64).round());
+    println!("Mosaic image     {} x {} px at {} dpi", grid.width_px, grid.height_px,
+             units::format_number(units::preview_px_per_mm(&cfg) * 25.4));
+    println!("Outputs          {} and its .pdf and .json", cfg.output);
+    Ok(())
+} // inspect
+
+// pdf subcommand, remake the instructions pdf from the tile colours saved by render
+// so page, symbol and checklist settings can change without matching the colours again
+fn remake_pdf(matches: &ArgMatches) -> Result<(), TileError> {
+    let (cfg, board) = load_run_config(matches)?;
+    let save_path = Path::new(&cfg.output);
+    let tiles_path = match matches.get_one::<String>("tiles") {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => save_path.with_extension("json"),
+    };
+
+    let input_img: DynamicImage = get_image(cfg.input.clone())
+        .map_err(|e| TileError::Image { path: Path::new(&cfg.input).to_path_buf(), source: e })?;
+    let out_img: DynamicImage = get_image(cfg.output.clone())
+        .map_err(|e| TileError::Image { path: save_path.to_path_buf(), source: e })?;
+    let (img_width, img_height): (u32, u32) = input_img.dimensions();
+    let grid: MosaicGrid = mosaic_grid(&cfg, img_width as f64, img_height as f64)?;
+
+    let tile_grid: Vec<Vec<modtile::RGB>> = json_export::load_tile_grid(&tiles_path)
+        .map_err(|e| TileError::Layout(format!("could not read tiles {} - {}", tiles_path.display(), e)))?;
+    let (tile_cols, tile_rows) = (tile_grid.first().map(|row| row.len()).unwrap_or(0), tile_grid.len());
+    if (tile_cols, tile_rows) != (grid.width_tile_count, grid.height_tile_count) {
+        return Err(TileError::Layout(format!("{} has {} x {} tiles but the config gives {} x {}, render the mosaic again",
+                                             tiles_path.display(), tile_cols, tile_rows, grid.width_tile_count, grid.height_tile_count)));
+    }
+
+    let mut input_window: Vec<Vec<(Box2D<i32,i32>,modtile::RGB)>> = create_out_panes(img_width as f64,
+                                                            img_height as f64,
+                                                            grid.width_tile_count,
+                                                            grid.height_tile_count,
+                                                            cfg.tiles_per_pane_width,
+                                                            cfg.tiles_per_pane_height);
+    modtile::set_tile_grid(&mut input_window, &tile_grid);
 
-    // println!("Window Pane Colors {:#?}", window_pane_colors);
+    let all_colors: modtile::AllColors = modtile::load_all_colors(&cfg.tile_colors)?;
+    let mut tile_color_count: HashMap<Vec<u8>, i32> = HashMap::new();
+    for rgb in tile_grid.iter().flatten() {
+        *tile_color_count.entry(vec![rgb.0, rgb.1, rgb.2]).or_insert(0) += 1;
+    }
+    let mut tile_color_count_vec: Vec<(Vec<u8>, i32)> = tile_color_count.into_iter().collect();
+    tile_color_count_vec.sort_by_key(|c| std::cmp::Reverse(c.1));
+
+    let pdf_path = pdf_util::build_output_pdf(save_path, &all_colors, tile_color_count_vec, &input_window,
+                                              parts_board(matches, board).as_ref(), &cfg, (&out_img, &input_img))?;
+    log::info!("Wrote instructions pdf {}", pdf_path.display());
+    Ok(())
+} // remake_pdf
+
+// swatch subcommand, a swatch pdf of any palette, the config is only needed for fonts and page settings
+fn swatch_command(matches: &ArgMatches) -> Result<(), TileError> {
+    let cfg: modtile::Config = match matches.get_one::<String>("config") {
+        Some(path) => modtile::load_configs(path, &[])?,
+        None => modtile::Config::default(),
+    };
+    let all_colors: modtile::AllColors = modtile::load_all_colors(matches.get_one::<String>("palette").expect("palette argument missing"))?;
+    let default_path = Path::new(&format!("{}.pdf", all_colors.name.split_whitespace().collect::<Vec<&str>>().join("_"))).to_path_buf();
+    let path = write_swatch(matches, &all_colors, &cfg, matches.get_one::<String>("output"), default_path)?;
+    log::info!("Wrote colour swatch {}", path.display());
+    Ok(())
+}
 
-    // Create the output instructions doc
-    // pdf_util::build_output_pdf(&save_path,&all_colors,&tile_color_count_vec,&output_window);
-    // Changed from output window to input window to simplify PDF to image space cooridinates translation
-    pdf_util::build_output_pdf(&save_path,&all_colors,tile_color_count_vec,&input_window);
+// Write the swatch pdf to `path` (a pdf or a directory) or `default_path`, laid out by the swatch options in `matches`
+fn write_swatch(matches: &ArgMatches,
+                all_colors: &modtile::AllColors,
+                cfg: &modtile::Config,
+                path: Option<&String>,
+                default_path: std::path::PathBuf) -> Result<std::path::PathBuf, TileError> {
+    let swatch_opts = swatch::SwatchOptions {
+        size_mm: *matches.get_one::<f64>("swatch_size").expect("swatch size missing"),
+        columns: matches.get_one::<usize>("swatch_columns").copied(),
+        labels: matches.get_many::<String>("swatch_labels").expect("swatch labels missing").cloned().collect(),
+        sort: matches.get_one::<String>("swatch_sort").expect("swatch sort missing").to_owned(),
+    };
+    let swatch_path = match path {
+        Some(path) if Path::new(path).is_dir() => Path::new(path).join(default_path.file_name().unwrap_or_default()),
+        Some(path) => Path::new(path).to_path_buf(),
+        None => default_path,
+    };
+    swatch::generate_color_swatch(all_colors, cfg, &swatch_opts, &swatch_path)
+        .map_err(|v| TileError::Export { what: "color swatch file", source: v })
+}
 
+// schema subcommand, the config's JSON Schema to `path` or stdout
+fn write_schema(path: Option<&String>) -> Result<(), TileError> {
+    let schema = serde_json::to_string_pretty(&config_schema::config_schema()).map_err(|e| TileError::output(Path::new("schema"), e))?;
+    match path {
+        Some(path) => std::fs::write(path, schema).map_err(|e| TileError::output(Path::new(path), e)),
+        None => {
+            println!("{}", schema);
+            Ok(())
+        }
+    }
 }
 
 // create the output image
@@ -382,29 +707,26 @@ fn create_out_panes(input_img_width: f64,
                     tiles_per_pane_width: usize,
                     tiles_per_pane_height: usize) -> Vec<Vec< (Box2D<i32, i32>,modtile::RGB) >> {
 
-    println!();
-    println!("input_img_width: {:?}", input_img_width);
-    println!("input_img_height: {:?}", input_img_height);
-    println!();
-    println!("output_width_tile_count: {:?}", output_width_tile_count);
-    println!("output_height_tile_count: {:?}", output_height_tile_count);
-    println!();
-    println!("tiles_per_pane_width: {:?}", tiles_per_pane_width);
-    println!("tiles_per_pane_height: {:?}", tiles_per_pane_height);
+    log::trace!("input_img_width: {:?}", input_img_width);
+    log::trace!("input_img_height: {:?}", input_img_height);
+    log::trace!("output_width_tile_count: {:?}", output_width_tile_count);
+    log::trace!("output_height_tile_count: {:?}", output_height_tile_count);
+    log::trace!("tiles_per_pane_width: {:?}", tiles_per_pane_width);
+    log::trace!("tiles_per_pane_height: {:?}", tiles_per_pane_height);
 
     let mut window_grid: Vec<Vec<(Box2D<i32, i32>,modtile::RGB)>> = Vec::new();
 
     let window_pane_rows = output_height_tile_count / tiles_per_pane_height;
     let window_pane_cols = output_width_tile_count / tiles_per_pane_width;
-    println!();
-    println!("window_pane_rows: {:?}", &window_pane_rows);
-    println!("window_pane_cols: {:?}", &window_pane_cols);
+    log::trace!("window_pane_rows: {:?}", &window_pane_rows);
+    log::trace!("window_pane_cols: {:?}", &window_pane_cols);
 
-    // Cannot have fractional pixels so round and convert to usize
+    // Cannot have fractional pixels so round down and convert to usize, the tiles then never run past
+    // the image edge and the few pixels left over on the right and bottom are left out
     // TODO mgj add some more error checking
     //    i.e. if output hieght tile count is 3 and tiles per pane hieght is 4
-    let img_width_div  = (input_img_width / output_width_tile_count as f64).round() as usize;
-    let img_height_div = (input_img_height / output_height_tile_count as f64).round() as usize;
+    let img_width_div  = (input_img_width / output_width_tile_count as f64).floor() as usize;
+    let img_height_div = (input_img_height / output_height_tile_count as f64).floor() as usize;
 
     // ******** NOTE ********
     // TODO - mgj Output image size is MM and NOT PX so adjust window pane co-ords to account for this
@@ -466,13 +788,13 @@ fn build_color_vec(all_colors: &modtile::AllColors) -> Vec<Vec<u8>> {
 
 // return maximum possible output dimensions (width height) for input box of a given size
 // and desired output dimensions while maintaining aspect ratio of the input box
-// All dimensions must be greater than 1.0 or function panics
+// All dimensions must be greater than 1.0 or a layout error is returned
 // This function also does not handle the case where the input box has bigger dimensions that the output box
-fn get_max_box(ip_width: f64, ip_height: f64, op_width: f64, op_height: f64) -> (f64,f64) {
+fn get_max_box(ip_width: f64, ip_height: f64, op_width: f64, op_height: f64) -> Result<(f64,f64), TileError> {
 
     // we need to do this check as RUST will happily divide by float zero
     if ip_width < 1.0 || ip_height < 1.0 || op_width < 1.0 || op_height < 1.0 {
-        panic!("get_max_box() - all supplied dimensions must be greater than 1.0");
+        return Err(TileError::Layout(format!("image {} x {} and output {} x {} must all be at least 1", ip_width, ip_height, op_width, op_height)));
     }
 
     // println!("get max box {} {} {} {}", ip_width, ip_height,op_width, op_height);
@@ -483,7 +805,7 @@ fn get_max_box(ip_width: f64, ip_height: f64, op_width: f64, op_height: f64) ->
 
         // println!("1 op_width/ip_width > op_height/ip_height");
         // println!("1 trans_width: {:?}, trans_height: {:?}", &trans_width,&trans_height);
-        (trans_width,trans_height)
+        Ok((trans_width,trans_height))
 
     } else if op_width/ip_width < op_height/ip_height {
         let trans_height = &ip_height * (op_width/ip_width);
@@ -491,16 +813,70 @@ fn get_max_box(ip_width: f64, ip_height: f64, op_width: f64, op_height: f64) ->
 
         // println!("2 op_width/ip_width < op_height/ip_height");
         // println!("2 trans_width: {:?}, trans_height: {:?}", &trans_width,&trans_height);
-        (trans_width,trans_height)
+        Ok((trans_width,trans_height))
     } else
     {
         let trans_height = op_height;
         let trans_width = op_width;
         // println!("3 op_width/ip_width = op_height/ip_height");
         // println!("3 trans_width: {:?}, trans_height: {:?}", trans_width,trans_height);
-        (trans_width,trans_height)
+        Ok((trans_width,trans_height))
     }
 
 }
 
- 
\ No newline at end of file
+ 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Length;
+
+    fn square_config(size_mm: f64, tile_mm: f64, per_pane: usize, preview_dpi: Option<f64>) -> modtile::Config {
+        modtile::Config {
+            output_width: Length::new(size_mm),
+            output_height: Length::new(size_mm),
+            tile_size_x: Length::new(tile_mm),
+            tile_size_y: Length::new(tile_mm),
+            tiles_per_pane_width: per_pane,
+            tiles_per_pane_height: per_pane,
+            preview_dpi,
+            ..Default::default()
+        }
+    }
+
+    // 100mm of 10mm tiles at 7 dpi is 27.6 px and 20mm of 3mm tiles at 25.4 dpi is 20 px,
+    // rounding the pixels per tile up used to run the tiles past the edge of the mosaic image
+    #[test]
+    fn tile_boxes_fit_the_mosaic_image() {
+        for (size_mm, tile_mm, per_pane, dpi) in [(100.0, 10.0, 10, Some(7.0)), (20.0, 3.0, 7, None), (600.0, 10.0, 10, None)] {
+            let cfg = square_config(size_mm, tile_mm, per_pane, dpi);
+            let grid = mosaic_grid(&cfg, 553.0, 553.0).unwrap();
+            let panes = create_out_panes(grid.width_px, grid.height_px, grid.width_tile_count, grid.height_tile_count, per_pane, per_pane);
+            for (tile, _) in panes.iter().flatten() {
+                assert!(tile.max.x < grid.width_px as i32 && tile.max.y < grid.height_px as i32,
+                        "{:?} outside {} x {} px for {}mm of {}mm tiles", tile, grid.width_px, grid.height_px, size_mm, tile_mm);
+            }
+            let out_img = create_output_image(&panes, grid.width_px, grid.height_px);
+            assert_eq!(out_img.dimensions(), (grid.width_px as u32, grid.height_px as u32));
+        }
+    }
+
+    #[test]
+    fn tile_boxes_stay_inside_the_input_image() {
+        // 8 tiles of 67.5 pixels across a 540 pixel image
+        let panes = create_out_panes(540.0, 540.0, 8, 8, 8, 8);
+        assert_eq!(panes[0].len(), 64);
+        for (tile, _) in panes.iter().flatten() {
+            assert!(tile.max.x < 540 && tile.max.y < 540, "{:?} outside 540 x 540 px", tile);
+            assert_eq!((tile.width() + 1, tile.height() + 1), (67, 67));
+        }
+    }
+
+    #[test]
+    fn image_px_is_whole_pixels_per_tile() {
+        assert_eq!(image_px(27.56, 10), 30.0);
+        assert_eq!(image_px(20.0, 7), 21.0);
+        assert_eq!(image_px(600.0, 60), 600.0);
+        assert_eq!(image_px(3.0, 10), 10.0);
+    }
+}
@@ -3,72 +3,103 @@ use std::fs::File;
 use std::path::Path;
 use std::io::{Read, Write, Result};
 use std::fmt::{self, Formatter, Display};
+use euclid::Box2D;
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+use crate::{config_file, config_schema};
+use crate::error::TileError;
+use crate::page_layout::PageConfig;
+use crate::units::Length;
+
+// Settings that can be left out are filled by serde defaults or copied from their partner, see config_schema.rs
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
 pub struct Config{
-    pub tile_colors: String,// "./json_files/crayola_colors.json",
+    #[serde(default = "config_version")]
+    pub version: u64,       // config file format version, older configs are migrated when loaded
+    #[serde(default)]
+    pub tile_colors: String,// "./json_files/crayola_colors.json", the preset's palette when empty
     pub input: String,      // :"./images/4x4_16_color_test.png",
     pub output: String,     // ./images/output/4x4_cray_15x15.jpg",
-    pub output_width: f64,//600,
-    pub output_height: f64, //600,
-    pub tile_size_x:  f64,   //10,
-    pub tile_size_y: f64,   //10,
-    pub tile_space_x: f64,
-    pub tile_space_y: f64,
+    pub output_width: Length,//600, or "60cm" lengths are in "units" unless they give their own see units.rs
+    pub output_height: Length, //600,
+    pub tile_size_x:  Length,   //10,
+    pub tile_size_y: Length,   //10,
+    #[serde(default)]
+    pub tile_space_x: Length,
+    #[serde(default)]
+    pub tile_space_y: Length,
+    #[serde(default = "tiles_per_pane")]
     pub tiles_per_pane_width: usize,  //4,
+    #[serde(default = "tiles_per_pane")]
     pub tiles_per_pane_height: usize,  //4,
+    pub preset: Option<String>,  // "lego_48", board preset see preset.rs
+    pub font: Option<String>,          // "./fonts/MyFont.ttf" or "Helvetica", embedded font when not set see fonts.rs
+    pub heading_font: Option<String>,  // font for page titles, embedded bold font when not set
+    pub page: Option<PageConfig>,      // instruction pdf page size, orientation and margins see page_layout.rs
+    pub summary_order: Option<String>, // "count" (default), "number" or "hue", order of the tile colour summary pages
+    pub tile_symbols: Option<String>,  // "index" (default), "count", "number", "letter" or "glyph" see tile_symbols.rs
+    pub print_mode: Option<String>,    // "color" (default) or "mono" for black and white printing of the pane pages
+    pub title: Option<String>,         // project title on the instructions cover page, made from the input file name when not set
+    pub author: Option<String>,        // shown under the title on the cover page
+    pub checklist: Option<bool>,       // add assembly checklist and pick list pages to the instructions pdf see checklist.rs
+    pub units: Option<String>,         // "mm" (default), "cm", "in" or "px", unit of the lengths given as plain numbers
+    pub preview_dpi: Option<f64>,      // pixels per inch of the rendered mosaic image, 25.4 (1 pixel per mm) when not set
+    pub dither: Option<String>,        // "none" (default), "floyd-steinberg" or "atkinson" see dither.rs
 }
 
-pub fn load_configs(path_str: &str) -> Config {
+fn config_version() -> u64 {
+    config_schema::CONFIG_VERSION
+}
 
-    let path = Path::new(path_str);
+fn tiles_per_pane() -> usize {
+    config_schema::DEFAULT_TILES_PER_PANE
+}
+
+// load the config settings from a JSON, TOML or YAML file and any configs it extends, see config_file.rs.
+// Older config versions are migrated and unknown keys are warned about.
+// `overrides` are (setting, value) pairs from the command line that replace the config's settings
+pub fn load_configs(path_str: &str, overrides: &[(String, String)]) -> std::result::Result<Config, TileError> {
 
-    match File::open(path) {
-       Ok(mut file) => {
-           println!("Config File was successfully opened");
-           let mut buf = vec![];
-           if file.read_to_end(&mut buf).is_ok() {
-               match serde_json::from_slice(&buf[..]) {
-                   Ok(config) => return config,
-                   Err(e) => {
-                       eprintln!("Could not read config file {:?} \n  {}", path, e );
-                       panic!("Improperly formed JSON file");
-                   }
-               } // match serde_json
-           }
-           else {
-               eprintln!("Could not read config file {:?}", path);
-               panic!("Improperly formed JSON file");
-           }
-       },
-       Err(e) => {
-           eprintln!(" Could not open config file {:?} \n  {}", path, e );
-           panic!("Missing Config File");
-        },
-     }
+    let path = Path::new(path_str);
+    let mut value: serde_json::Value = config_file::read_config_value(path)?;
+    config_file::apply_overrides(&mut value, overrides, path)?;
+    config_schema::fill_paired_defaults(&mut value);
+    serde_json::from_value(value).map_err(|e| TileError::ConfigParse { path: path.to_path_buf(), source: e.into() })
 }
 
 pub fn _create_and_save_test_config(path_str: &str) -> Config {
 
     let cfg = Config{
+        version: config_schema::CONFIG_VERSION,
         tile_colors:"./tile_json/crayola_colors.json".to_owned(),
         input:"./images/4x4_16_color_test.png".to_owned(),
         output:"./images/output/4x4_cray_15x15.jpg".to_owned(),
-        output_width:10.0,
-        output_height:10.0,
-        tile_size_x:2.0,
-        tile_size_y:2.0,
-        tile_space_x:1.0,
-        tile_space_y:1.0,
+        output_width:Length::new(10.0),
+        output_height:Length::new(10.0),
+        tile_size_x:Length::new(2.0),
+        tile_size_y:Length::new(2.0),
+        tile_space_x:Length::new(1.0),
+        tile_space_y:Length::new(1.0),
         tiles_per_pane_width:3,
         tiles_per_pane_height:3,
-
+        preset: None,
+        font: None,
+        heading_font: None,
+        page: None,
+        summary_order: None,
+        tile_symbols: None,
+        print_mode: None,
+        title: None,
+        author: None,
+        checklist: None,
+        units: None,
+        preview_dpi: None,
+        dither: None,
     };
 
     let path = Path::new(path_str);
     match save_config(path, &cfg) {
         Ok(()) => cfg,
-        Err(e) => { eprintln!("Could not write test config file {:?} \n  {}", path, e );
+        Err(e) => { log::warn!("Could not write test config file {:?} - {}", path, e );
                     panic!("Error Writing Test Config File");}
     }
 }
@@ -92,6 +123,8 @@ pub struct TileColor {
     pub rgb: RGB,
     pub name: String,
     pub number: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ldraw: Option<i32>,     // LDraw colour code, only needed for LDraw export
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
@@ -101,6 +134,55 @@ pub struct AllColors{
     pub description: String,
     pub colors: Vec<TileColor>
 }
+
+// Flatten a window of panes into the colour of every tile of the mosaic.
+// Returned as rows (top to bottom) of tile colours (left to right) in image space.
+// Every tile in the window has the same size so the tile row and column are
+// found by dividing the tile box min corner by the tile size.
+pub fn get_tile_grid(window: &[Vec<(Box2D<i32, i32>, RGB)>]) -> Vec<Vec<RGB>> {
+
+    let first_tile = match window.first().and_then(|pane| pane.first()) {
+        Some(tile) => tile.0,
+        None => return Vec::new(),
+    };
+    let tile_wid = first_tile.width() + 1;   // pixel dimensions are zero based so add 1
+    let tile_hgt = first_tile.height() + 1;
+
+    let mut cols: usize = 0;
+    let mut rows: usize = 0;
+    for tile in window.iter().flatten() {
+        cols = cols.max((tile.0.min.x / tile_wid) as usize + 1);
+        rows = rows.max((tile.0.min.y / tile_hgt) as usize + 1);
+    }
+
+    let mut grid = vec![vec![RGB(0, 0, 0); cols]; rows];
+    for tile in window.iter().flatten() {
+        let col = (tile.0.min.x / tile_wid) as usize;
+        let row = (tile.0.min.y / tile_hgt) as usize;
+        grid[row][col] = tile.1;
+    }
+    grid
+}
+
+// Set the colour of every tile in `window` from `grid`, the tile colours in grid order as given by get_tile_grid
+pub fn set_tile_grid(window: &mut [Vec<(Box2D<i32, i32>, RGB)>], grid: &[Vec<RGB>]) {
+
+    let first_tile = match window.first().and_then(|pane| pane.first()) {
+        Some(tile) => tile.0,
+        None => return,
+    };
+    let tile_wid = first_tile.width() + 1;
+    let tile_hgt = first_tile.height() + 1;
+
+    for tile in window.iter_mut().flatten() {
+        let col = (tile.0.min.x / tile_wid) as usize;
+        let row = (tile.0.min.y / tile_hgt) as usize;
+        if let Some(rgb) = grid.get(row).and_then(|r| r.get(col)) {
+            tile.1 = *rgb;
+        }
+    }
+}
+
 // Quick function to create populate and return an AllColors struct
 pub fn _test_allcolors_struct()  -> AllColors {
 
@@ -108,78 +190,59 @@ pub fn _test_allcolors_struct()  -> AllColors {
         rgb: RGB(0,0,0),
         name: "black".to_owned(),
         number: "1".to_owned(),
+        ldraw: None,
     };
     let tc2 = TileColor {
         rgb: RGB(255,0,0),
         name: "red".to_owned(),
         number: "2".to_owned(),
+        ldraw: None,
     };
     let tc3 = TileColor {
         rgb: RGB(0,255,0),
         name: "blue".to_owned(),
         number: "3".to_owned(),
+        ldraw: None,
     };
     let tc4 = TileColor {
         rgb: RGB(0,0,255),
         name: "green".to_owned(),
         number: "4".to_owned(),
+        ldraw: None,
     };
 
     let v = vec![tc1,tc2,tc3,tc4];
 
     let ac = AllColors{ name:"Test".to_owned(),url:"none".to_owned(),description:"Test".to_owned(), colors: v};
-    println!("{:?}",ac);
+    log::debug!("{:?}",ac);
 
     // function return value
     ac
 }
 
 
-// load tile color files from a json file
-// todo need throw exception for missing or deformed file
-pub fn load_all_colors(path_str: &str) -> AllColors {
-    let path = Path::new(path_str);
+// Palettes built into the binary for the board presets, named in "tile_colors" like a file
+// so they are found on any machine and a saved run config still works elsewhere
+const BUILTIN_PALETTES: [(&str, &str); 2] = [
+    ("builtin:perler", include_str!("../tile_json/perler_colors.json")),
+    ("builtin:lego", include_str!("../tile_json/lego_colors.json")),
+];
+
+/// True for the names of the palettes built into the binary, any other tile_colors setting is a file
+pub fn is_builtin_palette(name: &str) -> bool {
+    BUILTIN_PALETTES.iter().any(|(builtin, _)| *builtin == name)
+}
+
+// load tile color files from a json file, or one of the BUILTIN_PALETTES
+pub fn load_all_colors(path_str: &str) -> std::result::Result<AllColors, TileError> {
 
-    match File::open(path) {
-       Ok(mut file) => {
-           println!();
-           println!("Tile Colour File was successfully opened");
-           let mut buf = vec![];
-           if file.read_to_end(&mut buf).is_ok() {
-               match serde_json::from_slice(&buf[..]) {
-                   Ok(all_colors) => return all_colors,
-                   Err(e) => {
-                       eprintln!("Could not read Colour file {:?} \n  {}", path, e );
-                       panic!("Improperly formed JSON file");
-                   }
-               } // match serde_json
-           }
-           else {
-               eprintln!("Could not read tile colors file {:?}", path);
-               panic!("Improperly formed JSON file");
-           }
-       },
-       Err(e) => {
-           eprintln!(" Could not open Colors file {:?} \n  {}", path, e );
-           panic!("Missing Tile Colors File");
-        },
-     }
-
-    // if let Ok(mut file) = File::open(path) {
-    //     let mut buf = vec![];
-    //     if file.read_to_end(&mut buf).is_ok() {
-    //         if let Ok(all_colors) = serde_json::from_slice(&buf[..]) {
-    //             return all_colors;
-    //         }
-    //     }
-    // }
-    // // todo  update error handling
-    // // See load_configs
-    // // There was no file, or the file failed to load, create a new All_Colors.
-    // println!("no file, or the file failed to load, create a new All_Colors\n*****\n*****\nThere was a problem \n*****\n*****" );
-    //
-    // let tc1 = TileColor { rgb: RGB(0,0,0), name: "black".to_owned() , number: "0".to_owned() };
-    // AllColors{name:"Hack".to_owned(),url:"none".to_owned(),description:"MadeUp".to_owned(), colors: vec![tc1] }
+    let path = Path::new(path_str);
+    if let Some((_, json)) = BUILTIN_PALETTES.iter().find(|(builtin, _)| *builtin == path_str) {
+        return serde_json::from_str(json).map_err(|e| TileError::PaletteParse { path: path.to_path_buf(), source: e });
+    }
+    let buf = std::fs::read(path).map_err(|e| TileError::PaletteRead { path: path.to_path_buf(), source: e })?;
+    log::debug!("Tile Colour File {} was successfully opened", path.display());
+    serde_json::from_slice(&buf[..]).map_err(|e| TileError::PaletteParse { path: path.to_path_buf(), source: e })
 }
 
 
@@ -197,9 +260,9 @@ pub fn old_load_all_colors<P: AsRef<Path>>(path: P) -> AllColors {
     // todo  update error handling
     // See load_configs
     // There was no file, or the file failed to load, create a new All_Colors.
-    println!("no file, or the file failed to load, create a new All_Colors\n*****\n*****\nThere was a problem \n*****\n*****" );
+    log::warn!("no file, or the file failed to load, create a new All_Colors" );
 
-    let tc1 = TileColor { rgb: RGB(0,0,0), name: "black".to_owned() , number: "0".to_owned() };
+    let tc1 = TileColor { rgb: RGB(0,0,0), name: "black".to_owned() , number: "0".to_owned(), ldraw: None };
     AllColors{name:"Hack".to_owned(),url:"none".to_owned(),description:"MadeUp".to_owned(), colors: vec![tc1] }
 }
 
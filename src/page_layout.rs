@@ -0,0 +1,225 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pdf_util::{PANE_PAGE_MIN_HEIGHT_MM, PANE_PAGE_MIN_WIDTH_MM};
+
+// Paper sizes, name used in the config file and on the command line, width and height in mm (portrait)
+pub const PAPER_SIZES: [(&str, f64, f64); 6] = [
+    ("a3", 297.0, 420.0),
+    ("a4", 210.0, 297.0),
+    ("a5", 148.0, 210.0),
+    ("letter", 215.9, 279.4),
+    ("legal", 215.9, 355.6),
+    ("tabloid", 279.4, 431.8),
+];
+
+// Names of all the paper sizes, used for command line help and error messages
+pub fn paper_names() -> Vec<&'static str> {
+    PAPER_SIZES.iter().map(|(name, _, _)| *name).collect()
+}
+
+// Portrait width and height in mm of a named paper size
+pub fn paper_size(name: &str) -> Option<(f64, f64)> {
+    PAPER_SIZES.iter()
+               .find(|(n, _, _)| n.eq_ignore_ascii_case(name))
+               .map(|(_, w, h)| (*w, *h))
+}
+
+/// Optional "page" section of the config file, all sizes in mm. For example
+///   "page": { "size": "a4", "orientation": "portrait", "margin": 15, "margin_right": 25 }
+///   "page": { "size": "custom", "width": 300, "height": 200 }
+/// Anything left out keeps the default of US Letter landscape with 20 mm margins.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone)]
+pub struct PageConfig {
+    pub size: Option<String>,          // one of PAPER_SIZES or "custom"
+    pub width: Option<f64>,            // custom page width
+    pub height: Option<f64>,           // custom page height
+    pub orientation: Option<String>,   // "landscape" or "portrait"
+    pub margin: Option<f64>,           // all four margins
+    pub margin_top: Option<f64>,       // the per side margins override "margin"
+    pub margin_right: Option<f64>,
+    pub margin_bottom: Option<f64>,
+    pub margin_left: Option<f64>,
+}
+
+/// Page size and margins in mm used to lay out the instruction pages.
+/// The content box is the part of the page inside the margins, PDF origin is the lower left corner.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PageLayout {
+    pub width_mm: f64,
+    pub height_mm: f64,
+    pub margin_top_mm: f64,
+    pub margin_right_mm: f64,
+    pub margin_bottom_mm: f64,
+    pub margin_left_mm: f64,
+}
+
+impl Default for PageLayout {
+    // US Letter landscape with 20 mm margins
+    fn default() -> Self {
+        PageLayout {
+            width_mm: 279.4,
+            height_mm: 215.9,
+            margin_top_mm: 20.0,
+            margin_right_mm: 20.0,
+            margin_bottom_mm: 20.0,
+            margin_left_mm: 20.0,
+        }
+    }
+}
+
+impl PageLayout {
+
+    /// Build the page layout from the optional "page" config section.
+    /// Returns a description of the problem if the settings do not make a usable page.
+    pub fn from_config(page: Option<&PageConfig>) -> Result<PageLayout, String> {
+
+        let page = match page {
+            Some(page) => page,
+            None => return Ok(PageLayout::default()),
+        };
+        let default = PageLayout::default();
+
+        // page size, a custom size is taken as given unless an orientation is asked for
+        let size = page.size.as_deref().unwrap_or(if page.width.is_some() || page.height.is_some() { "custom" } else { "letter" });
+        let (width_mm, height_mm) = if size.eq_ignore_ascii_case("custom") {
+            match (page.width, page.height) {
+                (Some(w), Some(h)) => (w, h),
+                _ => return Err("page \"size\": \"custom\" needs both \"width\" and \"height\" in mm".to_owned()),
+            }
+        } else {
+            match paper_size(size) {
+                Some((w, h)) => (w, h),
+                None => return Err(format!("unknown page \"size\" {} - expected one of {:?} or custom", size, paper_names())),
+            }
+        };
+        let (short_mm, long_mm) = (width_mm.min(height_mm), width_mm.max(height_mm));
+        let (width_mm, height_mm) = match page.orientation.as_deref() {
+            Some(o) if o.eq_ignore_ascii_case("landscape") => (long_mm, short_mm),
+            Some(o) if o.eq_ignore_ascii_case("portrait") => (short_mm, long_mm),
+            Some(o) => return Err(format!("unknown page \"orientation\" {} - expected landscape or portrait", o)),
+            None if size.eq_ignore_ascii_case("custom") => (width_mm, height_mm),
+            None => (long_mm, short_mm),
+        };
+
+        let margin = |side: Option<f64>, fallback: f64| side.or(page.margin).unwrap_or(fallback);
+        let layout = PageLayout {
+            width_mm,
+            height_mm,
+            margin_top_mm: margin(page.margin_top, default.margin_top_mm),
+            margin_right_mm: margin(page.margin_right, default.margin_right_mm),
+            margin_bottom_mm: margin(page.margin_bottom, default.margin_bottom_mm),
+            margin_left_mm: margin(page.margin_left, default.margin_left_mm),
+        };
+
+        if layout.width_mm <= 0.0 || layout.height_mm <= 0.0 {
+            return Err(format!("page size {} x {} mm must be greater than zero", layout.width_mm, layout.height_mm));
+        }
+        if layout.margin_top_mm < 0.0 || layout.margin_right_mm < 0.0 || layout.margin_bottom_mm < 0.0 || layout.margin_left_mm < 0.0 {
+            return Err("page margins cannot be negative".to_owned());
+        }
+        // the pane detail pages need the most room
+        if layout.content_width_mm() < PANE_PAGE_MIN_WIDTH_MM || layout.content_height_mm() < PANE_PAGE_MIN_HEIGHT_MM {
+            return Err(format!("page margins leave only {:.1} x {:.1} mm of a {:.1} x {:.1} mm page - the pane pages need at least {:.0} x {:.0} mm inside the margins",
+                               layout.content_width_mm(), layout.content_height_mm(), layout.width_mm, layout.height_mm,
+                               PANE_PAGE_MIN_WIDTH_MM, PANE_PAGE_MIN_HEIGHT_MM));
+        }
+        Ok(layout)
+    } // from_config
+
+    pub fn content_width_mm(&self) -> f64 {
+        self.width_mm - self.margin_left_mm - self.margin_right_mm
+    }
+
+    pub fn content_height_mm(&self) -> f64 {
+        self.height_mm - self.margin_top_mm - self.margin_bottom_mm
+    }
+
+    // PDF y of the top edge of the content box
+    pub fn content_top_mm(&self) -> f64 {
+        self.height_mm - self.margin_top_mm
+    }
+
+    // Baseline for a page title written in the top margin
+    pub fn header_y_mm(&self) -> f64 {
+        self.height_mm - (self.margin_top_mm * 0.6).max(8.0)
+    }
+
+    // Baseline for a page footer written in the bottom margin
+    pub fn footer_y_mm(&self) -> f64 {
+        (self.margin_bottom_mm * 0.3).max(3.0)
+    }
+}
+
+/// Largest tile size (width, height) in mm that fits `cols` x `rows` tiles inside a box of
+/// `box_wid_mm` x `box_hgt_mm` while keeping the tile height to width ratio `tile_aspect`.
+/// Whichever of the box width or height runs out first limits the size.
+pub fn fit_grid(box_wid_mm: f64, box_hgt_mm: f64, cols: f64, rows: f64, tile_aspect: f64) -> (f64, f64) {
+
+    let wid_limited_mm = box_wid_mm / cols;
+    let hgt_limited_mm = box_hgt_mm / rows / tile_aspect;
+    let tile_wid_mm = wid_limited_mm.min(hgt_limited_mm);
+
+    (tile_wid_mm, tile_wid_mm * tile_aspect)
+} // fit_grid
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(page: PageConfig) -> Result<PageLayout, String> {
+        PageLayout::from_config(Some(&page))
+    }
+
+    #[test]
+    fn default_is_letter_landscape() {
+        assert_eq!(PageLayout::from_config(None), Ok(PageLayout::default()));
+        assert_eq!(layout(PageConfig::default()), Ok(PageLayout::default()));
+    }
+
+    #[test]
+    fn paper_sizes_are_landscape_unless_asked() {
+        let a4 = layout(PageConfig { size: Some("A4".to_owned()), ..Default::default() }).unwrap();
+        assert_eq!((a4.width_mm, a4.height_mm), (297.0, 210.0));
+        let a4 = layout(PageConfig { size: Some("a4".to_owned()), orientation: Some("Portrait".to_owned()), ..Default::default() }).unwrap();
+        assert_eq!((a4.width_mm, a4.height_mm), (210.0, 297.0));
+        assert!(layout(PageConfig { size: Some("b5".to_owned()), ..Default::default() }).unwrap_err().contains("unknown page \"size\" b5"));
+        assert!(layout(PageConfig { orientation: Some("upright".to_owned()), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn custom_sizes_keep_their_orientation_unless_asked() {
+        let custom = PageConfig { width: Some(400.0), height: Some(300.0), ..Default::default() };
+        let page = layout(custom.clone()).unwrap();
+        assert_eq!((page.width_mm, page.height_mm), (400.0, 300.0));
+        let page = layout(PageConfig { orientation: Some("portrait".to_owned()), ..custom }).unwrap();
+        assert_eq!((page.width_mm, page.height_mm), (300.0, 400.0));
+        assert!(layout(PageConfig { size: Some("custom".to_owned()), width: Some(400.0), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn side_margins_override_the_margin() {
+        let page = layout(PageConfig { margin: Some(10.0), margin_left: Some(25.0), ..Default::default() }).unwrap();
+        assert_eq!((page.margin_top_mm, page.margin_right_mm, page.margin_bottom_mm, page.margin_left_mm), (10.0, 10.0, 10.0, 25.0));
+        assert!((page.content_width_mm() - 244.4).abs() < 1e-9);
+        assert!(layout(PageConfig { margin_top: Some(-1.0), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn pages_too_small_for_the_pane_pages_are_an_error() {
+        let small = PageConfig { size: Some("custom".to_owned()), width: Some(100.0), height: Some(100.0), ..Default::default() };
+        assert!(layout(small).unwrap_err().contains("the pane pages need at least"));
+        assert!(layout(PageConfig { margin: Some(100.0), ..Default::default() }).is_err());
+        assert!(layout(PageConfig { margin: Some(0.0), size: Some("custom".to_owned()), width: Some(PANE_PAGE_MIN_WIDTH_MM), height: Some(PANE_PAGE_MIN_HEIGHT_MM), ..Default::default() }).is_ok());
+        assert!(layout(PageConfig { size: Some("a5".to_owned()), orientation: Some("portrait".to_owned()), ..Default::default() }).is_ok());
+    }
+
+    #[test]
+    fn fit_grid_keeps_the_tile_aspect() {
+        // width runs out first
+        assert_eq!(fit_grid(100.0, 100.0, 10.0, 5.0, 1.0), (10.0, 10.0));
+        // height runs out first
+        assert_eq!(fit_grid(100.0, 50.0, 5.0, 10.0, 1.0), (5.0, 5.0));
+        // tiles twice as tall as wide
+        assert_eq!(fit_grid(100.0, 100.0, 10.0, 10.0, 2.0), (5.0, 10.0));
+    }
+}
@@ -0,0 +1,100 @@
+use clap::ArgMatches;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::{ConfigProblem, TileError};
+use crate::modtile::{self, AllColors};
+use crate::swatch;
+
+// The palette subcommand, tools for the tile colour json files
+//   list   print every colour of a palette with its number, rgb, hex and LDraw code
+//   check  report repeated colours and names and colours without a name or number. Repeated numbers are only
+//          warned about as paint palettes give the pigment number, shared by several paints
+
+/// Run "palette list" or "palette check"
+pub(crate) fn palette_command(matches: &ArgMatches) -> Result<(), TileError> {
+    match matches.subcommand() {
+        Some(("list", m)) => list_palette(m),
+        Some(("check", m)) => check_palette(m),
+        _ => unreachable!("clap requires a palette subcommand"),
+    }
+}
+
+// Print the colours of the palette as a table in the order asked for
+fn list_palette(matches: &ArgMatches) -> Result<(), TileError> {
+    let all_colors: AllColors = modtile::load_all_colors(matches.get_one::<String>("palette").expect("palette argument missing"))?;
+    let sort = matches.get_one::<String>("sort").expect("sort missing");
+
+    println!();
+    println!("{} - {} colours", all_colors.name, all_colors.colors.len());
+    if !all_colors.description.is_empty() {
+        println!("{}", all_colors.description);
+    }
+    println!();
+    println!("{:<8} {:<28} {:<15} {:<8} LDraw", "Number", "Name", "RGB", "Hex");
+    for tc in swatch::sorted_colors(&all_colors.colors, sort) {
+        println!("{:<8} {:<28} {:<15} #{:02X}{:02X}{:02X}  {}",
+                 tc.number, tc.name,
+                 format!("{},{},{}", tc.rgb.0, tc.rgb.1, tc.rgb.2),
+                 tc.rgb.0, tc.rgb.1, tc.rgb.2,
+                 tc.ldraw.map(|code| code.to_string()).unwrap_or_default());
+    }
+    Ok(())
+}
+
+// Report every problem with the palette at once, a palette with problems is an error (exit code 4)
+fn check_palette(matches: &ArgMatches) -> Result<(), TileError> {
+    let path_str = matches.get_one::<String>("palette").expect("palette argument missing");
+    let all_colors: AllColors = modtile::load_all_colors(path_str)?;
+
+    let mut problems: Vec<ConfigProblem> = Vec::new();
+    if all_colors.colors.is_empty() {
+        problems.push(ConfigProblem { key: "colors".to_owned(), message: "the palette has no colours".to_owned(), suggestion: None });
+    }
+    if all_colors.name.trim().is_empty() {
+        problems.push(ConfigProblem { key: "name".to_owned(), message: "the palette has no name".to_owned(),
+                                      suggestion: Some("the name is used for swatch file names and on the cover page".to_owned()) });
+    }
+
+    // first colour seen with each rgb, number and name
+    let mut seen_rgb: HashMap<(u8, u8, u8), usize> = HashMap::new();
+    let mut seen_number: HashMap<String, usize> = HashMap::new();
+    let mut seen_name: HashMap<String, usize> = HashMap::new();
+    for (i, tc) in all_colors.colors.iter().enumerate() {
+        let key = format!("colors[{}]", i);
+        let mut repeated = |what: &str, first: Option<&usize>| {
+            if let Some(first) = first {
+                problems.push(ConfigProblem { key: key.clone(),
+                                              message: format!("{} {} repeats colors[{}] {}", tc.name, what, first, all_colors.colors[*first].name),
+                                              suggestion: None });
+            }
+        };
+        repeated(&format!("rgb {},{},{}", tc.rgb.0, tc.rgb.1, tc.rgb.2), seen_rgb.get(&(tc.rgb.0, tc.rgb.1, tc.rgb.2)));
+        if let Some(first) = seen_number.get(tc.number.trim()).filter(|_| !tc.number.trim().is_empty()) {
+            log::warn!("colors[{}] {} number {} repeats colors[{}] {}", i, tc.name, tc.number, first, all_colors.colors[*first].name);
+        }
+        if !tc.name.trim().is_empty() {
+            repeated("name", seen_name.get(&tc.name.trim().to_lowercase()));
+        }
+
+        if tc.name.trim().is_empty() {
+            problems.push(ConfigProblem { key: key.clone(), message: format!("rgb {},{},{} has no name", tc.rgb.0, tc.rgb.1, tc.rgb.2), suggestion: None });
+        }
+        if tc.number.trim().is_empty() {
+            problems.push(ConfigProblem { key: key.clone(), message: format!("{} has no number", tc.name),
+                                          suggestion: Some("numbers are printed on the swatches and used by the number tile symbols".to_owned()) });
+        }
+
+        seen_rgb.entry((tc.rgb.0, tc.rgb.1, tc.rgb.2)).or_insert(i);
+        seen_number.entry(tc.number.trim().to_owned()).or_insert(i);
+        seen_name.entry(tc.name.trim().to_lowercase()).or_insert(i);
+    }
+
+    if problems.is_empty() {
+        println!();
+        println!("Palette {} is valid - {} colours", all_colors.name, all_colors.colors.len());
+        Ok(())
+    } else {
+        Err(TileError::PaletteInvalid { path: Path::new(path_str).to_path_buf(), problems })
+    }
+} // check_palette
@@ -0,0 +1,132 @@
+use lopdf::{Dictionary, Document, Object, ObjectId};
+
+// Navigation added to the instructions pdf once printpdf has written it.
+// printpdf 0.3 has no page labels or link annotations so the saved pdf is loaded
+// with lopdf, the outline, page labels and links are added and the pdf is written again.
+
+/// A clickable pane on the overview page, `rect_mm` is the lower left corner, width and
+/// height of the pane on the overview page. `page` is the 0 based index of the pane's detail page.
+pub(crate) struct PaneLink {
+    pub(crate) page: usize,
+    pub(crate) rect_mm: (f64, f64, f64, f64),
+}
+
+/// Label shown by pdf viewers for the pages starting at `page` (0 based) up to the next label.
+/// When `numbered` the pages of the range are numbered from 1 after the prefix.
+pub(crate) struct PageLabel {
+    pub(crate) page: usize,
+    pub(crate) prefix: String,
+    pub(crate) numbered: bool,
+}
+
+/// An entry in the pdf outline (bookmarks) with optional nested entries.
+pub(crate) struct Bookmark {
+    pub(crate) title: String,
+    pub(crate) page: usize,
+    pub(crate) children: Vec<Bookmark>,
+}
+
+/// Add bookmarks, page labels and links from the panes on `overview_page` to their detail pages
+/// to a pdf written by printpdf. Returns the updated pdf.
+pub(crate) fn add_navigation(pdf_bytes: &[u8],
+                             overview_page: usize,
+                             bookmarks: &[Bookmark],
+                             labels: &[PageLabel],
+                             links: &[PaneLink]) -> Result<Vec<u8>, lopdf::Error> {
+
+    let mut doc = Document::load_mem(pdf_bytes)?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+    let page_ref = |page: usize| page_ids.get(page).copied().ok_or(lopdf::Error::PageNumberNotFound(page as u32 + 1));
+
+    // outline, the top level entries point at their first page
+    let outline_id = doc.new_object_id();
+    let (first, last, count) = add_outline_items(&mut doc, outline_id, bookmarks, &page_ref)?;
+    let mut outline = Dictionary::new();
+    outline.set("Type", "Outlines");
+    if let (Some(first), Some(last)) = (first, last) {
+        outline.set("First", first);
+        outline.set("Last", last);
+        outline.set("Count", count as i64);
+    }
+    doc.objects.insert(outline_id, Object::Dictionary(outline));
+
+    // page labels number tree, the ranges must be in page order
+    let mut nums: Vec<Object> = Vec::new();
+    let mut sorted_labels: Vec<&PageLabel> = labels.iter().collect();
+    sorted_labels.sort_by_key(|label| label.page);
+    for label in sorted_labels {
+        let mut range = Dictionary::new();
+        range.set("P", Object::string_literal(label.prefix.as_str()));
+        if label.numbered {
+            range.set("S", "D");
+        }
+        nums.push((label.page as i64).into());
+        nums.push(Object::Dictionary(range));
+    }
+    let mut page_labels = Dictionary::new();
+    page_labels.set("Nums", nums);
+
+    // links from each pane on the overview page to its detail page
+    let mm_to_pt = 72.0 / 25.4;
+    let mut annots: Vec<Object> = Vec::new();
+    for link in links {
+        let (x, y, w, h) = link.rect_mm;
+        let mut annot = Dictionary::new();
+        annot.set("Type", "Annot");
+        annot.set("Subtype", "Link");
+        annot.set("Rect", vec![(x * mm_to_pt).into(), (y * mm_to_pt).into(), ((x + w) * mm_to_pt).into(), ((y + h) * mm_to_pt).into()]);
+        annot.set("Border", vec![0.into(), 0.into(), 0.into()]);
+        annot.set("Dest", vec![page_ref(link.page)?.into(), "Fit".into()]);
+        annots.push(doc.add_object(annot).into());
+    }
+    let overview_id = page_ref(overview_page)?;
+    if let Object::Dictionary(ref mut overview) = doc.get_object_mut(overview_id)? {
+        overview.set("Annots", annots);
+    }
+
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    if let Object::Dictionary(ref mut catalog) = doc.get_object_mut(catalog_id)? {
+        catalog.set("Outlines", outline_id);
+        catalog.set("PageLabels", page_labels);
+        catalog.set("PageMode", "UseOutlines");
+    }
+
+    let mut out: Vec<u8> = Vec::new();
+    doc.save_to(&mut out)?;
+    Ok(out)
+} // add_navigation
+
+// Add one level of outline items under `parent_id`, returns the first and last item and the number of visible items
+fn add_outline_items<F>(doc: &mut Document,
+                        parent_id: ObjectId,
+                        bookmarks: &[Bookmark],
+                        page_ref: &F) -> Result<(Option<ObjectId>, Option<ObjectId>, usize), lopdf::Error>
+    where F: Fn(usize) -> Result<ObjectId, lopdf::Error> {
+
+    let item_ids: Vec<ObjectId> = bookmarks.iter().map(|_| doc.new_object_id()).collect();
+    let mut count = 0;
+    for (i, bookmark) in bookmarks.iter().enumerate() {
+        let mut item = Dictionary::new();
+        item.set("Title", Object::string_literal(bookmark.title.as_str()));
+        item.set("Parent", parent_id);
+        item.set("Dest", vec![page_ref(bookmark.page)?.into(), "Fit".into()]);
+        if i > 0 {
+            item.set("Prev", item_ids[i - 1]);
+        }
+        if i + 1 < item_ids.len() {
+            item.set("Next", item_ids[i + 1]);
+        }
+
+        // nested entries start closed so a long list of panes does not fill the outline
+        let (first, last, child_count) = add_outline_items(doc, item_ids[i], &bookmark.children, page_ref)?;
+        if let (Some(first), Some(last)) = (first, last) {
+            item.set("First", first);
+            item.set("Last", last);
+            item.set("Count", -(child_count as i64));
+        }
+        doc.objects.insert(item_ids[i], Object::Dictionary(item));
+        count += 1;
+    }
+
+    Ok((item_ids.first().copied(), item_ids.last().copied(), count))
+} // add_outline_items
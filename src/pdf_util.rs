@@ -2,206 +2,76 @@ use printpdf::*;
 use printpdf::utils::calculate_points_for_circle;
 // use printpdf::utils::calculate_points_for_rect;
 use std::{collections::HashMap, fs::File};
-use std::ops::Range;
-use std::io::BufWriter;
-use regex::Regex;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
 use std::option::Option::Some;use crate::modtile;
+use crate::error::TileError;
+use crate::preset::BoardPreset;
+use crate::fonts::{self, PdfFonts};
+use crate::page_layout::{self, PageLayout};
+use crate::swatch;
+use crate::cover_page::{self, CoverStats};
+use crate::checklist;
+use image::DynamicImage;
+use crate::tile_symbols::{self, TileSymbols};
+use crate::pdf_nav::{self, Bookmark, PageLabel, PaneLink};
+use crate::units;
 use euclid::{Point2D,Box2D};
 
 #[derive(PartialEq, Debug)]
 pub struct PanePdfConfig {
-     max_pane_x_px: i32,    // img_max_x_px : i32 ,
-     max_pane_y_px: i32,    // img_max_y_px : i32,
-     pane_row_count : i32,
-     pane_col_count : i32,
-     pane_tile_row_count : i32,
-     pane_tile_col_count :i32 ,
-     window_panes_coords_px : Vec<Box2D<i32,i32>>
+     pub(crate) max_pane_x_px: i32,    // img_max_x_px : i32 ,
+     pub(crate) max_pane_y_px: i32,    // img_max_y_px : i32,
+     pub(crate) pane_row_count : i32,
+     pub(crate) pane_col_count : i32,
+     pub(crate) pane_tile_row_count : i32,
+     pub(crate) pane_tile_col_count :i32 ,
+     pub(crate) window_panes_coords_px : Vec<Box2D<i32,i32>>
 }
 
-pub(crate) fn generate_color_swatch(all_colors: &crate::modtile::AllColors) -> Result<(), String> {
-
-    let x_mm = 215.9;
-    let y_mm = 279.4;
-    let (doc, page1, layer1) =
-        PdfDocument::new(&all_colors.name.to_owned(), Mm(x_mm), Mm(y_mm), "Layer 1");
-
-    let(page2, layer2) = doc.add_page(Mm(x_mm), Mm(y_mm), "Layer 2");
-    let(page3, layer3) = doc.add_page(Mm(x_mm), Mm(y_mm), "Layer 3");
-
-    let layer1 = doc.get_page(page1).get_layer(layer1);
-    let layer2 = doc.get_page(page2).get_layer(layer2);
-    let layer3 = doc.get_page(page3).get_layer(layer3);
-
-    let swatch_name = &all_colors.name;
-    let swatch_url= &all_colors.url;
-    let swatch_desc = &all_colors.description;
-
-    // println!("swatch desc {:?}", swatch_desc);
-    // let swatch_desc = "Artist's quality colours made with permanent light fast pigments in 100% acrylic resin,\n made by hand in Vancouver, Canada since 1970. No fillers or extenders are added.";
-
-    let font1 = doc
-        .add_external_font(File::open("/System/Library/Fonts/NewYork.ttf").unwrap())
-        .unwrap();
-
-    // text, font size, x from left edge, y from bottom edge, font
-    layer1.use_text(swatch_name, 14.0, Mm(40.0), Mm(260.0), &font1);
-    layer1.use_text(swatch_url, 11.0, Mm(40.0), Mm(254.0), &font1);
-    // layer1.use_text(swatch_desc, 11.0, Mm(40.0), Mm(248.0), &font1);
-
-    layer1.begin_text_section();
-        // setup the general fonts.
-        // see the docs for these functions for details
-        layer1.set_font(&font1, 10.0);
-        layer1.set_text_cursor(Mm(40.0), Mm(250.0));
-        layer1.set_line_height(10.0);
-
-        for line in swatch_desc.lines() {
-            layer1.write_text(line, &font1);
-            layer1.add_line_break(); // <---
-        }
-    layer1.end_text_section();  // <- important!
-
-    layer2.use_text(swatch_name, 14.0, Mm(40.0), Mm(260.0), &font1);
-    layer3.use_text(swatch_name, 14.0, Mm(40.0), Mm(260.0), &font1);
-
-    let step = 4;  // This value is the number of columns (4) to be displayed per line in PDF doc
-    let len = &all_colors.colors.len();
-    println!("Total number of colour swatches: {}", &len);
-
-    let mut row :i32 = 0;
-    // loop over the colors array in steps of step size
-    // for each slice create a row of color swatch squares
-    for st in (0..*len).step_by(step) {
-        // println!("Step Start {} End {}", st, st+step);
-
-        let mut e = st+step; // end of range
-        if (e) >= *len {     // if end is past the limit of elements and adjust end to len
-            e = *len;
-            // println!("end of array so trim last slice to {}" , &e);
-        }
-
-        let rnge = Range { start: st , end: e };  // range for current slice
-        let slice = &all_colors.colors[rnge];     // grab the slice of TileColors
-
-        // todo remove magic nos below.
-        // draw swatches to pdf layer/page.
-        // Currrently Each layer/page can accomodate 5 rows of 4 swatches
-        if row <= 4 {
-                draw_layer1_swatches(&layer1, slice, &font1, row);
-        } else if row > 4 && row <= 9 {
-                draw_layer1_swatches(&layer2, slice, &font1, row - 5 );
-        } else {
-                draw_layer1_swatches(&layer3, slice, &font1, row - 10 );
-        }
-
-        // increment row count to display next row
-        row += 1;
-    }
-
-    // create output file name from name field hyphenated
-    let tile_name =  &all_colors.name.to_owned();
-
-    // remove all the spaces and replace with underscores
-    let re = Regex::new(r"([\s])").unwrap();
-    let output = re.replace_all(tile_name, "_");
-    let fileout = format!("./images/output/{}.pdf", output);
-    println!("Output instructions found in {}", &fileout);
-
-    doc.save(&mut BufWriter::new(
-        File::create(fileout).unwrap(),
-    )).unwrap();
-
-    // mgj todo some proper error handling
-    Ok(())
-    // Err("something broke".to_owned())
-} // generate_color_swatch
-
-// draw swatches to pdf layer/page.
-// Currrently Each layer/page can accomodate 5 rows of 4 swatches
-fn draw_layer1_swatches(layer1: &PdfLayerReference,
-                                slice: &[crate::modtile::TileColor],
-                                font: &IndirectFontRef, row: i32) -> () {
-
-    // println!("draw_layer1_swatches - cur_row {}", row);
-
-    // Todo Do we make these global vars? Constants?
-    let swatch_font_size: f64 = 11.0; // size 10.0 might be ok
-    let x_space = 10.0;  // horizontal spacing between color swatches in MM
-    let y_space = 7.0; // vertical spacing between color swatches in MM
-    let square_size: f64 = 30.0;
-    let x_start_pos: f64 = 30.0;
-    let y_font_line_height: f64 = 4.0;
-    let y_first_row_pos: f64 = 214.0;
-    let y_start_pos: f64 = y_first_row_pos - row as f64 * (square_size + 3.0 * y_font_line_height + y_space );
-
-    for column in 1..= slice.len() {
-        // draw a swatch box with the color
-        // Convert Mm into Pt function.
-        let size_x_rect : Pt = Mm(square_size).into();
-        let size_y_rect : Pt = Mm(square_size).into();
-        let offset_x_rect : Pt = Mm((x_start_pos + x_space) * column as f64).into();
-        let offset_y_rect : Pt = Mm(y_start_pos).into();
-
-        let line = Line {
-            points: get_points_for_rect(size_x_rect, size_y_rect, offset_x_rect, offset_y_rect),
-            is_closed: true,
-            has_fill: true,
-            has_stroke: false,
-            is_clipping_path: false,
-        };
-
-        // Grab the next color tile
-        let tc : &crate::modtile::TileColor = &slice[column-1];
-
-        // Set the fill color here to current TileColor
-        let fill_color = Color::Rgb(Rgb::new(*&tc.rgb.0 as f64/255.0, *&tc.rgb.1 as f64/255.0, *&tc.rgb.2 as f64/255.0, None));
-        layer1.set_fill_color(fill_color);
-        layer1.add_shape(line);
-
-        // TileColor info as black text below box shape
-        let fill_color_black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
-        layer1.set_fill_color(fill_color_black);
-
-        let x_in_mm: Mm = Mm((x_start_pos + x_space) * column as f64);
-        let y_in_mm: Mm = Mm(y_start_pos - 1.0 * y_font_line_height);     // First line
-
-        let r_rgb = String::from (&tc.rgb.0.to_string().to_owned());
-        let g_rgb = String::from (&tc.rgb.1.to_string().to_owned());
-        let b_rgb = String::from (&tc.rgb.2.to_string().to_owned());
-
-        let rgb_str = format!("rgb ({}, {}, {})", r_rgb, g_rgb, b_rgb);
-        layer1.use_text(rgb_str, swatch_font_size, x_in_mm, y_in_mm, font);
-
-        let y_in_mm: Mm = Mm(y_start_pos - 2.0 * y_font_line_height);     // Second line
-        layer1.use_text(&tc.name, swatch_font_size, x_in_mm, y_in_mm, font);
-
-        let y_in_mm: Mm = Mm(y_start_pos - 3.0 * y_font_line_height);     // Third line
-        layer1.use_text(&tc.number, swatch_font_size, x_in_mm, y_in_mm, font);
-    }
-
-} // draw_layer1_swatches
-
 // Create the output PDF document containing all the info necessary to construct the mosaic
 // Layout based off the LEGO Art Mosaics shiny app. See https://github.com/joachim−gassen/legoartmosaic for more.
+//  0. Cover page with the mosaic preview, the original image and project details, see cover_page.rs
 //  1. Create output Swatch for tiles used?
 //  2. output image grid showing tiles and respective color.
 //  3. overlayed with Grid and number for each pane grouping of nXn tiles
 //  4. Create a detail summary page for each pane with Tile color and number and tile legend
+//  5. When building with a board preset each pane is a board and gets its own parts list page
+//  6. Bookmarks and page labels for every page and links from each pane on the overview to its page, see pdf_nav.rs
+//  7. Optional assembly checklist, a progress table and per pane pick lists, see checklist.rs
 pub(crate) fn build_output_pdf(save_path: &std::path::Path,
                                all_colors: &modtile::AllColors,
                                tile_color_count_vec: Vec<(Vec<u8>, i32)>,
-                               output_window: &Vec<Vec<(euclid::Box2D<i32, i32>, modtile::RGB)>>) -> () {
+                               output_window: &Vec<Vec<(euclid::Box2D<i32, i32>, modtile::RGB)>>,
+                               board: Option<&BoardPreset>,
+                               cfg: &modtile::Config,
+                               images: (&DynamicImage, &DynamicImage)) -> Result<PathBuf, TileError> {
 
-    let doc_width_mm = 279.4;
-    let doc_height_mm = 215.9;
+    // page size and margins from the config, checked when the config is loaded
+    let page: PageLayout = PageLayout::from_config(cfg.page.as_ref()).map_err(TileError::Layout)?;
+    let fileout = save_path.with_extension("pdf");
     let (doc, page1, layer1) =
-        PdfDocument::new(&all_colors.name.to_owned(), Mm(doc_width_mm), Mm(doc_height_mm), "Layer 1");
-    let current_layer = doc.get_page(page1).get_layer(layer1);
+        PdfDocument::new(&all_colors.name.to_owned(), Mm(page.width_mm), Mm(page.height_mm), "Layer 1");
+    let cover_layer = doc.get_page(page1).get_layer(layer1);
+
+    let pdf_fonts: PdfFonts = fonts::load_pdf_fonts(&doc, cfg).map_err(|e| TileError::output(&fileout, e))?;
+
+    // cover page with the mosaic preview, original image and project details
+    let pane_label = if board.is_some() { "Board" } else { "Pane" };
+    let p_cfg: PanePdfConfig = get_pane_pdf_coords(output_window);
+    let tile_grid: Vec<Vec<modtile::RGB>> = modtile::get_tile_grid(output_window);
+    let stats = CoverStats {
+        tile_cols: tile_grid.first().map(|row| row.len()).unwrap_or(0),
+        tile_rows: tile_grid.len(),
+        pane_cols: p_cfg.pane_col_count as usize,
+        pane_rows: p_cfg.pane_row_count as usize,
+        pane_label,
+    };
+    cover_page::draw_cover_page(&cover_layer, &pdf_fonts, &page, all_colors, cfg, images, &stats);
 
-    let font1 = doc
-        .add_external_font(File::open("/System/Library/Fonts/NewYork.ttf").unwrap())
-        .unwrap();
+    // the overview of all the panes follows the cover
+    let (page2, layer2) = doc.add_page(Mm(page.width_mm), Mm(page.height_mm), "Overview, Layer 1");
+    let current_layer = doc.get_page(page2).get_layer(layer2);
 
     let fill_color = Color::Cmyk(Cmyk::new(0.0, 0.23, 0.0, 0.0, None));
     let outline_color = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
@@ -213,18 +83,93 @@ pub(crate) fn build_output_pdf(save_path: &std::path::Path,
     current_layer.set_outline_thickness(2.0);
 
     // construct a grid of window panes on current layer
-    construct_window_panes(&current_layer, &doc, doc_width_mm,doc_height_mm , &font1 , all_colors, tile_color_count_vec,  output_window);
+    // the symbol and print mode of each tile colour, checked when the config is loaded
+    let symbols: TileSymbols = TileSymbols::new(cfg.tile_symbols.as_deref().unwrap_or("index"),
+                                                cfg.print_mode.as_deref().unwrap_or("color"),
+                                                all_colors,
+                                                &tile_color_count_vec)
+                                   .map_err(TileError::Layout)?;
+
+    let (mut pane_links, summary_page) = construct_window_panes(&current_layer, &doc, &page, &pdf_fonts , all_colors, &symbols,  output_window, board);
+
+    // true size of the mosaic and the scale the overview is drawn at, in the config's units
+    if let (Some(link), true) = (pane_links.first(), stats.pane_cols > 0) {
+        let (build_wid, build_hgt) = units::finished_size_mm(cfg, stats.tile_cols, stats.tile_rows);
+        let drawn_tile_mm = link.rect_mm.2 / (stats.tile_cols / stats.pane_cols) as f64;
+        let scale = (cfg.tile_size_x.mm + cfg.tile_space_x.mm) / drawn_tile_mm;
+        let scale_text = format!("Finished size {} x {}   Tile {} x {}   Scale 1:{}",
+                                 units::format_length(build_wid, cfg), units::format_length(build_hgt, cfg),
+                                 units::format_length(cfg.tile_size_x.mm, cfg), units::format_length(cfg.tile_size_y.mm, cfg),
+                                 units::format_number(scale));
+        current_layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+        current_layer.use_text(scale_text, 10.0, Mm(page.margin_left_mm), Mm(page.footer_y_mm()), &pdf_fonts.regular);
+    }
+
+    // construct the final summary pages listing total number of each tile color used
+    // in the order set by "summary_order" in the config, most used first by default
+    let summary_page_count = construct_tile_color_summary_page(&&doc,
+                                  &pdf_fonts,
+                                  all_colors,
+                                  &tile_color_count_vec,
+                                  &symbols,
+                                  &page,
+                                  cfg.summary_order.as_deref().unwrap_or("count"));
+
+    // bookmarks and page labels for the cover, overview, every pane and the summary
+    // construct_window_panes numbers pages from the overview, shift them past the cover
+    let overview_page: usize = 1;
+    for link in pane_links.iter_mut() {
+        link.page += overview_page;
+    }
+    let summary_page = summary_page + overview_page;
+    let mut labels: Vec<PageLabel> = vec![
+        PageLabel { page: 0, prefix: "Cover".to_owned(), numbered: false },
+        PageLabel { page: overview_page, prefix: "Overview".to_owned(), numbered: false },
+    ];
+    let mut pane_bookmarks: Vec<Bookmark> = Vec::new();
+    for (i, link) in pane_links.iter().enumerate() {
+        let title = format!("{} {}", pane_label, i + 1);
+        labels.push(PageLabel { page: link.page, prefix: title.to_owned(), numbered: false });
+        if board.is_some() {
+            labels.push(PageLabel { page: link.page + 1, prefix: format!("{} Parts ", title), numbered: true });
+        }
+        pane_bookmarks.push(Bookmark { title, page: link.page, children: Vec::new() });
+    }
+    let mut bookmarks: Vec<Bookmark> = vec![
+        Bookmark { title: "Cover".to_owned(), page: 0, children: Vec::new() },
+        Bookmark { title: "Overview".to_owned(), page: overview_page, children: Vec::new() },
+        Bookmark { title: format!("{}s", pane_label), page: overview_page + 1, children: pane_bookmarks },
+    ];
+    if summary_page_count > 0 {
+        labels.push(PageLabel { page: summary_page, prefix: "Tile Summary ".to_owned(), numbered: true });
+        bookmarks.push(Bookmark { title: "Tile Summary".to_owned(), page: summary_page, children: Vec::new() });
+    }
+
+    // optional assembly checklist with progress table and pick lists after the summary
+    if cfg.checklist.unwrap_or(false) {
+        let checklist_page = summary_page + summary_page_count;
+        let checklist_page_count = checklist::construct_checklist_pages(&doc, &pdf_fonts, &page, all_colors, &symbols, output_window, pane_label);
+        if checklist_page_count > 0 {
+            labels.push(PageLabel { page: checklist_page, prefix: "Checklist ".to_owned(), numbered: true });
+            bookmarks.push(Bookmark { title: "Checklist".to_owned(), page: checklist_page, children: Vec::new() });
+        }
+    }
 
     // save build instructions to same output file name but with pdf extension
-    let fileout = save_path.with_extension("pdf");
-    doc.save(&mut BufWriter::new(
-        File::create(fileout).unwrap(),
-    )).unwrap();
+    let mut pdf_bytes = BufWriter::new(Vec::new());
+    doc.save(&mut pdf_bytes).map_err(|e| TileError::output(&fileout, e))?;
+    let pdf_bytes: Vec<u8> = pdf_bytes.into_inner().map_err(|e| TileError::output(&fileout, e.into_error()))?;
+    let pdf_bytes: Vec<u8> = match pdf_nav::add_navigation(&pdf_bytes, overview_page, &bookmarks, &labels, &pane_links) {
+        Ok(with_nav) => with_nav,
+        Err(e) => {
+            log::warn!("Could not add bookmarks and links to the instructions pdf - saving without them - {}", e);
+            pdf_bytes
+        }
+    };
 
-    // mgj todo some proper error handling
-    // Ok(())
-    // Err("something broke".to_owned())
+    File::create(&fileout).and_then(|mut f| f.write_all(&pdf_bytes)).map_err(|e| TileError::output(&fileout, e))?;
 
+    Ok(fileout)
 } // build_output_pdf
 
 // construct_window_panes()
@@ -256,31 +201,32 @@ pub(crate) fn build_output_pdf(save_path: &std::path::Path,
 //    min---------------|----------------|----------------|
 //       225 units wide   225 units wide   225 units wide
 //
-// Given a physical PDF output document "letter size" in landscape orientation
-//    doc_width_mm = 279.4mm and doc_height_mm = 215.9mm
+// Given a physical PDF output document, by default "letter size" in landscape orientation
+//    page width 279.4mm and page height 215.9mm
 //    horizontal and vertical page margins of 20mm each
+//    (page size, orientation and margins can be set in the config, see page_layout.rs)
 //
 //    And that the output PDF window has the same aspect ratio as the input image
-// then tile width and height in MM used in output PDF can be determined with basic math.
+// then tile width and height in MM used in output PDF are the largest that fit the grid
+// inside the page content box, see page_layout::fit_grid
 //
 fn construct_window_panes(current_layer: &PdfLayerReference,
                          doc: &PdfDocumentReference,
-                         doc_width_mm: f64,
-                         doc_height_mm: f64,
-                         pane_font: &IndirectFontRef,
+                         page: &PageLayout,
+                         fonts: &PdfFonts,
                          all_colors: &modtile::AllColors,
-                         tile_color_count_vec: Vec<(Vec<u8>, i32)>,
-                         output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>) -> () {
+                         symbols: &TileSymbols,
+                         output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>,
+                         board: Option<&BoardPreset>) -> (Vec<PaneLink>, usize) {
 
-    println!();
-    println!("construct_window_panes number of panes: {}", output_window.len());
-    println!("construct_window_panes number of tiles per pane: {}", output_window[0].len());
+    log::debug!("construct_window_panes number of panes: {}", output_window.len());
+    log::debug!("construct_window_panes number of tiles per pane: {}", output_window[0].len());
 
     // draw a simple quarter arc at (0,0). Leave as a "makers mark"
     draw_quarter_arc(&current_layer);
 
     // draw some cross marks to aid in element placement
-    draw_page_marks(&current_layer,doc_width_mm,doc_height_mm);
+    draw_page_marks(&current_layer,page.width_mm,page.height_mm);
 
     // PDF Coordinate system based on bottom left corner as origin
     // Get pane_pdf coord adusts the Box2D min max values accordingly
@@ -289,31 +235,19 @@ fn construct_window_panes(current_layer: &PdfLayerReference,
     // return a PDF output window where all Box2D coords translated from image coord space to PDF coord space
     let pdf_output_window :Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>> = get_pdf_coords(output_window,p_cfg.max_pane_y_px);
 
-    let page_margin_ver_mm = 20.0; // size of top bottom margin
-    let page_margin_hor_mm = 20.0;  // size of left right margin
-
     let imgtile_wid_px :f64 = (p_cfg.max_pane_x_px as f64 + 1.0) / p_cfg.pane_col_count as f64 / p_cfg.pane_tile_col_count as f64;  // convert p_cfg.max_pane_x_px to 1 based instead of 0 based to calc width
     let imgtile_hgt_px :f64 = (p_cfg.max_pane_y_px as f64 + 1.0) / p_cfg.pane_row_count as f64 / p_cfg.pane_tile_row_count as f64;  // convert p_cfg.max_pane_y_px to 1 based instead of 0 based to calc height
 
-    // based on the image aspect ratio compared to pdf aspect ratio adjust the max width of output image in the pdf file
-    let image_aspect :f64 = (p_cfg.max_pane_y_px + 1) as f64 / (p_cfg.max_pane_x_px + 1) as f64;  // Add one as pixel dimensions are zero based
-    let pdf_doc_aspect : f64 = (doc_height_mm - 2.0 * page_margin_ver_mm) / (doc_width_mm - 2.0 * page_margin_hor_mm);  // adjust for horizontal and vertical page margins
-
-    let pdftile_wid_mm : f64;
-    let pdftile_hgt_mm : f64;
-
     // want pdf tile height and width to remain proportional to original input imagetile height and width
-    if image_aspect < pdf_doc_aspect {
-        pdftile_wid_mm = (doc_width_mm - (2.0 * page_margin_hor_mm)) / p_cfg.pane_col_count as f64 / p_cfg.pane_tile_col_count as f64;
-        pdftile_hgt_mm = pdftile_wid_mm * imgtile_hgt_px/imgtile_wid_px;
-        println!();
-        println!("image_aspect {:.4} < pdf_doc_aspect {:.4} -> pdftile_wid_mm: {:.4}, use pdf width to limit output", image_aspect, pdf_doc_aspect, pdftile_wid_mm);
-    } else {
-        pdftile_hgt_mm = (doc_height_mm - (2.0 * page_margin_ver_mm)) / p_cfg.pane_row_count as f64 / p_cfg.pane_tile_row_count as f64;
-        pdftile_wid_mm = pdftile_hgt_mm * imgtile_wid_px/imgtile_hgt_px;
-        println!();
-        println!("image_aspect {:.4} => pdf_doc_aspect {:.4} -> pdftile_wid_mm: {:.4}, use pdf height to limit output", image_aspect, pdf_doc_aspect, pdftile_wid_mm);
-    }
+    // so the page width or height, whichever runs out first, limits the output
+    let grid_tile_cols: f64 = (p_cfg.pane_col_count * p_cfg.pane_tile_col_count) as f64;
+    let grid_tile_rows: f64 = (p_cfg.pane_row_count * p_cfg.pane_tile_row_count) as f64;
+    let (pdftile_wid_mm, pdftile_hgt_mm) = page_layout::fit_grid(page.content_width_mm(),
+                                                                 page.content_height_mm(),
+                                                                 grid_tile_cols,
+                                                                 grid_tile_rows,
+                                                                 imgtile_hgt_px / imgtile_wid_px);
+    log::debug!("pdftile_wid_mm: {:.4}, pdftile_hgt_mm: {:.4}", pdftile_wid_mm, pdftile_hgt_mm);
 
     let pdftile_wid_pt: Pt = Mm(pdftile_wid_mm).into();
     let pdftile_hgt_pt: Pt = Mm(pdftile_hgt_mm).into();
@@ -322,13 +256,14 @@ fn construct_window_panes(current_layer: &PdfLayerReference,
     let scale_factor_wid :f64 = pdftile_wid_pt.0 / imgtile_wid_px;
     let scale_factor_hgt :f64 = pdftile_hgt_pt.0 / imgtile_hgt_px ;
 
-    println!("??---> p_cfg.max_pane_x_px: {:.3},   p_cfg.max_pane_y_px: {:.3}", p_cfg.max_pane_x_px, p_cfg.max_pane_y_px );
-    println!("??---> imgtile_wid_px: {:.3}, imgtile_hgt_px: {:.3}", imgtile_wid_px, imgtile_hgt_px );
-    println!("??---> pdftile_wid_mm: {:.3}, pdftile_hgt_mm: {:.3}", pdftile_wid_mm, pdftile_hgt_mm );
-    println!("??---> scale_factor_wid: {:.3}, scale_factor_hgt: {:.3}", scale_factor_wid, scale_factor_hgt );
+    log::trace!("p_cfg.max_pane_x_px: {:.3},   p_cfg.max_pane_y_px: {:.3}", p_cfg.max_pane_x_px, p_cfg.max_pane_y_px );
+    log::trace!("imgtile_wid_px: {:.3}, imgtile_hgt_px: {:.3}", imgtile_wid_px, imgtile_hgt_px );
+    log::trace!("pdftile_wid_mm: {:.3}, pdftile_hgt_mm: {:.3}", pdftile_wid_mm, pdftile_hgt_mm );
+    log::trace!("scale_factor_wid: {:.3}, scale_factor_hgt: {:.3}", scale_factor_wid, scale_factor_hgt );
 
-    let grid_origin_x_mm :f64 = page_margin_hor_mm;  // PDF Origin point (lower left corner of grid)
-    let grid_origin_y_mm :f64 = page_margin_ver_mm;
+    // PDF Origin point (lower left corner of grid), grid sits at the top left of the content box
+    let grid_origin_x_mm :f64 = page.margin_left_mm;
+    let grid_origin_y_mm :f64 = page.content_top_mm() - grid_tile_rows * pdftile_hgt_mm;
 
     let outline_color = Color::Rgb(Rgb::new(0.5, 0.5, 0.5, None)); // gray
     current_layer.set_outline_color(outline_color);
@@ -411,190 +346,340 @@ fn construct_window_panes(current_layer: &PdfLayerReference,
         // println!("text location {:?}", number);
         // TODO Scale font to pane size and number of panes
         // TODO adjust Location of center where number displays
-        current_layer.use_text(number.2, 48.0, x_mm, y_mm, &pane_font);
+        current_layer.use_text(number.2, 48.0, x_mm, y_mm, &fonts.heading);
     }
 
-    // construct a detail summary page for each pane
-    for (pane_no, pane) in pdf_output_window.iter().enumerate() {
-            construct_pane_detail_page(pane_no + 1,
+    // with a board preset every pane is a separate board
+    let pane_label = if board.is_some() { "Board" } else { "Pane" };
+
+    // colour of every tile in the mosaic for the thumbnail on each pane page
+    let tile_grid: Vec<Vec<modtile::RGB>> = modtile::get_tile_grid(output_window);
+
+    // construct a detail summary page for each pane, the overview page is page 0.
+    // Each pane on the overview links to its detail page.
+    let pane_wid_mm = p_cfg.pane_tile_col_count as f64 * pdftile_wid_mm;
+    let pane_hgt_mm = p_cfg.pane_tile_row_count as f64 * pdftile_hgt_mm;
+    let mut pane_links: Vec<PaneLink> = Vec::new();
+    let mut next_page: usize = 1;
+    let pane_count = (p_cfg.pane_col_count as usize, p_cfg.pane_row_count as usize);
+    add_pane_pages(doc, page, pane_count, |pane_page| {
+            let pane = &pdf_output_window[pane_page.pane_no - 1];
+            pane_links.push(PaneLink {
+                page: next_page,
+                rect_mm: (grid_origin_x_mm + pane_page.pane_col as f64 * pane_wid_mm,
+                          grid_origin_y_mm + (p_cfg.pane_row_count as usize - 1 - pane_page.pane_row) as f64 * pane_hgt_mm,
+                          pane_wid_mm,
+                          pane_hgt_mm),
+            });
+            next_page += 1;
+
+            construct_pane_detail_page(&pane_page,
+                                          pane_label,
                                           &pane,
-                                          &doc,
-                                          &pane_font,
+                                          fonts,
                                           all_colors,
-                                          &tile_color_count_vec,
-                                          doc_width_mm,
-                                          doc_height_mm,
-                                          &p_cfg);
-                                      }
-
-    // // construct a final summary page listing total number of each tile color used
-    // // sorted from most used to least used
-    construct_tile_color_summary_page(&doc,
-                                  &pane_font,
-                                  all_colors,
-                                  &tile_color_count_vec,
-                                  doc_width_mm,
-                                  doc_height_mm);
+                                          symbols,
+                                          page,
+                                          &p_cfg,
+                                          &tile_grid);
+
+            // followed by the parts needed to build the board
+            if let Some(board) = board {
+                next_page += construct_board_parts_page(pane_page.pane_no,
+                                                        &pane,
+                                                        &doc,
+                                                        fonts,
+                                                        all_colors,
+                                                        board,
+                                                        page);
+            }
+    });
+
+    (pane_links, next_page)
 } // construct_window_panes
 
+// Orders the tile colour summary can be listed in, "count" is most used first
+pub const SUMMARY_ORDERS: [&str; 3] = ["count", "number", "hue"];
+
+// Names of the summary orders, used for error messages
+pub fn summary_order_names() -> Vec<&'static str> {
+    SUMMARY_ORDERS.to_vec()
+}
+
+// List every tile colour used with a swatch showing its tile symbol, name, palette number, count and share of all tiles.
+// The symbols match the pane pages whatever order the summary is listed in.
+// Three columns of entries, as many pages as needed. Returns the number of pages added.
 fn construct_tile_color_summary_page(doc: &&PdfDocumentReference,
-                                pane_font: &&IndirectFontRef,
+                                fonts: &PdfFonts,
                                 all_colors: &modtile::AllColors,
                                 tile_color_count_vec: &[(Vec<u8>, i32)],
-                                doc_width_mm: f64,
-                                doc_height_mm: f64) -> () {
-
-     println!("Construct tile color summary page");
-
-     let (page1, layer1) = doc.add_page(Mm(doc_width_mm), Mm(doc_height_mm),format!("Page {}, Layer 1", "37".to_owned()));
-     let current_layer = doc.get_page(page1).get_layer(layer1);
-
-     // draw a simple quarter arc at (0,0). Leave as a "makers mark"
-     draw_quarter_arc(&&current_layer);
-
-     // draw some cross marks to aid in element placement  comment out following line if pane detail without color fill circles
-     // draw_page_marks(&&current_layer,doc_width_mm,doc_height_mm);  // pane detail with color fill circles
-
-     let page_margin_ver_mm = 20.0; // size of top bottom margin
-     let page_margin_left_mm: f64 = 20.0; // size of left margin
-     let page_margin_right_mm: f64 = 50.0; // size of right margin
-
-     // set a black "Pane #" footer
-     let fill_color = Color::Rgb(Rgb::new(0.0, 0.0,0.0, None));
-     current_layer.set_fill_color(fill_color);
-     let pn: String = format!("Tile Colors ") ;
-     current_layer.use_text(pn, 24.0, Mm(100.0), Mm(6.0), pane_font);
+                                symbols: &TileSymbols,
+                                page: &PageLayout,
+                                order: &str) -> usize {
+
+     log::debug!("Construct tile color summary page");
+
+     // three columns of entries across the content box, 15mm per entry
+     let entry_height_mm = 15.0;
+     let col_width_mm = page.content_width_mm() / 3.0;
+     let entries_per_col = (page.content_height_mm() / entry_height_mm).floor().max(1.0) as usize;
+     let entries_per_page = 3 * entries_per_col;
+     let first_entry_y_mm = page.content_top_mm() - 6.0;
+     let page_count = tile_color_count_vec.len().div_ceil(entries_per_page).max(1);
+     let max_name_chars = ((col_width_mm - 12.0) / 2.2).floor().max(4.0) as usize;
+
+     let total_tiles: i32 = tile_color_count_vec.iter().map(|bc| bc.1).sum();
+     let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+
+     // rgb, count and palette colour of each entry in the order asked for
+     let mut entries: Vec<(modtile::RGB, i32, Option<&modtile::TileColor>)> = tile_color_count_vec.iter()
+         .map(|bc| {
+             let var_rgb = modtile::RGB(bc.0[0], bc.0[1], bc.0[2]);
+             (var_rgb, bc.1, all_colors.colors.iter().find(|tc| tc.rgb == var_rgb))
+         })
+         .collect();
+     match order {
+         "number" => entries.sort_by(|a, b| swatch::natural_cmp(a.2.map(|tc| tc.number.as_str()).unwrap_or(""),
+                                                                b.2.map(|tc| tc.number.as_str()).unwrap_or(""))),
+         "hue" => entries.sort_by(|a, b| swatch::hue_cmp(&a.0, &b.0)),
+         _ => {}
+     }
 
-     let pn: String = format!("There are {} different coloured tiles", &tile_color_count_vec.len() ) ;
-     current_layer.use_text(pn, 24.0, Mm(60.0), Mm(205.0), pane_font);
+     log::debug!("Number of colors used: {}" , tile_color_count_vec.len());
+     log::debug!("Colors ordered by {}", order);
 
-     // we want to print out detailed TileColor info (not just rgb value and count)
-     println!();
-     println!("Number of colors used: {}" , tile_color_count_vec.len());
-     println!("Colors ordered by tile count") ;
+     for (page_idx, page_entries) in entries.chunks(entries_per_page).enumerate() {
+         let (page1, layer1) = doc.add_page(Mm(page.width_mm), Mm(page.height_mm), format!("Tile Colors {}, Layer 1", page_idx + 1));
+         let current_layer = doc.get_page(page1).get_layer(layer1);
 
-     for (i,bc) in tile_color_count_vec.iter().enumerate() {
-         let var_rgb : modtile::RGB = modtile::RGB(bc.0[0],bc.0[1],bc.0[2]);
+         // draw a simple quarter arc at (0,0). Leave as a "makers mark"
+         draw_quarter_arc(&&current_layer);
 
-         let red = var_rgb.0 as f64;
-         let green = var_rgb.1 as f64;
-         let blue = var_rgb.2 as f64;
+         // set a black "Tile Colors" footer and a title
+         current_layer.set_fill_color(black.clone());
+         let pn: String = if page_count > 1 { format!("Tile Colors {} of {}", page_idx + 1, page_count) } else { "Tile Colors".to_owned() };
+         current_layer.use_text(pn, 24.0, Mm(page.width_mm / 2.0 - FOOTER_HALF_WIDTH_MM), Mm(page.footer_y_mm()), &fonts.heading);
 
-         let fill_color = Color::Rgb(Rgb::new(red/255.0, green/255.0,blue/255.0, None));
-         current_layer.set_fill_color(fill_color);
+         let pn: String = if page_idx == 0 {
+             format!("There are {} different coloured tiles", tile_color_count_vec.len())
+         } else {
+             "Tile colours (continued)".to_owned()
+         };
+         current_layer.use_text(pn, 24.0, Mm(page.margin_left_mm), Mm(page.header_y_mm()), &fonts.heading);
+
+         for (i, (var_rgb, count, tc)) in page_entries.iter().enumerate() {
+             let cur_col : usize = i / entries_per_col;
+             let col_x_mm : f64 = page.margin_left_mm + 6.0 + cur_col as f64 * col_width_mm;
+             let entry_y_mm : f64 = first_entry_y_mm - entry_height_mm * (i % entries_per_col) as f64;
+
+             // draw a circle with tile color and symbol beside Name String
+             tile_symbols::draw_symbol_circle(&current_layer, fonts, symbols, var_rgb, (col_x_mm, entry_y_mm, 6.0));
+
+             // name on the first line, palette number, count and percentage underneath
+             let (name, number) = match tc {
+                 Some(tc) => (tc.name.to_owned(), tc.number.to_owned()),
+                 None => (format!("rgb ({}, {}, {})", var_rgb.0, var_rgb.1, var_rgb.2), "-".to_owned()),
+             };
+             let name = if name.chars().count() > max_name_chars {
+                 format!("{}...", name.chars().take(max_name_chars - 3).collect::<String>())
+             } else {
+                 name
+             };
+             let percent = if total_tiles > 0 { 100.0 * *count as f64 / total_tiles as f64 } else { 0.0 };
+             let detail = format!("No. {}   {} tiles   {:.1}%", number, count, percent);
+             log::trace!("Count: {}, \t {} {}", count, number, name);
+
+             current_layer.set_fill_color(black.clone());
+             current_layer.use_text(&name, 12.0, Mm(col_x_mm + 8.0), Mm(entry_y_mm + 0.5), &fonts.regular);
+             current_layer.use_text(&detail, 9.0, Mm(col_x_mm + 8.0), Mm(entry_y_mm - 4.0), &fonts.regular);
+         }
+     }
 
-         let col_0_x :i32 = 20;
-         let col_1_x :i32 = 110;
-         let col_2_x :i32 = 195;
+     entries.len().div_ceil(entries_per_page)
+}  // construct_tile_color_summary_page
 
-         let mut cur_col : usize = 0;
-         if i <= 9
-            { cur_col = 0 }
-         else if i > 9 && i <= 19
-            { cur_col = 1 }
-         else if i > 19 && i <= 29
-            { cur_col = 2 };
+// Drop below the top of the content box of the board and piece lines under the parts list title
+// and of the first colour entry
+const PARTS_BOARD_LINE_MM: f64 = 12.0;
+const PARTS_PIECES_LINE_MM: f64 = 20.0;
+const PARTS_FIRST_ENTRY_MM: f64 = 35.0;
+
+// Construct a parts list page for a single board (pane) when building with a board preset.
+// Lists the board itself followed by the number of pieces of each colour, most used first.
+// Entries are laid out in two columns, more pages are added if the board uses a lot of colours.
+// Returns the number of pages added.
+fn construct_board_parts_page(board_no: usize,
+                              pane: &Vec<(Box2D<i32, i32>, modtile::RGB)>,
+                              doc: &PdfDocumentReference,
+                              fonts: &PdfFonts,
+                              all_colors: &modtile::AllColors,
+                              board: &BoardPreset,
+                              page: &PageLayout) -> usize {
+
+    log::debug!("Construct Board Parts List page {}", board_no);
+
+    // count the pieces of each colour on the board
+    let board_piece_vec: Vec<(modtile::RGB, i32)> = pane_color_counts(pane);
+
+    let entry_height_mm = 7.0;
+    let first_entry_y_mm = page.content_top_mm() - PARTS_FIRST_ENTRY_MM;
+    let col_width_mm = page.content_width_mm() / 2.0;
+    let entries_per_col = ((first_entry_y_mm - page.margin_bottom_mm) / entry_height_mm).floor().max(1.0) as usize;
+    let entries_per_page = 2 * entries_per_col;
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let mut current_layer: Option<PdfLayerReference> = None;
+
+    for (i, (rgb, count)) in board_piece_vec.iter().enumerate() {
+        // start a new page
+        if i % entries_per_page == 0 {
+            let (parts_page, layer) = doc.add_page(Mm(page.width_mm), Mm(page.height_mm), format!("Page {} Parts, Layer 1", board_no));
+            let layer = doc.get_page(parts_page).get_layer(layer);
+
+            // draw a simple quarter arc at (0,0). Leave as a "makers mark"
+            draw_quarter_arc(&&layer);
+
+            layer.set_fill_color(black.clone());
+            layer.use_text(format!("Board {} Parts List", board_no), 24.0, Mm(page.margin_left_mm), Mm(page.content_top_mm()), &fonts.heading);
+            layer.use_text(format!("1 x {}", board.board_part), 14.0, Mm(page.margin_left_mm), Mm(page.content_top_mm() - PARTS_BOARD_LINE_MM), &fonts.regular);
+            layer.use_text(format!("{} x {}", pane.len(), board.piece_part), 14.0, Mm(page.margin_left_mm), Mm(page.content_top_mm() - PARTS_PIECES_LINE_MM), &fonts.regular);
+            current_layer = Some(layer);
+        }
+        let layer = match &current_layer {
+            Some(layer) => layer,
+            None => break,
+        };
 
-        let col_pos_x: [i32; 3]= [20,110,195];
+        let pos = i % entries_per_page;
+        let x_mm = page.margin_left_mm + (pos / entries_per_col) as f64 * col_width_mm;
+        let y_mm = first_entry_y_mm - (pos % entries_per_col) as f64 * entry_height_mm;
+
+        // colour swatch
+        let fill_color = Color::Rgb(Rgb::new(rgb.0 as f64 / 255.0, rgb.1 as f64 / 255.0, rgb.2 as f64 / 255.0, None));
+        layer.set_fill_color(fill_color);
+        layer.set_outline_color(black.clone());
+        layer.set_outline_thickness(0.5);
+        layer.add_shape(Line {
+            points: get_points_for_rect(Mm(5.0), Mm(5.0), Mm(x_mm), Mm(y_mm)),
+            is_closed: true,
+            has_fill: true,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
 
-         // let col_0_x :f64 = 20.0;
-         // let col_1_x :f64 = 110.0;
-         // let col_2_x :f64 = 195.0;
+        let (name, number) = match all_colors.colors.iter().find(|tc| tc.rgb == *rgb) {
+            Some(tc) => (tc.name.to_owned(), tc.number.to_owned()),
+            None => (rgb.to_string(), String::new()),
+        };
+        layer.set_fill_color(black.clone());
+        layer.use_text(format!("{} x", count), 12.0, Mm(x_mm + 8.0), Mm(y_mm + 1.0), &fonts.regular);
+        layer.use_text(name, 12.0, Mm(x_mm + 25.0), Mm(y_mm + 1.0), &fonts.regular);
+        layer.use_text(number, 12.0, Mm(x_mm + 95.0), Mm(y_mm + 1.0), &fonts.regular);
+    }
 
-         // MOD magic number here
-         let display_entries_per_col = 10;
+    board_piece_vec.len().div_ceil(entries_per_page)
+} // construct_board_parts_page
 
-         for tc in &all_colors.colors {
-             if var_rgb == tc.rgb {
-                 println!("Count: {}, \t {:?}, ", bc.1,  tc );
+// Room kept to the right of the pane grid on the pane detail page for the colour legend
+const PANE_LEGEND_WIDTH_MM: f64 = 70.0;
 
-                      // draw a circle with tile color beside Name String
-                      let radius_pt: Pt = Mm(6.0).into();
+// Room kept around the pane grid for the tile rulers and the neighbouring pane numbers
+const PANE_RULER_MM: f64 = 5.0;
+const PANE_NEIGHBOUR_MM: f64 = 8.0;
 
-                      // let center_x_pt: Pt = Mm(25.0).into();
-                      let center_x_pt: Pt = Mm(col_pos_x[cur_col] as f64).into();
+// Smallest pane grid worth printing
+const PANE_MIN_GRID_MM: f64 = 10.0;
 
-                      let center_y_pt: Pt = Mm((doc_height_mm as f64 - page_margin_ver_mm as f64) - 15.0 * (i % display_entries_per_col) as f64).into();
-                      draw_circle_with_pts(&&current_layer, center_x_pt, center_y_pt, radius_pt) ;
+// Smallest content box the pane detail page fits in, the grid with its gutters and the legend, see PageLayout::from_config
+pub(crate) const PANE_PAGE_MIN_WIDTH_MM: f64 = PANE_NEIGHBOUR_MM + PANE_RULER_MM + PANE_MIN_GRID_MM + PANE_NEIGHBOUR_MM + PANE_LEGEND_WIDTH_MM;
+pub(crate) const PANE_PAGE_MIN_HEIGHT_MM: f64 = PANE_NEIGHBOUR_MM + PANE_RULER_MM + PANE_MIN_GRID_MM + PANE_NEIGHBOUR_MM;
 
-                      let tc_name :String = tc.name.to_owned();
-                      let pos = i;
-                      let pos_str = pos.to_string().to_owned();
+// Largest size of the whole mosaic thumbnail under the pane legend and the gap between them
+const THUMBNAIL_WID_MM: f64 = 50.0;
+const THUMBNAIL_HGT_MM: f64 = 40.0;
+const THUMBNAIL_GAP_MM: f64 = 8.0;
 
-                      let fill_color = Color::Rgb(Rgb::new(255.0, 255.0,255.0, None));
-                      current_layer.set_fill_color(fill_color);
-                      current_layer.use_text(&pos_str, 20.0, Mm(col_pos_x[cur_col] as f64 - 2.0), Mm((doc_height_mm as f64 - page_margin_ver_mm as f64 - 2.0) - 15.0 * (i % display_entries_per_col)  as f64), pane_font);
+// Pane legend: gap after the neighbouring pane numbers right of the grid, drop of the first entry below the
+// top of the content box, entry heights and the largest colour circle
+const PANE_LEGEND_GAP_MM: f64 = 4.0;
+const PANE_LEGEND_TOP_MM: f64 = 6.0;
+const PANE_LEGEND_ENTRY_MIN_MM: f64 = 5.0;
+const PANE_LEGEND_ENTRY_MAX_MM: f64 = 15.0;
+const PANE_LEGEND_RADIUS_MM: f64 = 6.0;
 
-                      let name_str: String = format!("{} - {} ", tc_name, bc.1) ;
-                      let fill_color = Color::Rgb(Rgb::new(0.0, 0.0,0.0, None));
-                      current_layer.set_fill_color(fill_color);
-                      current_layer.use_text(&name_str, 20.0, Mm(col_pos_x[cur_col] as f64 + 7.0), Mm((doc_height_mm as f64 - page_margin_ver_mm as f64 - 1.0) - 15.0 * (i % display_entries_per_col) as f64), pane_font);
+// About half the width of a 24pt page footer, taken off the page centre so the footer sits near the middle
+const FOOTER_HALF_WIDTH_MM: f64 = 20.0;
 
-                      // once we have the colour we can break out of loop
-                      break;
-             }
-         };
-      }
+// About half the width of a "Pane 12 above" note, taken off the middle of the grid to centre it
+const NEIGHBOUR_HALF_WIDTH_MM: f64 = 12.0;
 
-}  // construct_tile_color_summary_page
+// A page added for one window pane, panes are numbered from 1 along each row from the top left
+pub(crate) struct PanePage {
+    pub(crate) pane_no: usize,
+    pub(crate) pane_row: usize,
+    pub(crate) pane_col: usize,
+    pub(crate) layer: PdfLayerReference,
+}
 
-// Construct the detail page for each pane
-fn construct_pane_detail_page(pane_no: usize,
+// Add a page for each pane of a mosaic (pane_cols x pane_rows) in pane number order and draw it with
+// `draw_pane`. Pages `draw_pane` adds itself follow its pane's page.
+pub(crate) fn add_pane_pages<F>(doc: &PdfDocumentReference,
+                                page: &PageLayout,
+                                (pane_cols, pane_rows): (usize, usize),
+                                mut draw_pane: F) where F: FnMut(PanePage) {
+
+    for pane_index in 0..pane_cols * pane_rows {
+        let pane_no = pane_index + 1;
+        let (page1, layer1) = doc.add_page(Mm(page.width_mm), Mm(page.height_mm), format!("Page {}, Layer 1", pane_no));
+        draw_pane(PanePage {
+            pane_no,
+            pane_row: pane_index / pane_cols,
+            pane_col: pane_index % pane_cols,
+            layer: doc.get_page(page1).get_layer(layer1),
+        });
+    }
+} // add_pane_pages
+
+// Construct the detail page for each pane.
+// The pane grid has column letters and row numbers along the top and left edges and the numbers of the
+// neighbouring panes on each side. The legend on the right ends with a thumbnail of the whole mosaic
+// with this pane outlined, the footer gives the pane's row and column in the mosaic.
+fn construct_pane_detail_page(pane_page: &PanePage,
+                                  pane_label: &str,
                                   pane: &&Vec<(Box2D<i32, i32>, modtile::RGB)>,
-                                  doc: &&PdfDocumentReference,
-                                  pane_font: &&IndirectFontRef,
+                                  fonts: &PdfFonts,
                                   all_colors: &modtile::AllColors,
-                                  tile_color_count_vec: &Vec<(Vec<u8>, i32)>,
-                                  doc_width_mm: f64,
-                                  doc_height_mm: f64,
-                                  p_cfg: &PanePdfConfig) -> () {
+                                  symbols: &TileSymbols,
+                                  page: &PageLayout,
+                                  p_cfg: &PanePdfConfig,
+                                  tile_grid: &[Vec<modtile::RGB>]) -> () {
 
-    println!("Construct Pane Detail page {}", pane_no);
+    let pane_no = pane_page.pane_no;
+    log::debug!("Construct Pane Detail page {}", pane_no);
     // println!("Pane: {:?}", &pane);
 
-    let (page1, layer1) = doc.add_page(Mm(doc_width_mm), Mm(doc_height_mm),format!("Page {}, Layer 1", pane_no.to_string().to_owned()));
-    let current_layer = doc.get_page(page1).get_layer(layer1);
+    let current_layer = pane_page.layer.clone();
 
     // draw a simple quarter arc at (0,0). Leave as a "makers mark"
     draw_quarter_arc(&&current_layer);
 
     // draw some cross marks to aid in element placement  comment out following line if pane detail without color fill circles
-    draw_page_marks(&&current_layer,doc_width_mm,doc_height_mm);  // pane detail with color fill circles
-
-    let page_margin_ver_mm = 20.0; // size of top bottom margin
-    let page_margin_left_mm = 20.0; // size of left margin
-    let page_margin_right_mm = 50.0; // size of right margin
+    draw_page_marks(&&current_layer,page.width_mm,page.height_mm);  // pane detail with color fill circles
 
     let imgtile_wid_px :f64 = (p_cfg.max_pane_x_px as f64 + 1.0) / p_cfg.pane_col_count as f64 / p_cfg.pane_tile_col_count as f64;  // convert p_cfg.max_pane_x_px to 1 based instead of 0 based to calc width
     let imgtile_hgt_px :f64 = (p_cfg.max_pane_y_px as f64 + 1.0) / p_cfg.pane_row_count as f64 / p_cfg.pane_tile_row_count as f64;  // convert p_cfg.max_pane_y_px to 1 based instead of 0 based to calc height
 
-    // based on the image aspect ratio compared to pdf aspect ratio adjust the max width of output image in the pdf file
-    let image_aspect :f64 = (p_cfg.max_pane_y_px + 1) as f64 / (p_cfg.max_pane_x_px + 1) as f64; // add one as pixel dimensions are zero based.
-
-    let pdf_doc_aspect : f64 = (doc_height_mm - 2.0 * page_margin_ver_mm) / (doc_width_mm - page_margin_left_mm - page_margin_right_mm);  // adjust for horizontal and vertical page margins
-    let pdftile_wid_mm : f64;
-    let pdftile_hgt_mm : f64;
-
-    // TODO mgj 030721 There is an issue below with the following code in that the right margin is not accounted for
-    // when image aspect ratio is greater than pdf_doc aspect
-    // Test with following setting
-    // let page_margin_right_mm = 150.0; // size of right margin
-
-    // want pdf tile height and width to remain proportional to original input imagetile height and width
-    if image_aspect < pdf_doc_aspect {
-        pdftile_wid_mm = (doc_width_mm - page_margin_left_mm - page_margin_right_mm) / p_cfg.pane_tile_col_count as f64;
-        pdftile_hgt_mm = &pdftile_wid_mm * imgtile_hgt_px/imgtile_wid_px;
-        println!();
-        println!("image_aspect {:.4} < pdf_doc_aspect {:.4} -> use pdf width to limit output", image_aspect, pdf_doc_aspect);
-        println!("pdftile_wid_mm: {:.4}, pdftile_hgt_mm: {:.4}",pdftile_wid_mm, pdftile_hgt_mm);
-    } else {
-        // TODO need to somehow account for right margin not being accounted for here.
-        pdftile_hgt_mm = (doc_height_mm - (2.0 * page_margin_ver_mm)) / p_cfg.pane_tile_row_count as f64;
-        pdftile_wid_mm = &pdftile_hgt_mm * imgtile_wid_px/imgtile_hgt_px;
-        println!();
-        println!("image_aspect {:.4} => pdf_doc_aspect {:.4} -> use pdf height to limit output", image_aspect, pdf_doc_aspect);
-        println!("pdftile_wid_mm: {:.4}, pdftile_hgt_mm: {:.4}",pdftile_wid_mm, pdftile_hgt_mm);
-    }
+    // want pdf tile height and width to remain proportional to original input imagetile height and width.
+    // The pane grid fits in the content box less room on the right for the pane legend
+    // and room around the grid for the rulers and neighbouring pane numbers.
+    let left_gutter_mm = PANE_NEIGHBOUR_MM + PANE_RULER_MM;
+    let top_gutter_mm = PANE_NEIGHBOUR_MM + PANE_RULER_MM;
+    let (pdftile_wid_mm, pdftile_hgt_mm) = page_layout::fit_grid(page.content_width_mm() - PANE_LEGEND_WIDTH_MM - left_gutter_mm - PANE_NEIGHBOUR_MM,
+                                                                 page.content_height_mm() - top_gutter_mm - PANE_NEIGHBOUR_MM,
+                                                                 p_cfg.pane_tile_col_count as f64,
+                                                                 p_cfg.pane_tile_row_count as f64,
+                                                                 imgtile_hgt_px / imgtile_wid_px);
+    log::trace!("pdftile_wid_mm: {:.4}, pdftile_hgt_mm: {:.4}",pdftile_wid_mm, pdftile_hgt_mm);
 
     let pdftile_wid_pt: Pt = Mm(pdftile_wid_mm).into();
     let pdftile_hgt_pt: Pt = Mm(pdftile_hgt_mm).into();
@@ -602,14 +687,16 @@ fn construct_pane_detail_page(pane_no: usize,
     let scale_factor_wid :f64 = pdftile_wid_pt.0 / imgtile_wid_px;
     let scale_factor_hgt :f64 = pdftile_hgt_pt.0 / imgtile_hgt_px ;
 
-    println!();
-    println!("**---> p_cfg.max_pane_x_px: {:.3},   p_cfg.max_pane_y_px: {:.3}", p_cfg.max_pane_x_px, p_cfg.max_pane_y_px );
-    println!("**---> imgtile_wid_px: {:.3}, imgtile_hgt_px: {:.3}", imgtile_wid_px, imgtile_hgt_px );
-    println!("**---> pdftile_wid_mm: {:.3}, pdftile_hgt_mm: {:.3}", pdftile_wid_mm, pdftile_hgt_mm );
-    println!("**---> scale_factor_wid: {:.3}, scale_factor_hgt: {:.3}", scale_factor_wid, scale_factor_hgt );
+    log::trace!("p_cfg.max_pane_x_px: {:.3},   p_cfg.max_pane_y_px: {:.3}", p_cfg.max_pane_x_px, p_cfg.max_pane_y_px );
+    log::trace!("imgtile_wid_px: {:.3}, imgtile_hgt_px: {:.3}", imgtile_wid_px, imgtile_hgt_px );
+    log::trace!("pdftile_wid_mm: {:.3}, pdftile_hgt_mm: {:.3}", pdftile_wid_mm, pdftile_hgt_mm );
+    log::trace!("scale_factor_wid: {:.3}, scale_factor_hgt: {:.3}", scale_factor_wid, scale_factor_hgt );
 
-    let grid_origin_x_mm :f64 = page_margin_left_mm;  // Origin point (lower left corner of grid)
-    let grid_origin_y_mm :f64 = page_margin_ver_mm;
+    // Origin point (lower left corner of grid), grid sits at the top left of the content box inside the gutters
+    let grid_wid_mm :f64 = p_cfg.pane_tile_col_count as f64 * pdftile_wid_mm;
+    let grid_hgt_mm :f64 = p_cfg.pane_tile_row_count as f64 * pdftile_hgt_mm;
+    let grid_origin_x_mm :f64 = page.margin_left_mm + left_gutter_mm;
+    let grid_origin_y_mm :f64 = page.content_top_mm() - top_gutter_mm - grid_hgt_mm;
 
     let outline_color = Color::Rgb(Rgb::new(0.5, 0.5, 0.5, None)); // gray
     current_layer.set_outline_color(outline_color);
@@ -617,7 +704,7 @@ fn construct_pane_detail_page(pane_no: usize,
 
     draw_pane_circles(&pane,
                         &&current_layer,
-                        pane_font,
+                        fonts,
                         grid_origin_x_mm,
                         grid_origin_y_mm,
                         scale_factor_wid,
@@ -626,161 +713,285 @@ fn construct_pane_detail_page(pane_no: usize,
                         p_cfg.pane_tile_col_count,
                         pdftile_wid_mm,
                         pdftile_hgt_mm,
-                        all_colors,
-                        tile_color_count_vec);
+                        symbols);
+
+    let grid_box_mm = (grid_origin_x_mm, grid_origin_y_mm, grid_wid_mm, grid_hgt_mm);
+    draw_pane_rulers(&current_layer, fonts, grid_box_mm, p_cfg);
+    draw_pane_neighbours(&current_layer, fonts, pane_no, pane_label, grid_box_mm, p_cfg);
+
+    // set a black "Pane #" footer with the pane's place in the mosaic
+    let (pane_row, pane_col) = (pane_page.pane_row, pane_page.pane_col);
+    current_layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    let pn: String = format!("{} {}   R{} C{}", pane_label, pane_no, pane_row + 1, pane_col + 1);
+    current_layer.use_text(pn, 24.0, Mm(page.width_mm / 2.0 - FOOTER_HALF_WIDTH_MM), Mm(page.footer_y_mm()), &fonts.heading);
+
+    // colour legend right of the grid with a thumbnail of the whole mosaic at the bottom of the legend column,
+    // the thumbnail is left out when the legend needs the room
+    let legend_x_mm = grid_origin_x_mm + grid_wid_mm + PANE_NEIGHBOUR_MM + PANE_LEGEND_GAP_MM;
+    let legend_wid_mm = page.width_mm - page.margin_right_mm - legend_x_mm;
+    let legend_top_mm = page.content_top_mm() - PANE_LEGEND_TOP_MM;
+    let grid_rows = tile_grid.len() as f64;
+    let grid_cols = tile_grid.first().map(|r| r.len()).unwrap_or(0) as f64;
+    let (thumb_tile_wid_mm, thumb_tile_hgt_mm) = page_layout::fit_grid(THUMBNAIL_WID_MM.min(legend_wid_mm),
+                                                                       THUMBNAIL_HGT_MM,
+                                                                       grid_cols,
+                                                                       grid_rows,
+                                                                       pdftile_hgt_mm / pdftile_wid_mm);
+    let thumb_top_mm = page.margin_bottom_mm + thumb_tile_hgt_mm * grid_rows + THUMBNAIL_GAP_MM;
+    let legend_hgt_mm = pane_color_counts(pane).len() as f64 * PANE_LEGEND_ENTRY_MIN_MM;
+    let legend_bottom_mm = if legend_top_mm - thumb_top_mm >= legend_hgt_mm {
+        draw_mosaic_thumbnail(&current_layer,
+                              tile_grid,
+                              (pane_row, pane_col),
+                              p_cfg,
+                              (legend_x_mm, page.margin_bottom_mm, thumb_tile_wid_mm, thumb_tile_hgt_mm));
+        thumb_top_mm
+    } else {
+        page.margin_bottom_mm
+    };
 
     draw_pane_legend(pane,
                      pane_no,
                      &current_layer,
-                     pane_font,
-                     doc_width_mm,
-                     doc_height_mm,
-                     page_margin_ver_mm,
-                     page_margin_left_mm,
-                     page_margin_right_mm,
+                     fonts,
+                     page,
+                     (legend_x_mm, legend_bottom_mm, legend_wid_mm, legend_top_mm - legend_bottom_mm),
                      all_colors,
-                     tile_color_count_vec
+                     symbols
                     );
 
 } // construct_pane_detail_page
 
+// Column letters across the top of the pane grid and row numbers down the left side
+// grid_box_mm is the grid's lower left corner, width and height
+fn draw_pane_rulers(current_layer: &PdfLayerReference,
+                    fonts: &PdfFonts,
+                    (grid_x_mm, grid_y_mm, grid_wid_mm, grid_hgt_mm): (f64, f64, f64, f64),
+                    p_cfg: &PanePdfConfig) {
+
+    let cols = p_cfg.pane_tile_col_count as usize;
+    let rows = p_cfg.pane_tile_row_count as usize;
+    let tile_wid_mm = grid_wid_mm / cols as f64;
+    let tile_hgt_mm = grid_hgt_mm / rows as f64;
+    let mm_per_pt = 25.4 / 72.0;
+    let font_size: f64 = (tile_wid_mm.min(tile_hgt_mm).min(PANE_RULER_MM) * 0.8 / mm_per_pt).min(10.0);
+
+    current_layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    for col in 0..cols {
+        let letters = tile_symbols::letter_symbol(col);
+        let x_mm = grid_x_mm + (col as f64 + 0.5) * tile_wid_mm - letters.len() as f64 * 0.3 * font_size * mm_per_pt;
+        current_layer.use_text(letters, font_size, Mm(x_mm), Mm(grid_y_mm + grid_hgt_mm + 1.5), &fonts.regular);
+    }
+    for row in 0..rows {
+        let number = (row + 1).to_string();
+        let x_mm = grid_x_mm - 1.5 - number.len() as f64 * 0.55 * font_size * mm_per_pt;
+        let y_mm = grid_y_mm + grid_hgt_mm - (row as f64 + 0.5) * tile_hgt_mm - 0.35 * font_size * mm_per_pt;
+        current_layer.use_text(number, font_size, Mm(x_mm), Mm(y_mm), &fonts.regular);
+    }
+} // draw_pane_rulers
+
+// Numbers of the panes touching each edge of this pane, written just outside the rulers
+fn draw_pane_neighbours(current_layer: &PdfLayerReference,
+                        fonts: &PdfFonts,
+                        pane_no: usize,
+                        pane_label: &str,
+                        (grid_x_mm, grid_y_mm, grid_wid_mm, grid_hgt_mm): (f64, f64, f64, f64),
+                        p_cfg: &PanePdfConfig) {
+
+    let pane_cols = p_cfg.pane_col_count as usize;
+    let pane_rows = p_cfg.pane_row_count as usize;
+    let row = (pane_no - 1) / pane_cols;
+    let col = (pane_no - 1) % pane_cols;
+    let font_size = 10.0;
+    let mid_x_mm = grid_x_mm + grid_wid_mm / 2.0 - NEIGHBOUR_HALF_WIDTH_MM;
+    let mid_y_mm = grid_y_mm + grid_hgt_mm / 2.0;
+
+    current_layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    if row > 0 {
+        let text = format!("{} {} above", pane_label, pane_no - pane_cols);
+        current_layer.use_text(text, font_size, Mm(mid_x_mm), Mm(grid_y_mm + grid_hgt_mm + PANE_RULER_MM + 2.0), &fonts.regular);
+    }
+    if row + 1 < pane_rows {
+        let text = format!("{} {} below", pane_label, pane_no + pane_cols);
+        current_layer.use_text(text, font_size, Mm(mid_x_mm), Mm(grid_y_mm - PANE_NEIGHBOUR_MM + 3.0), &fonts.regular);
+    }
+    if col > 0 {
+        let text = format!("< {}", pane_no - 1);
+        current_layer.use_text(text, font_size, Mm(grid_x_mm - PANE_RULER_MM - PANE_NEIGHBOUR_MM), Mm(mid_y_mm), &fonts.regular);
+    }
+    if col + 1 < pane_cols {
+        let text = format!("{} >", pane_no + 1);
+        current_layer.use_text(text, font_size, Mm(grid_x_mm + grid_wid_mm + 1.0), Mm(mid_y_mm), &fonts.regular);
+    }
+} // draw_pane_neighbours
+
+// Small picture of the whole mosaic with the pane lines drawn in and the current pane outlined.
+// Big mosaics are sampled so at most about 40 cells are drawn across or down.
+// thumb_mm is the lower left corner and the size of one mosaic tile in the thumbnail.
+fn draw_mosaic_thumbnail(current_layer: &PdfLayerReference,
+                         tile_grid: &[Vec<modtile::RGB>],
+                         (pane_row, pane_col): (usize, usize),
+                         p_cfg: &PanePdfConfig,
+                         (thumb_x_mm, thumb_y_mm, tile_wid_mm, tile_hgt_mm): (f64, f64, f64, f64)) {
+
+    let rows = tile_grid.len();
+    let cols = tile_grid.first().map(|r| r.len()).unwrap_or(0);
+    if rows == 0 || cols == 0 {
+        return;
+    }
+    let step = rows.max(cols).div_ceil(40);
+    let top_mm = thumb_y_mm + rows as f64 * tile_hgt_mm;
+
+    // sampled cells of the same colour next to each other in a row are drawn as one rectangle
+    for row in (0..rows).step_by(step) {
+        let cell_rows = step.min(rows - row) as f64;
+        let sampled: Vec<usize> = (0..cols).step_by(step).collect();
+        let mut run_start = 0;
+        for (i, col) in sampled.iter().enumerate() {
+            let rgb = tile_grid[row][*col];
+            if sampled.get(i + 1).map(|next| tile_grid[row][*next] == rgb).unwrap_or(false) {
+                continue;
+            }
+            let start_col = sampled[run_start];
+            let run_cols = (col + step).min(cols) - start_col;
+            run_start = i + 1;
+
+            current_layer.set_fill_color(Color::Rgb(Rgb::new(rgb.0 as f64 / 255.0, rgb.1 as f64 / 255.0, rgb.2 as f64 / 255.0, None)));
+            current_layer.add_shape(Line {
+                points: get_points_for_rect(Mm(run_cols as f64 * tile_wid_mm),
+                                            Mm(cell_rows * tile_hgt_mm),
+                                            Mm(thumb_x_mm + start_col as f64 * tile_wid_mm),
+                                            Mm(top_mm - row as f64 * tile_hgt_mm - cell_rows * tile_hgt_mm)),
+                is_closed: true,
+                has_fill: true,
+                has_stroke: false,
+                is_clipping_path: false,
+            });
+        }
+    }
+
+    // pane lines in grey then the current pane in a thick black outline
+    let pane_wid_mm = p_cfg.pane_tile_col_count as f64 * tile_wid_mm;
+    let pane_hgt_mm = p_cfg.pane_tile_row_count as f64 * tile_hgt_mm;
+    let pane_outline = |row: usize, col: usize| Line {
+        points: get_points_for_rect(Mm(pane_wid_mm),
+                                    Mm(pane_hgt_mm),
+                                    Mm(thumb_x_mm + col as f64 * pane_wid_mm),
+                                    Mm(top_mm - (row + 1) as f64 * pane_hgt_mm)),
+        is_closed: true,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    };
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.5, 0.5, 0.5, None)));
+    current_layer.set_outline_thickness(0.3);
+    for row in 0..p_cfg.pane_row_count as usize {
+        for col in 0..p_cfg.pane_col_count as usize {
+            current_layer.add_shape(pane_outline(row, col));
+        }
+    }
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    current_layer.set_outline_thickness(2.0);
+    current_layer.add_shape(pane_outline(pane_row, pane_col));
+} // draw_mosaic_thumbnail
+
 // Create legend of each color used in pane on RHS of pane detail page
+// legend_x_mm is the left edge of the legend, just right of the pane grid
 fn draw_pane_legend(pane: &&Vec<(Box2D<i32, i32>, modtile::RGB)>,
                     pane_no : usize,
                     current_layer: &PdfLayerReference,
-                    pane_font: &&IndirectFontRef,
-                    _doc_width_mm: f64,
-                    doc_height_mm: f64,
-                    page_margin_ver_mm: f64,
-                    _page_margin_left_mm: f64,
-                    _page_margin_right_mm: f64,
+                    fonts: &PdfFonts,
+                    page: &PageLayout,
+                    (legend_x_mm, legend_y_mm, legend_wid_mm, legend_hgt_mm): (f64, f64, f64, f64),
                     all_colors: &modtile::AllColors,
-                    tile_color_count_vec: &Vec<(Vec<u8>, i32)>,) -> () {
+                    symbols: &TileSymbols,) -> () {
 
     // create list of unique colors ordered by number of times used in the pane
-    let mut pane_tile_colours: HashMap<modtile::RGB, i32> = HashMap::new();
-    for (_i, tile) in pane.iter().enumerate() {
-        let tile_rgb = tile.1;
-        *pane_tile_colours.entry(tile_rgb).or_insert(0) += 1;
-    }
-
-    println!("draw_pane_legend for page {} " , pane_no);
-    println!("There are {} different colors " , &pane_tile_colours.len());
+    let pane_colour_vec: Vec<(modtile::RGB, i32)> = pane_color_counts(pane);
 
-    // Sort the pane colours by the number of times used in pane
-    let mut pane_colour_vec: Vec<(&modtile::RGB, &i32)> = pane_tile_colours.iter().collect();
-    pane_colour_vec.sort_by(|a, b| b.1.cmp(a.1));
+    log::trace!("draw_pane_legend for page {} " , pane_no);
+    log::trace!("There are {} different colors " , &pane_colour_vec.len());
 
-    // set a black "Pane #" footer
     let fill_color = Color::Rgb(Rgb::new(0.0, 0.0,0.0, None));
     current_layer.set_fill_color(fill_color);
-    let pn: String = format!("Pane {}", pane_no) ;
-    current_layer.use_text(pn, 24.0, Mm(100.0), Mm(6.0), pane_font);
-
-    let pn: String = format!("There are {} different coloured tiles", &pane_tile_colours.len() ) ;
-    current_layer.use_text(pn, 24.0, Mm(60.0), Mm(205.0), pane_font);
-
-    // split tile_color_count_vec into two separate vecs
-    let (tile_colors, _count) : (Vec<&Vec<u8>>,Vec<&i32>) = tile_color_count_vec.iter().map(|&(ref a, ref b)| (a, b)).unzip();
+    let pn: String = format!("There are {} different coloured tiles", &pane_colour_vec.len() ) ;
+    current_layer.use_text(pn, 24.0, Mm(page.margin_left_mm), Mm(page.header_y_mm()), &fonts.heading);
+
+    // entries fill the legend box (lower left corner, width and height) column by column
+    let (col_count, rows, entry_hgt_mm) = legend_columns(pane_colour_vec.len(), legend_hgt_mm);
+    let col_wid_mm = legend_wid_mm / col_count as f64;
+    let legend_top_mm = legend_y_mm + legend_hgt_mm;
+    let radius_mm = (entry_hgt_mm * 0.4).min(PANE_LEGEND_RADIUS_MM);
+    let font_size = (entry_hgt_mm * 1.33).min(20.0);
+    // names are cut short to their column, glyphs are roughly half the font size wide (1 mm is 2.835 pt)
+    let text_offset_mm = PANE_LEGEND_RADIUS_MM + 1.0 + radius_mm;
+    let max_name_chars = ((col_wid_mm - text_offset_mm) / (font_size * 0.5 / 2.835)).floor().max(4.0) as usize;
 
     // loop through all the colors and print to legend
     for (i,tile_rgb) in pane_colour_vec.iter().enumerate() {
 
-        let red = tile_rgb.0.0 as f64;
-        let green = tile_rgb.0.1 as f64;
-        let blue = tile_rgb.0.2 as f64;
-
-        let fill_color = Color::Rgb(Rgb::new(red/255.0, green/255.0,blue/255.0, None));
-        current_layer.set_fill_color(fill_color);
-
         // grab the Color Name from all_colors
         for tc in &all_colors.colors {
-            if *tile_rgb.0 == tc.rgb {
-
-                // draw a circle with tile color beside Name String
-                let radius_pt: Pt = Mm(6.0).into();
+            if tile_rgb.0 == tc.rgb {
 
-                let center_x_pt: Pt = Mm(205.0).into();
-                let center_y_pt: Pt = Mm((doc_height_mm as f64 - page_margin_ver_mm as f64) - 15.0 * i as f64).into();
-                draw_circle_with_pts(&current_layer, center_x_pt, center_y_pt, radius_pt) ;
+                // draw a circle with tile color and symbol beside Name String
+                let entry_x_mm = legend_x_mm + (i / rows) as f64 * col_wid_mm;
+                let entry_y_mm = legend_top_mm - entry_hgt_mm * (i % rows) as f64;
+                tile_symbols::draw_symbol_circle(current_layer, fonts, symbols, &tile_rgb.0, (entry_x_mm + PANE_LEGEND_RADIUS_MM, entry_y_mm, radius_mm));
 
                 let tc_name :String = tc.name.to_owned();
                 // println!("---> Count: {}, \t {:?}, ", *tile_rgb.1,  tc_name );
 
-                let t : Vec<u8> =  vec![tile_rgb.0.0, tile_rgb.0.1 , tile_rgb.0.2 ];
-                let pos = &tile_colors.iter().position(|rgb| **rgb == t );
-
-                let pos_str: String = format!("{}", pos.unwrap().to_string()) ;
-                let fill_color = Color::Rgb(Rgb::new(255.0, 255.0,255.0, None));
-                current_layer.set_fill_color(fill_color);
-                current_layer.use_text(pos_str, 20.0, Mm(203.0), Mm((doc_height_mm as f64 - page_margin_ver_mm as f64 - 2.0) - 15.0 * i as f64), pane_font);
-
-                let name_str: String = format!("{} - {}", tc_name, *tile_rgb.1) ;
+                let count_str: String = format!(" - {}", tile_rgb.1);
+                let name_chars = max_name_chars.saturating_sub(count_str.len()).max(4);
+                let tc_name: String = if tc_name.chars().count() > name_chars {
+                    format!("{}...", tc_name.chars().take(name_chars - 3).collect::<String>())
+                } else {
+                    tc_name
+                };
+                let name_str: String = format!("{}{}", tc_name, count_str) ;
                 let fill_color = Color::Rgb(Rgb::new(0.0, 0.0,0.0, None));
                 current_layer.set_fill_color(fill_color);
-                current_layer.use_text(name_str, 20.0, Mm(212.0), Mm((doc_height_mm as f64 - page_margin_ver_mm as f64 - 1.0) - 15.0 * i as f64), pane_font);
+                current_layer.use_text(name_str, font_size, Mm(entry_x_mm + text_offset_mm), Mm(entry_y_mm - font_size * 0.05), &fonts.regular);
             }
         };
     }
 } // draw_pane_legend
 
-fn draw_page_marks(current_layer: &&PdfLayerReference, doc_width_as_mm: f64, doc_height_as_mm: f64) -> () {
+// Columns, entries per column and entry height in mm for a legend of `entry_count` entries in a box
+// `box_hgt_mm` high. Entries close up to fit, when even the smallest entries do not fit they run on
+// in more columns side by side.
+fn legend_columns(entry_count: usize, box_hgt_mm: f64) -> (usize, usize, f64) {
+    let entry_count = entry_count.max(1);
+    let entries_per_col = ((box_hgt_mm / PANE_LEGEND_ENTRY_MIN_MM).floor() as usize).max(1);
+    let col_count = entry_count.div_ceil(entries_per_col);
+    let rows = entry_count.div_ceil(col_count);
+    (col_count, rows, (box_hgt_mm / rows as f64).clamp(PANE_LEGEND_ENTRY_MIN_MM, PANE_LEGEND_ENTRY_MAX_MM))
+}
 
-    current_layer.set_outline_thickness(0.5);
+// Number of tiles of each colour in a pane, most used first.
+// Used by the pane legend, the board parts list and the checklist pick lists.
+pub(crate) fn pane_color_counts(pane: &[(Box2D<i32, i32>, modtile::RGB)]) -> Vec<(modtile::RGB, i32)> {
+    let mut pane_tile_colours: HashMap<modtile::RGB, i32> = HashMap::new();
+    for tile in pane.iter() {
+        *pane_tile_colours.entry(tile.1).or_insert(0) += 1;
+    }
+    let mut pane_colour_vec: Vec<(modtile::RGB, i32)> = pane_tile_colours.into_iter().collect();
+    pane_colour_vec.sort_by(|a, b| b.1.cmp(&a.1));
+    pane_colour_vec
+}
 
-    // A cross is made up from 1 horizontal line and 1 vertical line (8 points)
-    let hor_min_x: Pt = Mm(-2.5).into();
-    let hor_min_y: Pt = Mm(0.0).into();
-    let hor_max_x: Pt = Mm(2.5).into();
-    let hor_max_y: Pt = Mm(0.0).into();
+fn draw_page_marks(current_layer: &&PdfLayerReference, doc_width_as_mm: f64, doc_height_as_mm: f64) -> () {
 
-    let ver_min_x: Pt = Mm(0.0).into();
-    let ver_min_y: Pt = Mm(-2.5).into();
-    let ver_max_x: Pt = Mm(0.0).into();
-    let ver_max_y: Pt = Mm(2.5).into();
+    current_layer.set_outline_thickness(0.5);
 
     // draw multiple crosses vertically and horizontally spaced across the page at 10 mm invervals
     let step = 10;  // mm
-    let step_pt: Pt = Mm(10.0).into();
     let mut rcount: f64 = 0.0 ;
     for _row in (0..=doc_height_as_mm as i32).step_by(step){
         let mut ccount: f64 = 0.0;
         for _col in (0..=doc_width_as_mm as i32).step_by(step){
-        // println!("Did we get here?{:?} {:?} {:?} {:?}", row, rcount, col, ccount);
-
-            let hmin_x: Pt = Pt(hor_min_x.0 + ccount * step_pt.0); // Mm(-2.5).into();
-            let hmin_y: Pt = Pt(hor_min_y.0 + rcount * step_pt.0); // Mm(0.0).into();
-            let hmax_x: Pt = Pt(hor_max_x.0 + ccount * step_pt.0); // Mm(2.5).into();
-            let hmax_y: Pt = Pt(hor_max_y.0 + rcount * step_pt.0); // Mm(0.0).into();
-
-            let vmin_x: Pt = Pt(ver_min_x.0 + ccount * step_pt.0); // Mm(0.0).into();
-            let vmin_y: Pt = Pt(ver_min_y.0 + rcount * step_pt.0); // Mm(-2.5).into();
-            let vmax_x: Pt = Pt(ver_max_x.0 + ccount * step_pt.0); // Mm(0.0).into();
-            let vmax_y: Pt = Pt(ver_max_y.0 + rcount * step_pt.0); // Mm(2.5).into();
-
-            let hmin = Point { x: hmin_x,  y: hmin_y };
-            let hmax = Point { x: hmax_x,  y: hmax_y};
-            let hline_pts = vec![(hmin, false),(hmax, false)];
-            let hline_line = Line {
-                points: hline_pts,
-                is_closed: false,
-                has_fill: false,
-                has_stroke: true,
-                is_clipping_path: false,
-            };
-            current_layer.add_shape(hline_line);
-
-            let vmin = Point { x: vmin_x,  y: vmin_y };
-            let vmax = Point { x: vmax_x,  y: vmax_y};
-            let vline_pts = vec![(vmin, false),(vmax, false)];
-            let vline_line = Line {
-                points: vline_pts,
-                is_closed: false,
-                has_fill: false,
-                has_stroke: true,
-                is_clipping_path: false,
-            };
-            current_layer.add_shape(vline_line);
+            draw_cross(current_layer, Mm(ccount * step as f64).into(), Mm(rcount * step as f64).into(), Mm(2.5).into());
             ccount += 1.0;
         }
         rcount += 1.0;
@@ -788,6 +999,45 @@ fn draw_page_marks(current_layer: &&PdfLayerReference, doc_width_as_mm: f64, doc
 
 } // draw_page_marks
 
+// Draw registration marks, a cross inside a circle, centered on each (x, y) mm location.
+// Used to line up neighbouring sheets of a tiled poster so the marks must be drawn at the same
+// place relative to the mosaic on every sheet that shares them.
+pub(crate) fn draw_registration_marks(current_layer: &&PdfLayerReference, marks_mm: &[(f64, f64)]) {
+
+    let outline_color = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)); // black
+    current_layer.set_outline_color(outline_color);
+    current_layer.set_outline_thickness(0.5);
+
+    for (x_mm, y_mm) in marks_mm {
+        let x_pt: Pt = Mm(*x_mm).into();
+        let y_pt: Pt = Mm(*y_mm).into();
+        draw_cross(current_layer, x_pt, y_pt, Mm(6.0).into());
+        draw_circle_with_pts_no_fill(current_layer, x_pt, y_pt, Mm(3.5).into());
+    }
+} // draw_registration_marks
+
+// A cross is made up from 1 horizontal line and 1 vertical line centered on (x_pt, y_pt)
+fn draw_cross(current_layer: &&PdfLayerReference, x_pt: Pt, y_pt: Pt, arm_pt: Pt) {
+
+    let hline_line = Line {
+        points: get_points_for_line(Pt(x_pt.0 - arm_pt.0), y_pt, Pt(x_pt.0 + arm_pt.0), y_pt),
+        is_closed: false,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    };
+    current_layer.add_shape(hline_line);
+
+    let vline_line = Line {
+        points: get_points_for_line(x_pt, Pt(y_pt.0 - arm_pt.0), x_pt, Pt(y_pt.0 + arm_pt.0)),
+        is_closed: false,
+        has_fill: false,
+        has_stroke: true,
+        is_clipping_path: false,
+    };
+    current_layer.add_shape(vline_line);
+} // draw_cross
+
 fn draw_tiles(pdf_output_window: &Vec<Vec<(Box2D<i32, i32>,
                                modtile::RGB)>>,
                                current_layer: &&PdfLayerReference,
@@ -847,7 +1097,7 @@ fn draw_tiles(pdf_output_window: &Vec<Vec<(Box2D<i32, i32>,
 // Copy of draw_summary_circles using scale scale_factor_wid
 fn draw_pane_circles(pdf_output_pane: &Vec<(Box2D<i32, i32>, modtile::RGB)>,
                         current_layer: &&PdfLayerReference,
-                        pane_font: &&IndirectFontRef,
+                        fonts: &PdfFonts,
                         grid_origin_x_mm: f64,
                         grid_origin_y_mm: f64,
                         scale_factor_wid: f64,
@@ -856,42 +1106,26 @@ fn draw_pane_circles(pdf_output_pane: &Vec<(Box2D<i32, i32>, modtile::RGB)>,
                         pane_tile_col_count: i32,
                         pdftile_wid_mm: f64,
                         pdftile_hgt_mm: f64,
-                        all_colors: &modtile::AllColors,
-                        tile_color_count_vec: &Vec<(Vec<u8>,i32)>,) -> () {
+                        symbols: &TileSymbols,) -> () {
 
     // convert to Pt for strong typing
     let grid_origin_x_pt: Pt = Mm(grid_origin_x_mm).into();
     let grid_origin_y_pt: Pt = Mm(grid_origin_y_mm).into();
 
-    let outline_color = Color::Rgb(Rgb::new(0.5, 0.5, 0.5, None)); // gray
-    current_layer.set_outline_color(outline_color);
-
     // grab lower left tile
     let origin_tile = pdf_output_pane[ ((pane_tile_row_count - 1) * pane_tile_col_count) as usize ].0 ;
-    println!("***\n***\n*** -> Origin Tile = {:?}", origin_tile);
+    log::trace!("Origin Tile = {:?}", origin_tile);
 
     // moving all tiles to lower left corner (0,0) of PDF page is done by
     //  simply subtracting the min x,y value of the "Origin Pane" from all the tile x,y values
     let x_transpose: i32 = origin_tile.min.x;
     let y_transpose: i32 = origin_tile.min.y;
 
-    // split vec into two separate vecs
-    let (tile_colors, _count) : (Vec<&Vec<u8>>,Vec<&i32>) = tile_color_count_vec.iter().map(|&(ref a, ref b)| (a, b)).unzip();
-    // println!("---> tile_colors {:?}", &tile_colors);
-    // println!("---> count {:?}", count);
-
     for (_i, tile) in pdf_output_pane.iter().enumerate() {
 
             let tile_box = tile.0;
             let tile_rgb = tile.1;
 
-            let red = tile_rgb.0 as f64;
-            let green = tile_rgb.1 as f64;
-            let blue = tile_rgb.2 as f64;
-
-            let fill_color = Color::Rgb(Rgb::new(red/255.0, green/255.0,blue/255.0, None));
-            current_layer.set_fill_color(fill_color);
-
             let radius_pt: Pt;
             if pdftile_wid_mm < pdftile_hgt_mm {
                 radius_pt = Mm(pdftile_wid_mm / 2.0).into();
@@ -901,31 +1135,13 @@ fn draw_pane_circles(pdf_output_pane: &Vec<(Box2D<i32, i32>, modtile::RGB)>,
 
             let center_x_pt: Pt = Pt((tile_box.center().x - x_transpose) as f64 * scale_factor_wid + grid_origin_x_pt.0);
             let center_y_pt: Pt = Pt((tile_box.center().y - y_transpose) as f64 * scale_factor_hgt + grid_origin_y_pt.0);
-            draw_circle_with_pts(&current_layer, center_x_pt, center_y_pt, radius_pt) ;             // pane detail with color fill circles
-            // draw_circle_with_pts_no_fill(&current_layer, center_x_pt, center_y_pt, radius_pt) ;  // pane detail without color fill circles
 
-            // Draw text with number inside circle
-            // grab the Color Number vector of tile colors used to create mosaic
-            let t : Vec<u8> =  vec![tile_rgb.0, tile_rgb.1 , tile_rgb.2 ];
-            let pos = &tile_colors.iter().position(|rgb| **rgb == t );
-
-            for tc in &all_colors.colors {
-                if tile_rgb == tc.rgb {
-                    // println!("--->pos {:?} Name {}", pos, tc.name.to_owned());
-                    let tile_no: String = format!("{}", pos.unwrap().to_string()) ;
-                    let fill_color = Color::Rgb(Rgb::new(255.0, 255.0,255.0, None));   // pane detail with color fill circles
-                    // let fill_color = Color::Rgb(Rgb::new(0.0, 0.0,0.0, None));      // pane detail without color fill circles
-
-                    current_layer.set_fill_color(fill_color);
-                    let mut offset_center_x_mm : Mm = center_x_pt.into();
-                    offset_center_x_mm = offset_center_x_mm - Mm(2.0);
-                    let mut offset_center_y_mm : Mm = center_y_pt.into();
-                    offset_center_y_mm = offset_center_y_mm - Mm(2.0);
-
-                    // current_layer.use_text(tile_no, 20.0, center_x_pt.into() , center_y_pt.into(), pane_font);
-                    current_layer.use_text(tile_no, 20.0, offset_center_x_mm , offset_center_y_mm, pane_font);
-                }
-            };
+            // pane detail with color fill circles and the tile symbol inside
+            let center_x_mm: Mm = center_x_pt.into();
+            let center_y_mm: Mm = center_y_pt.into();
+            let radius_mm: Mm = radius_pt.into();
+            tile_symbols::draw_symbol_circle(current_layer, fonts, symbols, &tile_rgb, (center_x_mm.0, center_y_mm.0, radius_mm.0));
+            // draw_circle_with_pts_no_fill(&current_layer, center_x_pt, center_y_pt, radius_pt) ;  // pane detail without color fill circles
 
             // // Debug stuff
             // if i < 10
@@ -1027,12 +1243,12 @@ fn draw_diag(current_layer: &&PdfLayerReference) {
     let y1:Pt = Mm(13.25).into();
     let y2:Pt = Mm(3.0*13.25).into();
 
-    println!("Pt (x1,y1):({:.2?},{:.2?})", x1,y1);
-    println!("Pt (x2,y1):({:.2?},{:.2?})", x2,y1);
-    println!("Pt (x3,y1):({:.2?},{:.2?})", x3,y1);
-    println!("Pt (x1,y2):({:.2?},{:.2?})", x1,y2);
-    println!("Pt (x2,y2):({:.2?},{:.2?})", x2,y2);
-    println!("Pt (x3,y2):({:.2?},{:.2?})", x3,y2);
+    log::trace!("Pt (x1,y1):({:.2?},{:.2?})", x1,y1);
+    log::trace!("Pt (x2,y1):({:.2?},{:.2?})", x2,y1);
+    log::trace!("Pt (x3,y1):({:.2?},{:.2?})", x3,y1);
+    log::trace!("Pt (x1,y2):({:.2?},{:.2?})", x1,y2);
+    log::trace!("Pt (x2,y2):({:.2?},{:.2?})", x2,y2);
+    log::trace!("Pt (x3,y2):({:.2?},{:.2?})", x3,y2);
 
     draw_circle_with_pts(&current_layer, x1, y1, radi) ;
     draw_circle_with_pts(&current_layer, x2, y1, radi) ;
@@ -1047,7 +1263,7 @@ fn draw_diag(current_layer: &&PdfLayerReference) {
 
 // Convert all the Box2D coords from image coord space into PDF coord space.
 // see get_pane_pdf_coords() below for explanation of how this code works
-fn get_pdf_coords(output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>, max_y: i32) -> Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>> {
+pub(crate) fn get_pdf_coords(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>], max_y: i32) -> Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>> {
     // construct array to let us get equivalent y PDF coord from Image Coord
     let range = 0..=max_y;
     let mut img_y_to_pdf: Vec<i32> = Vec::new();
@@ -1086,7 +1302,7 @@ fn get_pdf_coords(output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>, max
 // adust the Box2D min max values accordingly
 // Get the PX cooridinates of each window pane.
 // esentially constructing a Box2D using first tile min loc and last tile max location.
-fn get_pane_pdf_coords(output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>) -> PanePdfConfig {
+pub(crate) fn get_pane_pdf_coords(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>]) -> PanePdfConfig {
 
     // grab the max x y dimensions
     let mut win_max_x : i32 = 0;
@@ -1109,8 +1325,7 @@ fn get_pane_pdf_coords(output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>
         }
     }
 
-    println!();
-    println!("get_pane_pdf_coords- Each pane is {} row(s) by {} col(s) of tiles", &tile_row_count, &tile_col_count);
+    log::debug!("get_pane_pdf_coords- Each pane is {} row(s) by {} col(s) of tiles", &tile_row_count, &tile_col_count);
 
     // construct array to let us get PDF Y coord from Image Y Coord
     let range = 0..=win_max_y;
@@ -1196,12 +1411,10 @@ fn get_pane_pdf_coords(output_window: &Vec<Vec<(Box2D<i32, i32>, modtile::RGB)>>
     // number of cols corresponds to number of discrete x coords values
     let win_pane_row_count :i32 = pane_y_coords.len() as i32 / 2 ;
     let win_pane_col_count :i32 = pane_x_coords.len() as i32 / 2 ;
-    println!();
-    println!{"width pane coords {:?}", &pane_x_coords}; //  width pane coords {0: 4, 99: 4, 100: 4, 199: 4}
-    println!{"height pane coords {:?}", &pane_y_coords} // height pane coords {0: 4, 99: 4, 100: 4, 199: 4}
-    println!();
-    println!("window pane row count: {:?}", &win_pane_row_count);  // pane row count is 1 correct
-    println!("window pane col count: {:?}", &win_pane_col_count);  // pane col count is 3 incorrect... should be something is wrong here
+    log::trace!("width pane coords {:?}", &pane_x_coords); //  width pane coords {0: 4, 99: 4, 100: 4, 199: 4}
+    log::trace!("height pane coords {:?}", &pane_y_coords); // height pane coords {0: 4, 99: 4, 100: 4, 199: 4}
+    log::trace!("window pane row count: {:?}", &win_pane_row_count);  // pane row count is 1 correct
+    log::trace!("window pane col count: {:?}", &win_pane_col_count);  // pane col count is 3 incorrect... should be something is wrong here
 
     let res : PanePdfConfig = PanePdfConfig { max_pane_x_px: win_max_x,
                                               max_pane_y_px: win_max_y,
@@ -1257,7 +1470,7 @@ fn get_pane_text_loc_px( window_panes_coords_px: &Vec<Box2D<i32, i32>>) -> Vec<(
      res
 } // get_pane_text_loc_px
 
-fn draw_circle_with_pts(current_layer: &&PdfLayerReference, offsetx_pt: Pt, offsety_pt: Pt, radius_pt: Pt) -> () {
+pub(crate) fn draw_circle_with_pts(current_layer: &&PdfLayerReference, offsetx_pt: Pt, offsety_pt: Pt, radius_pt: Pt) -> () {
 
     let circle_points = calculate_points_for_circle(radius_pt, offsetx_pt, offsety_pt);
 
@@ -1273,7 +1486,7 @@ fn draw_circle_with_pts(current_layer: &&PdfLayerReference, offsetx_pt: Pt, offs
     current_layer.add_shape(circle1);
 } // draw_circle_with_pts
 
-fn draw_circle_with_pts_no_fill(current_layer: &&PdfLayerReference, offsetx_pt: Pt, offsety_pt: Pt, radius_pt: Pt) -> () {
+pub(crate) fn draw_circle_with_pts_no_fill(current_layer: &&PdfLayerReference, offsetx_pt: Pt, offsety_pt: Pt, radius_pt: Pt) -> () {
 
     let circle_points = calculate_points_for_circle(radius_pt, offsetx_pt, offsety_pt);
 
@@ -1295,7 +1508,7 @@ fn draw_circle_with_pts_no_fill(current_layer: &&PdfLayerReference, offsetx_pt:
 // P0​=(0,1), P1​=(c,1), P2​=(1,c), P3​=(1,0) with c=0.551915024494
 // This yields an arc on the unit circle centered about the origin,
 // starting at P0​ and ending at P3, with the least amount of radial drift.
-fn draw_quarter_arc(current_layer: &&PdfLayerReference) -> () {
+pub(crate) fn draw_quarter_arc(current_layer: &&PdfLayerReference) -> () {
 
     // Quadratic shape. The "false" determines if the next (following)
     // point is a bezier handle (for curves)
@@ -1427,3 +1640,24 @@ pub fn get_points_for_line<P: Into<Pt>>(
         (end_pt, false),
     ]
 } // get_points_for_line
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legend_entries_fit_their_box() {
+        // a few colours get the largest entries
+        assert_eq!(legend_columns(3, 100.0), (1, 3, PANE_LEGEND_ENTRY_MAX_MM));
+        // more close up
+        assert_eq!(legend_columns(10, 100.0), (1, 10, 10.0));
+        // too many for one column of the smallest entries
+        assert_eq!(legend_columns(30, 100.0), (2, 15, 100.0 / 15.0));
+        assert_eq!(legend_columns(41, 100.0), (3, 14, 100.0 / 14.0));
+        for count in 1..200 {
+            let (cols, rows, entry_hgt_mm) = legend_columns(count, 60.0);
+            assert!(cols * rows >= count && rows as f64 * entry_hgt_mm <= 60.0 + 1e-9, "{} entries", count);
+        }
+        assert_eq!(legend_columns(0, 1.0), (1, 1, PANE_LEGEND_ENTRY_MIN_MM));
+    }
+}
@@ -0,0 +1,351 @@
+use printpdf::*;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use euclid::Box2D;
+
+use crate::fonts::{self, PdfFonts};
+use crate::modtile::{self, RGB};
+use crate::{page_layout, units};
+use crate::pdf_util::{draw_registration_marks, get_points_for_line, get_points_for_rect};
+
+// Unprinted border around each sheet. Page labels and neighbour names are written in here.
+const SHEET_MARGIN_MM: f64 = 12.0;
+
+// Length of the scale check bar printed on every sheet
+const SCALE_BAR_MM: f64 = 50.0;
+
+// Layout of the poster sheets over the mosaic. All values in mm, mosaic origin is the top left corner.
+struct PosterLayout {
+    mosaic_wid_mm: f64,
+    mosaic_hgt_mm: f64,
+    area_wid_mm: f64,   // part of the mosaic printed on one sheet
+    area_hgt_mm: f64,
+    step_x_mm: f64,     // distance between neighbouring sheets, sheet area less the overlap
+    step_y_mm: f64,
+    overlap_mm: f64,
+    cols: usize,
+    rows: usize,
+}
+
+impl PosterLayout {
+    // top left corner of the sheet in mosaic coordinates
+    fn sheet_origin(&self, row: usize, col: usize) -> (f64, f64) {
+        (col as f64 * self.step_x_mm, row as f64 * self.step_y_mm)
+    }
+}
+
+/// Write the mosaic at actual size as a tiled poster (`<name>_poster.pdf` next to `save_path`).
+///
+/// Every tile is `cfg.tile_size_x.mm` by `cfg.tile_size_y.mm` mm with `cfg.tile_space_x.mm` and `cfg.tile_space_y.mm`
+/// between neighbouring tiles, as in the DXF cut file, so the poster is the finished size given on the cover page.
+/// The mosaic is split over as many `paper` sheets as needed. Neighbouring sheets repeat `overlap_mm`
+/// of the mosaic so they can be trimmed and lined up using the registration marks in the overlap.
+///
+/// The first page is an assembly map showing where each sheet goes, followed by one page per sheet
+/// labelled with its row and column. Print without any "fit to page" scaling, each sheet has a
+/// scale bar to check.
+pub fn write_poster(output_window: &[Vec<(Box2D<i32, i32>, modtile::RGB)>],
+                    cfg: &modtile::Config,
+                    paper: &str,
+                    overlap_mm: f64,
+                    save_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+
+    let (page_wid_mm, page_hgt_mm) = match page_layout::paper_size(paper) {
+        Some(size) => size,
+        None => return Err(format!("Unknown paper size {} - expected one of {:?}", paper, page_layout::paper_names()).into()),
+    };
+
+    let grid: Vec<Vec<RGB>> = modtile::get_tile_grid(output_window);
+    let grid_rows = grid.len();
+    let grid_cols = grid.first().map(|r| r.len()).unwrap_or(0);
+
+    let area_wid_mm = page_wid_mm - 2.0 * SHEET_MARGIN_MM;
+    let area_hgt_mm = page_hgt_mm - 2.0 * SHEET_MARGIN_MM;
+    if overlap_mm < 0.0 || overlap_mm >= area_wid_mm / 2.0 {
+        return Err(format!("Poster overlap of {} must be between 0 and {} for {} paper",
+                           units::format_length(overlap_mm, cfg), units::format_length(area_wid_mm / 2.0, cfg), paper).into());
+    }
+
+    let (mosaic_wid_mm, mosaic_hgt_mm) = units::finished_size_mm(cfg, grid_cols, grid_rows);
+    let step_x_mm = area_wid_mm - overlap_mm;
+    let step_y_mm = area_hgt_mm - overlap_mm;
+
+    // number of sheets needed to cover the mosaic, the last sheet is usually only partly used
+    let sheet_count = |size_mm: f64, step_mm: f64| ((size_mm - overlap_mm) / step_mm).ceil().max(1.0) as usize;
+    let layout = PosterLayout {
+        mosaic_wid_mm,
+        mosaic_hgt_mm,
+        area_wid_mm,
+        area_hgt_mm,
+        step_x_mm,
+        step_y_mm,
+        overlap_mm,
+        cols: sheet_count(mosaic_wid_mm, step_x_mm),
+        rows: sheet_count(mosaic_hgt_mm, step_y_mm),
+    };
+
+    log::info!("Poster {:.1} x {:.1} mm on {} x {} sheets of {} paper", mosaic_wid_mm, mosaic_hgt_mm, layout.cols, layout.rows, paper);
+
+    let stem = save_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let (doc, page1, layer1) =
+        PdfDocument::new(&stem, Mm(page_wid_mm), Mm(page_hgt_mm), "Layer 1");
+
+    let pdf_fonts: PdfFonts = fonts::load_pdf_fonts(&doc, cfg)?;
+
+    construct_assembly_map_page(&doc.get_page(page1).get_layer(layer1), &pdf_fonts, &grid, cfg, &layout, (page_wid_mm, page_hgt_mm), paper);
+
+    for row in 0..layout.rows {
+        for col in 0..layout.cols {
+            let sheet_no = row * layout.cols + col + 1;
+            let (page, layer) = doc.add_page(Mm(page_wid_mm), Mm(page_hgt_mm), format!("Sheet {}, Layer 1", sheet_no));
+            let current_layer = doc.get_page(page).get_layer(layer);
+            construct_sheet_page(&current_layer, &pdf_fonts, &grid, cfg, &layout, (row, col), (page_wid_mm, page_hgt_mm));
+        }
+    }
+
+    let file_path = save_path.with_file_name(format!("{}_poster.pdf", stem));
+    doc.save(&mut BufWriter::new(File::create(&file_path)?))?;
+
+    Ok(file_path)
+} // write_poster
+
+// Left, top, right and bottom of a tile in mosaic coordinates, tiles are laid out at the pitch of tile size plus spacing
+fn tile_rect_mm(cfg: &modtile::Config, tile_row: usize, tile_col: usize) -> (f64, f64, f64, f64) {
+    let left = tile_col as f64 * (cfg.tile_size_x.mm + cfg.tile_space_x.mm);
+    let top = tile_row as f64 * (cfg.tile_size_y.mm + cfg.tile_space_y.mm);
+    (left, top, left + cfg.tile_size_x.mm, top + cfg.tile_size_y.mm)
+}
+
+// Name of a sheet used in page labels and on the assembly map
+fn sheet_label(row: usize, col: usize) -> String {
+    format!("R{} C{}", row + 1, col + 1)
+}
+
+// Draw one sheet of the poster at actual size.
+// Tiles are clipped to the part of the mosaic covered by the sheet.
+fn construct_sheet_page(current_layer: &PdfLayerReference,
+                        fonts: &PdfFonts,
+                        grid: &[Vec<RGB>],
+                        cfg: &modtile::Config,
+                        layout: &PosterLayout,
+                        (row, col): (usize, usize),
+                        (page_wid_mm, page_hgt_mm): (f64, f64)) {
+
+    let (x0_mm, y0_mm) = layout.sheet_origin(row, col);
+    let x1_mm = x0_mm + layout.area_wid_mm;
+    let y1_mm = y0_mm + layout.area_hgt_mm;
+
+    // mosaic coordinates (y down) to PDF page coordinates (y up)
+    let to_page = |x_mm: f64, y_mm: f64| (SHEET_MARGIN_MM + x_mm - x0_mm, page_hgt_mm - SHEET_MARGIN_MM - (y_mm - y0_mm));
+
+    for (tile_row, grid_row) in grid.iter().enumerate() {
+        for (tile_col, rgb) in grid_row.iter().enumerate() {
+            let (left, top, right, bottom) = tile_rect_mm(cfg, tile_row, tile_col);
+            let (left, top, right, bottom) = (left.max(x0_mm), top.max(y0_mm), right.min(x1_mm), bottom.min(y1_mm));
+            if right <= left || bottom <= top {
+                continue;
+            }
+
+            let (x_mm, y_mm) = to_page(left, bottom);
+            current_layer.set_fill_color(Color::Rgb(Rgb::new(rgb.0 as f64 / 255.0, rgb.1 as f64 / 255.0, rgb.2 as f64 / 255.0, None)));
+            current_layer.add_shape(Line {
+                points: get_points_for_rect(Mm(right - left), Mm(bottom - top), Mm(x_mm), Mm(y_mm)),
+                is_closed: true,
+                has_fill: true,
+                has_stroke: false,
+                is_clipping_path: false,
+            });
+        }
+    }
+
+    // dashed lines at the inside edge of each overlap, where the neighbouring sheet ends
+    let mut dash_pattern = LineDashPattern::default();
+    dash_pattern.dash_1 = Some(4);
+    current_layer.set_line_dash_pattern(dash_pattern);
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.4, 0.4, 0.4, None)));
+    current_layer.set_outline_thickness(0.5);
+    let mut edges: Vec<((f64, f64), (f64, f64))> = Vec::new();
+    if col > 0 {
+        edges.push(((x0_mm + layout.overlap_mm, y0_mm), (x0_mm + layout.overlap_mm, y1_mm)));
+    }
+    if col + 1 < layout.cols {
+        edges.push(((x0_mm + layout.step_x_mm, y0_mm), (x0_mm + layout.step_x_mm, y1_mm)));
+    }
+    if row > 0 {
+        edges.push(((x0_mm, y0_mm + layout.overlap_mm), (x1_mm, y0_mm + layout.overlap_mm)));
+    }
+    if row + 1 < layout.rows {
+        edges.push(((x0_mm, y0_mm + layout.step_y_mm), (x1_mm, y0_mm + layout.step_y_mm)));
+    }
+    for (start, end) in edges {
+        let (sx, sy) = to_page(start.0, start.1);
+        let (ex, ey) = to_page(end.0, end.1);
+        current_layer.add_shape(Line {
+            points: get_points_for_line(Mm(sx), Mm(sy), Mm(ex), Mm(ey)),
+            is_closed: false,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+    }
+    current_layer.set_line_dash_pattern(LineDashPattern::default());
+
+    // Registration marks sit in the middle of each overlap. Every mark is at the same place on the
+    // mosaic for all sheets that share it (left/right, above/below and the corners).
+    let mut mark_x: Vec<f64> = Vec::new();
+    let mut mark_y: Vec<f64> = Vec::new();
+    if col > 0 {
+        mark_x.push(x0_mm + layout.overlap_mm / 2.0);
+    }
+    if col + 1 < layout.cols {
+        mark_x.push(x0_mm + layout.step_x_mm + layout.overlap_mm / 2.0);
+    }
+    if row > 0 {
+        mark_y.push(y0_mm + layout.overlap_mm / 2.0);
+    }
+    if row + 1 < layout.rows {
+        mark_y.push(y0_mm + layout.step_y_mm + layout.overlap_mm / 2.0);
+    }
+    let mid_x = x0_mm + layout.area_wid_mm / 2.0;
+    let mid_y = y0_mm + layout.area_hgt_mm / 2.0;
+
+    let mut marks: Vec<(f64, f64)> = Vec::new();
+    for x in &mark_x {
+        marks.push(to_page(*x, mid_y));
+        for y in &mark_y {
+            marks.push(to_page(*x, *y));
+        }
+    }
+    for y in &mark_y {
+        marks.push(to_page(mid_x, *y));
+    }
+    draw_registration_marks(&current_layer, &marks);
+
+    // sheet label and the neighbouring sheets in the margin
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    current_layer.set_fill_color(black.clone());
+    let label = format!("{} - row {} of {}, column {} of {}", sheet_label(row, col), row + 1, layout.rows, col + 1, layout.cols);
+    current_layer.use_text(label, 10.0, Mm(SHEET_MARGIN_MM), Mm(SHEET_MARGIN_MM / 2.0 - 1.0), &fonts.regular);
+    if row > 0 {
+        current_layer.use_text(format!("^ {}", sheet_label(row - 1, col)), 8.0, Mm(page_wid_mm / 2.0 - 6.0), Mm(page_hgt_mm - SHEET_MARGIN_MM / 2.0 - 1.0), &fonts.regular);
+    }
+    if row + 1 < layout.rows {
+        current_layer.use_text(format!("v {}", sheet_label(row + 1, col)), 8.0, Mm(page_wid_mm / 2.0 - 6.0), Mm(SHEET_MARGIN_MM / 2.0 - 1.0), &fonts.regular);
+    }
+    if col > 0 {
+        current_layer.use_text(format!("< {}", sheet_label(row, col - 1)), 6.0, Mm(0.5), Mm(page_hgt_mm / 2.0), &fonts.regular);
+    }
+    if col + 1 < layout.cols {
+        current_layer.use_text(format!("{} >", sheet_label(row, col + 1)), 6.0, Mm(page_wid_mm - SHEET_MARGIN_MM + 0.5), Mm(page_hgt_mm / 2.0), &fonts.regular);
+    }
+
+    draw_scale_bar(current_layer, fonts, page_wid_mm - SHEET_MARGIN_MM - SCALE_BAR_MM, SHEET_MARGIN_MM / 2.0);
+} // construct_sheet_page
+
+// Draw a bar of SCALE_BAR_MM with end ticks, starting at (x_mm, y_mm), so the print scale can be checked with a ruler
+fn draw_scale_bar(current_layer: &PdfLayerReference, fonts: &PdfFonts, x_mm: f64, y_mm: f64) {
+
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    current_layer.set_outline_thickness(0.5);
+    let bar = [(x_mm, y_mm, x_mm + SCALE_BAR_MM, y_mm),
+               (x_mm, y_mm - 1.5, x_mm, y_mm + 1.5),
+               (x_mm + SCALE_BAR_MM, y_mm - 1.5, x_mm + SCALE_BAR_MM, y_mm + 1.5)];
+    for (sx, sy, ex, ey) in bar.iter() {
+        current_layer.add_shape(Line {
+            points: get_points_for_line(Mm(*sx), Mm(*sy), Mm(*ex), Mm(*ey)),
+            is_closed: false,
+            has_fill: false,
+            has_stroke: true,
+            is_clipping_path: false,
+        });
+    }
+    current_layer.use_text(format!("{} mm", SCALE_BAR_MM), 7.0, Mm(x_mm + SCALE_BAR_MM / 2.0 - 4.0), Mm(y_mm + 1.5), &fonts.regular);
+} // draw_scale_bar
+
+// First page of the poster. A scaled down mosaic with the outline of every sheet on top
+// so the printed sheets can be laid out in the right order.
+fn construct_assembly_map_page(current_layer: &PdfLayerReference,
+                               fonts: &PdfFonts,
+                               grid: &[Vec<RGB>],
+                               cfg: &modtile::Config,
+                               layout: &PosterLayout,
+                               (page_wid_mm, page_hgt_mm): (f64, f64),
+                               paper: &str) {
+
+    let page_margin_mm = 20.0;
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let white = Color::Rgb(Rgb::new(1.0, 1.0, 1.0, None));
+
+    current_layer.set_fill_color(black.clone());
+    let mut text_y_mm = page_hgt_mm - page_margin_mm;
+    current_layer.use_text("Poster Assembly Map", 24.0, Mm(page_margin_mm), Mm(text_y_mm), &fonts.heading);
+    let info = [
+        format!("Actual size {} x {}, {} x {} tiles", units::format_length(layout.mosaic_wid_mm, cfg), units::format_length(layout.mosaic_hgt_mm, cfg),
+                grid.first().map(|r| r.len()).unwrap_or(0), grid.len()),
+        format!("{} sheets of {} paper, {} rows of {} columns, {} overlap", layout.rows * layout.cols, paper, layout.rows, layout.cols,
+                units::format_length(layout.overlap_mm, cfg)),
+        "Print at 100% (actual size), check the scale bar on each sheet".to_owned(),
+        "Line up neighbouring sheets using the registration marks in the overlap".to_owned(),
+    ];
+    text_y_mm -= 4.0;
+    for line in info.iter() {
+        text_y_mm -= 6.0;
+        current_layer.use_text(line.as_str(), 11.0, Mm(page_margin_mm), Mm(text_y_mm), &fonts.regular);
+    }
+
+    // scale the mosaic to fit the space under the text
+    let map_top_mm = text_y_mm - 8.0;
+    let scale = ((page_wid_mm - 2.0 * page_margin_mm) / layout.mosaic_wid_mm)
+                    .min((map_top_mm - page_margin_mm) / layout.mosaic_hgt_mm);
+    let to_map = |x_mm: f64, y_mm: f64| (page_margin_mm + x_mm * scale, map_top_mm - y_mm * scale);
+
+    for (tile_row, grid_row) in grid.iter().enumerate() {
+        for (tile_col, rgb) in grid_row.iter().enumerate() {
+            let (left, _, _, bottom) = tile_rect_mm(cfg, tile_row, tile_col);
+            let (x_mm, y_mm) = to_map(left, bottom);
+            current_layer.set_fill_color(Color::Rgb(Rgb::new(rgb.0 as f64 / 255.0, rgb.1 as f64 / 255.0, rgb.2 as f64 / 255.0, None)));
+            current_layer.add_shape(Line {
+                points: get_points_for_rect(Mm(cfg.tile_size_x.mm * scale), Mm(cfg.tile_size_y.mm * scale), Mm(x_mm), Mm(y_mm)),
+                is_closed: true,
+                has_fill: true,
+                has_stroke: false,
+                is_clipping_path: false,
+            });
+        }
+    }
+
+    // outline of each sheet, limited to the mosaic, with its label in the middle of the part only it covers
+    current_layer.set_outline_color(black.clone());
+    current_layer.set_outline_thickness(1.0);
+    for row in 0..layout.rows {
+        for col in 0..layout.cols {
+            let (x0_mm, y0_mm) = layout.sheet_origin(row, col);
+            let x1_mm = (x0_mm + layout.area_wid_mm).min(layout.mosaic_wid_mm);
+            let y1_mm = (y0_mm + layout.area_hgt_mm).min(layout.mosaic_hgt_mm);
+            let (x_mm, y_mm) = to_map(x0_mm, y1_mm);
+            current_layer.add_shape(Line {
+                points: get_points_for_rect(Mm((x1_mm - x0_mm) * scale), Mm((y1_mm - y0_mm) * scale), Mm(x_mm), Mm(y_mm)),
+                is_closed: true,
+                has_fill: false,
+                has_stroke: true,
+                is_clipping_path: false,
+            });
+
+            let (cx_mm, cy_mm) = to_map((x0_mm + x1_mm) / 2.0, (y0_mm + y1_mm) / 2.0);
+            let label = format!("{} ({})", sheet_label(row, col), row * layout.cols + col + 1);
+            current_layer.set_fill_color(white.clone());
+            current_layer.add_shape(Line {
+                points: get_points_for_rect(Mm(22.0), Mm(6.0), Mm(cx_mm - 11.0), Mm(cy_mm - 2.0)),
+                is_closed: true,
+                has_fill: true,
+                has_stroke: true,
+                is_clipping_path: false,
+            });
+            current_layer.set_fill_color(black.clone());
+            current_layer.use_text(label, 9.0, Mm(cx_mm - 10.0), Mm(cy_mm), &fonts.regular);
+        }
+    }
+} // construct_assembly_map_page
@@ -0,0 +1,123 @@
+use crate::{ldraw_export, modtile};
+use crate::units::Length;
+
+/// A board preset describes a standard peg board or baseplate.
+/// Each window pane of the mosaic becomes one board of `board_size` x `board_size` pieces.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BoardPreset {
+    pub name: &'static str,          // name used in the config file / on the command line
+    pub board_size: usize,           // pieces per board edge
+    pub pitch_mm: f64,               // centre to centre distance of the pieces
+    pub palette: &'static str,       // built in palette used when the config does not name one, see modtile::BUILTIN_PALETTES
+    pub board_part: &'static str,    // description of the board itself for the parts list
+    pub piece_part: &'static str,    // description of a single piece for the parts list
+    pub ldraw_board: Option<&'static str>,  // LDraw part file of the board, if there is one
+}
+
+pub const PRESETS: [BoardPreset; 4] = [
+    BoardPreset {
+        name: "perler_29",
+        board_size: 29,
+        pitch_mm: 5.0,
+        palette: "builtin:perler",
+        board_part: "Perler large square pegboard 29 x 29",
+        piece_part: "Perler fuse bead 5mm",
+        ldraw_board: None,
+    },
+    BoardPreset {
+        name: "lego_16",
+        board_size: 16,
+        pitch_mm: 8.0,
+        palette: "builtin:lego",
+        board_part: "LEGO Plate 16 x 16 (91405)",
+        piece_part: "LEGO Plate Round 1 x 1 (4073)",
+        ldraw_board: Some("91405.dat"),
+    },
+    BoardPreset {
+        name: "lego_32",
+        board_size: 32,
+        pitch_mm: 8.0,
+        palette: "builtin:lego",
+        board_part: "LEGO Baseplate 32 x 32 (3811)",
+        piece_part: "LEGO Plate Round 1 x 1 (4073)",
+        ldraw_board: Some("3811.dat"),
+    },
+    BoardPreset {
+        name: "lego_48",
+        board_size: 48,
+        pitch_mm: 8.0,
+        palette: "builtin:lego",
+        board_part: "LEGO Baseplate 48 x 48 (4186)",
+        piece_part: "LEGO Plate Round 1 x 1 (4073)",
+        ldraw_board: Some("4186.dat"),
+    },
+];
+
+// Look up a preset by name
+pub fn get_preset(name: &str) -> Option<&'static BoardPreset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+// Names of all the presets, used for command line help and error messages
+pub fn preset_names() -> Vec<&'static str> {
+    PRESETS.iter().map(|p| p.name).collect()
+}
+
+// The board with the 1 x 1 piece chosen for the LDraw model (--ldraw-piece) in its parts list,
+// boards without an LDraw board part are built from their own pieces
+pub fn with_ldraw_piece(board: &BoardPreset, piece: &str) -> BoardPreset {
+    match (board.ldraw_board, ldraw_export::LDRAW_PIECES.iter().find(|(name, _, _)| *name == piece)) {
+        (Some(_), Some((_, _, piece_part))) => BoardPreset { piece_part, ..*board },
+        _ => *board,
+    }
+}
+
+// Fix the pane size and tile pitch of the config to the board.
+// The bundled palette is only used when the config leaves `tile_colors` empty.
+pub fn apply_preset(cfg: &mut modtile::Config, preset: &BoardPreset) {
+    cfg.tiles_per_pane_width = preset.board_size;
+    cfg.tiles_per_pane_height = preset.board_size;
+    cfg.tile_size_x = Length::from_mm(preset.pitch_mm);
+    cfg.tile_size_y = Length::from_mm(preset.pitch_mm);
+    cfg.tile_space_x = Length::from_mm(0.0);
+    cfg.tile_space_y = Length::from_mm(0.0);
+    if cfg.tile_colors.trim().is_empty() {
+        cfg.tile_colors = preset.palette.to_owned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_palettes_are_built_in() {
+        for preset in PRESETS.iter() {
+            assert!(modtile::is_builtin_palette(preset.palette), "{}", preset.name);
+            assert!(!modtile::load_all_colors(preset.palette).unwrap().colors.is_empty(), "{}", preset.name);
+        }
+        assert!(!modtile::is_builtin_palette("builtin:crayola"));
+    }
+
+    #[test]
+    fn empty_palette_takes_the_presets() {
+        let mut cfg = modtile::Config { tile_colors: " ".to_owned(), ..Default::default() };
+        apply_preset(&mut cfg, get_preset("lego_32").unwrap());
+        assert_eq!(cfg.tile_colors, "builtin:lego");
+        assert_eq!((cfg.tiles_per_pane_width, cfg.tile_size_x.mm), (32, 8.0));
+
+        let mut cfg = modtile::Config { tile_colors: "mine.json".to_owned(), ..Default::default() };
+        apply_preset(&mut cfg, get_preset("perler_29").unwrap());
+        assert_eq!(cfg.tile_colors, "mine.json");
+    }
+
+    #[test]
+    fn lego_parts_lists_use_the_ldraw_piece() {
+        let lego = get_preset("lego_16").unwrap();
+        assert_eq!(with_ldraw_piece(lego, "tile").piece_part, "LEGO Tile 1 x 1 (3070b)");
+        assert_eq!(with_ldraw_piece(lego, "round-plate"), *lego);
+        assert_eq!(with_ldraw_piece(lego, "unknown"), *lego);
+        let perler = get_preset("perler_29").unwrap();
+        assert_eq!(with_ldraw_piece(perler, "tile"), *perler);
+    }
+}
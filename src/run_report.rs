@@ -0,0 +1,109 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// What a render run read, wrote and counted and how long each stage took.
+// Logged as a short summary at the end of the run and saved as <output>.report.json (or --report FILE)
+// so scripts can pick up the outputs and compare runs without reading the log.
+
+#[derive(Serialize)]
+pub struct RunReport {
+    program: &'static str,
+    version: &'static str,
+    command: String,
+    inputs: Map<String, Value>,     // config, image, palette and font files read
+    outputs: Vec<OutputFile>,       // files written, in the order written
+    counts: Map<String, Value>,     // tiles, panes, colours ...
+    timings: Vec<StageTiming>,
+    total_seconds: f64,
+    #[serde(skip)]
+    started: Instant,
+}
+
+#[derive(Serialize)]
+struct OutputFile {
+    kind: String,
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct StageTiming {
+    stage: String,
+    seconds: f64,
+}
+
+impl RunReport {
+
+    pub fn new(command: &str) -> RunReport {
+        RunReport {
+            program: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+            command: command.to_owned(),
+            inputs: Map::new(),
+            outputs: Vec::new(),
+            counts: Map::new(),
+            timings: Vec::new(),
+            total_seconds: 0.0,
+            started: Instant::now(),
+        }
+    }
+
+    /// An input file of `kind`, e.g. "palette"
+    pub fn input(&mut self, kind: &str, path: &str) {
+        self.inputs.insert(kind.to_owned(), Value::String(path.to_owned()));
+    }
+
+    /// An output file of `kind`, e.g. "instructions pdf", was written to `path`
+    pub fn output(&mut self, kind: &str, path: &Path) {
+        log::debug!("wrote {} {}", kind, path.display());
+        self.outputs.push(OutputFile { kind: kind.to_owned(), path: path.to_path_buf() });
+    }
+
+    pub fn count<V: Into<Value>>(&mut self, name: &str, value: V) {
+        self.counts.insert(name.to_owned(), value.into());
+    }
+
+    /// Run `stage`, recording how long it took
+    pub fn time<T, F: FnOnce(&mut RunReport) -> T>(&mut self, stage: &str, f: F) -> T {
+        let started = Instant::now();
+        let result = f(self);
+        let seconds = started.elapsed().as_secs_f64();
+        log::debug!("{} took {:.2}s", stage, seconds);
+        self.timings.push(StageTiming { stage: stage.to_owned(), seconds: round3(seconds) });
+        result
+    }
+
+    /// Seconds since the run started
+    pub fn elapsed(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    /// Log the human readable summary of the run at info level
+    pub fn log_summary(&self) {
+        let count = |name: &str| match self.counts.get(name) {
+            Some(Value::String(text)) => text.to_owned(),
+            Some(value) => value.to_string(),
+            None => "?".to_owned(),
+        };
+        log::info!("Made {} in {:.1}s", self.outputs.first().map(|o| o.path.display().to_string()).unwrap_or_default(), self.elapsed());
+        log::info!("  {} x {} tiles ({}) in {} panes, {} of {} colours from {}", count("tiles_across"), count("tiles_down"), count("tiles"),
+                   count("panes"), count("colors_used"), count("palette_colors"), count("palette_name"));
+        log::info!("  colour error per tile mean {}, max {}", count("mean_error"), count("max_error"));
+        for output in &self.outputs {
+            log::info!("  {:<18} {}", output.kind, output.path.display());
+        }
+    }
+
+    /// Save the report as JSON to `path`
+    pub fn write(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        self.output("run report", path);
+        self.total_seconds = round3(self.elapsed());
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+fn round3(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
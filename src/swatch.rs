@@ -0,0 +1,283 @@
+use printpdf::*;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::fonts::{self, PdfFonts};
+use crate::modtile::{self, TileColor};
+use crate::page_layout::PageLayout;
+use crate::pdf_util::get_points_for_rect;
+
+// Orders the swatches can be printed in, "none" keeps the palette file order
+pub const SWATCH_SORTS: [&str; 4] = ["none", "hue", "lightness", "number"];
+
+// Text that can be printed under each swatch, in the order given
+pub const SWATCH_LABELS: [&str; 4] = ["name", "number", "rgb", "hex"];
+
+// spacing between swatches in mm
+const SWATCH_GAP_X_MM: f64 = 8.0;
+const SWATCH_GAP_Y_MM: f64 = 5.0;
+
+/// Swatch pdf settings, normally taken from the command line.
+/// `columns` of None fits as many `size_mm` swatches across the page as there is room for,
+/// otherwise the swatches shrink if needed to fit `columns` across.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SwatchOptions {
+    pub size_mm: f64,
+    pub columns: Option<usize>,
+    pub labels: Vec<String>,    // any of SWATCH_LABELS
+    pub sort: String,           // one of SWATCH_SORTS
+}
+
+impl Default for SwatchOptions {
+    fn default() -> Self {
+        SwatchOptions {
+            size_mm: 30.0,
+            columns: None,
+            labels: vec!["rgb".to_owned(), "name".to_owned(), "number".to_owned()],
+            sort: "none".to_owned(),
+        }
+    }
+}
+
+// Names of the sort orders and labels, used for command line help and error messages
+pub fn swatch_sort_names() -> Vec<&'static str> {
+    SWATCH_SORTS.to_vec()
+}
+
+pub fn swatch_label_names() -> Vec<&'static str> {
+    SWATCH_LABELS.to_vec()
+}
+
+/// Default swatch pdf path, the palette name with spaces replaced by underscores in the
+/// same directory as the mosaic output image.
+pub fn default_swatch_path(all_colors: &modtile::AllColors, cfg: &modtile::Config) -> PathBuf {
+    let file_name = format!("{}.pdf", all_colors.name.split_whitespace().collect::<Vec<&str>>().join("_"));
+    Path::new(&cfg.output).with_file_name(file_name)
+}
+
+/// Write a pdf showing every colour in the palette as a printed swatch with its labels.
+///
+/// The first page starts with the palette name, url and description. Swatches are laid out
+/// left to right, top to bottom in the order asked for and run onto as many pages as needed.
+/// Page size and margins come from the "page" section of the config.
+pub fn generate_color_swatch(all_colors: &modtile::AllColors,
+                             cfg: &modtile::Config,
+                             opts: &SwatchOptions,
+                             pdf_path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+
+    if !SWATCH_SORTS.contains(&opts.sort.as_str()) {
+        return Err(format!("Unknown swatch sort {} - expected one of {:?}", opts.sort, swatch_sort_names()).into());
+    }
+    if let Some(label) = opts.labels.iter().find(|l| !SWATCH_LABELS.contains(&l.as_str())) {
+        return Err(format!("Unknown swatch label {} - expected any of {:?}", label, swatch_label_names()).into());
+    }
+    if opts.size_mm <= 0.0 || opts.columns == Some(0) {
+        return Err("Swatch size and columns must be greater than zero".into());
+    }
+
+    let page: PageLayout = PageLayout::from_config(cfg.page.as_ref())?;
+    let colors: Vec<&TileColor> = sorted_colors(&all_colors.colors, &opts.sort);
+    log::debug!("Total number of colour swatches: {}", colors.len());
+
+    // swatch size and number of columns across the content box
+    let content_w = page.content_width_mm();
+    let (columns, size_mm) = match opts.columns {
+        Some(cols) => (cols, opts.size_mm.min((content_w - SWATCH_GAP_X_MM * (cols - 1) as f64) / cols as f64)),
+        None => ((((content_w + SWATCH_GAP_X_MM) / (opts.size_mm + SWATCH_GAP_X_MM)).floor() as usize).max(1), opts.size_mm.min(content_w)),
+    };
+    if size_mm <= 0.0 {
+        return Err(format!("{} swatch columns do not fit across a {:.1} mm page", columns, page.width_mm).into());
+    }
+    let font_size: f64 = if size_mm < 20.0 { 7.0 } else { 9.0 };
+    let line_hgt_mm = font_size * 0.45;
+    let row_pitch_mm = size_mm + opts.labels.len() as f64 * line_hgt_mm + 1.0 + SWATCH_GAP_Y_MM;
+    let col_pitch_mm = size_mm + SWATCH_GAP_X_MM;
+    let max_label_chars = ((col_pitch_mm - 1.0) / (font_size * 0.19)).floor().max(4.0) as usize;
+
+    // the url and description take room from the first page
+    let desc_lines: Vec<&str> = all_colors.description.lines().collect();
+    let first_top_mm = page.content_top_mm() - (1 + desc_lines.len()) as f64 * 5.0 - 3.0;
+    let rows_fit = |avail_mm: f64| (((avail_mm + SWATCH_GAP_Y_MM) / row_pitch_mm).floor() as usize).max(1);
+    let first_rows = rows_fit(first_top_mm - page.margin_bottom_mm);
+    let other_rows = rows_fit(page.content_height_mm());
+
+    let total_rows = colors.len().div_ceil(columns);
+    let page_count = 1 + total_rows.saturating_sub(first_rows).div_ceil(other_rows);
+
+    let (doc, page1, layer1) =
+        PdfDocument::new(&all_colors.name, Mm(page.width_mm), Mm(page.height_mm), "Layer 1");
+    let pdf_fonts: PdfFonts = fonts::load_pdf_fonts(&doc, cfg)?;
+
+    let mut next = 0;
+    for page_no in 1..=page_count {
+        let current_layer = if page_no == 1 {
+            doc.get_page(page1).get_layer(layer1)
+        } else {
+            let (page_idx, layer_idx) = doc.add_page(Mm(page.width_mm), Mm(page.height_mm), format!("Page {}, Layer 1", page_no));
+            doc.get_page(page_idx).get_layer(layer_idx)
+        };
+
+        // text, font size, x from left edge, y from bottom edge, font
+        let (rows, top_mm) = if page_no == 1 {
+            current_layer.use_text(&all_colors.name, 14.0, Mm(page.margin_left_mm), Mm(page.header_y_mm()), &pdf_fonts.heading);
+            current_layer.use_text(&all_colors.url, 11.0, Mm(page.margin_left_mm), Mm(page.content_top_mm()), &pdf_fonts.regular);
+            for (i, line) in desc_lines.iter().enumerate() {
+                current_layer.use_text(*line, 10.0, Mm(page.margin_left_mm), Mm(page.content_top_mm() - (i + 1) as f64 * 5.0), &pdf_fonts.regular);
+            }
+            (first_rows, first_top_mm)
+        } else {
+            current_layer.use_text(format!("{} (continued)", all_colors.name), 14.0, Mm(page.margin_left_mm), Mm(page.header_y_mm()), &pdf_fonts.heading);
+            (other_rows, page.content_top_mm())
+        };
+        current_layer.use_text(format!("Page {} of {}", page_no, page_count), 10.0,
+                               Mm(page.width_mm / 2.0 - 10.0), Mm(page.footer_y_mm()), &pdf_fonts.regular);
+
+        for row in 0..rows {
+            for col in 0..columns {
+                let tc = match colors.get(next) {
+                    Some(tc) => tc,
+                    None => break,
+                };
+                let x_mm = page.margin_left_mm + col as f64 * col_pitch_mm;
+                let y_mm = top_mm - row as f64 * row_pitch_mm;
+                draw_swatch(&current_layer, &pdf_fonts, tc, &opts.labels, (x_mm, y_mm, size_mm), (font_size, max_label_chars));
+                next += 1;
+            }
+        }
+    }
+
+    doc.save(&mut BufWriter::new(File::create(pdf_path)?))?;
+    log::debug!("Colour swatches found in {}", pdf_path.display());
+
+    Ok(pdf_path.to_path_buf())
+} // generate_color_swatch
+
+// Draw one colour swatch with its top left corner at (x_mm, y_mm) and the labels underneath
+fn draw_swatch(current_layer: &PdfLayerReference,
+               fonts: &PdfFonts,
+               tc: &TileColor,
+               labels: &[String],
+               (x_mm, y_mm, size_mm): (f64, f64, f64),
+               (font_size, max_chars): (f64, usize)) {
+
+    let size: Pt = Mm(size_mm).into();
+    let line = Line {
+        points: get_points_for_rect(size, size, Mm(x_mm).into(), Mm(y_mm - size_mm).into()),
+        is_closed: true,
+        has_fill: true,
+        has_stroke: true,
+        is_clipping_path: false,
+    };
+
+    // light grey outline so white and pale swatches still show on the page
+    current_layer.set_fill_color(Color::Rgb(Rgb::new(tc.rgb.0 as f64 / 255.0, tc.rgb.1 as f64 / 255.0, tc.rgb.2 as f64 / 255.0, None)));
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.75, 0.75, 0.75, None)));
+    current_layer.set_outline_thickness(0.5);
+    current_layer.add_shape(line);
+
+    // TileColor info as black text below the swatch
+    current_layer.set_fill_color(Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)));
+    for (i, label) in labels.iter().enumerate() {
+        let text = match label.as_str() {
+            "name" => tc.name.to_owned(),
+            "number" => tc.number.to_owned(),
+            "rgb" => format!("rgb ({}, {}, {})", tc.rgb.0, tc.rgb.1, tc.rgb.2),
+            "hex" => format!("#{:02X}{:02X}{:02X}", tc.rgb.0, tc.rgb.1, tc.rgb.2),
+            _ => continue,
+        };
+        let text = if text.chars().count() > max_chars {
+            format!("{}...", text.chars().take(max_chars - 3).collect::<String>())
+        } else {
+            text
+        };
+        let text_y_mm = y_mm - size_mm - (i + 1) as f64 * font_size * 0.45;
+        current_layer.use_text(text, font_size, Mm(x_mm), Mm(text_y_mm), &fonts.regular);
+    }
+} // draw_swatch
+
+// Palette colours in the requested order, the sort is stable so ties keep the palette order
+pub(crate) fn sorted_colors<'a>(colors: &'a [TileColor], sort: &str) -> Vec<&'a TileColor> {
+
+    let mut sorted: Vec<&TileColor> = colors.iter().collect();
+    match sort {
+        "hue" => sorted.sort_by(|a, b| hue_cmp(&a.rgb, &b.rgb)),
+        "lightness" => sorted.sort_by(|a, b| hsl(&a.rgb).2.partial_cmp(&hsl(&b.rgb).2).unwrap_or(Ordering::Equal)),
+        "number" => sorted.sort_by(|a, b| natural_cmp(&a.number, &b.number)),
+        _ => {}
+    }
+    sorted
+} // sorted_colors
+
+// Compare two colours by hue then lightness, greys have no real hue so they go last, dark to light
+pub(crate) fn hue_cmp(a: &modtile::RGB, b: &modtile::RGB) -> Ordering {
+
+    let (ha, sa, la) = hsl(a);
+    let (hb, sb, lb) = hsl(b);
+    (sa < 0.1).cmp(&(sb < 0.1))
+              .then_with(|| if sa < 0.1 { Ordering::Equal } else { ha.partial_cmp(&hb).unwrap_or(Ordering::Equal) })
+              .then_with(|| la.partial_cmp(&lb).unwrap_or(Ordering::Equal))
+} // hue_cmp
+
+// Hue in degrees, saturation and lightness (0.0 - 1.0) of an RGB colour
+fn hsl(rgb: &modtile::RGB) -> (f64, f64, f64) {
+
+    let (r, g, b) = (rgb.0 as f64 / 255.0, rgb.1 as f64 / 255.0, rgb.2 as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * (((g - b) / d).rem_euclid(6.0))
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    };
+    (h, s, l)
+} // hsl
+
+// Compare colour numbers so "2" comes before "10" and "P2" before "P10",
+// runs of digits are compared by value and everything else as text.
+pub(crate) fn natural_cmp(a: &str, b: &str) -> Ordering {
+
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_digit()) {
+                        digits.push(c);
+                        chars.next();
+                    }
+                    digits.trim_start_matches('0').to_owned()
+                };
+                let (na, nb) = (take_number(&mut a_chars), take_number(&mut b_chars));
+                let by_value = na.len().cmp(&nb.len()).then_with(|| na.cmp(&nb));
+                if by_value != Ordering::Equal {
+                    return by_value;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                let by_char = ca.to_ascii_lowercase().cmp(&cb.to_ascii_lowercase());
+                if by_char != Ordering::Equal {
+                    return by_char;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+} // natural_cmp
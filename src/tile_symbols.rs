@@ -0,0 +1,208 @@
+use printpdf::*;
+use std::collections::HashMap;
+
+use crate::cross_stitch::stitch_symbol;
+use crate::fonts::PdfFonts;
+use crate::modtile::{self, RGB};
+use crate::pdf_util::{draw_circle_with_pts, draw_circle_with_pts_no_fill, get_points_for_line};
+
+// Ways of labelling each tile colour on the instruction pages
+//   count  - position of the colour when sorted by tile count in this mosaic, 0 is the most used
+//   index  - position of the colour in the palette file starting at 1, the same for every mosaic
+//   number - the palette "number" of the colour, e.g. the paint or bead number
+//   letter - A to Z then AA, AB .. in palette order, the same for every mosaic
+//   glyph  - the cross stitch chart symbol in palette order, the same for every mosaic
+pub const SYMBOL_SCHEMES: [&str; 5] = ["count", "index", "number", "letter", "glyph"];
+
+// Names of the symbol schemes, used for error messages
+pub fn symbol_scheme_names() -> Vec<&'static str> {
+    SYMBOL_SCHEMES.to_vec()
+}
+
+// How the tile circles are printed
+//   color - circles filled with the tile colour
+//   mono  - outline circles with a hatch pattern per colour and black symbols on white,
+//           for black and white printers and colour blind readers
+pub const PRINT_MODES: [&str; 2] = ["color", "mono"];
+
+// Names of the print modes, used for error messages
+pub fn print_mode_names() -> Vec<&'static str> {
+    PRINT_MODES.to_vec()
+}
+
+// Hatch patterns for mono printing, line angles in degrees and line spacing as a fraction of the radius.
+// The first pattern is left blank, colours past the end of the list reuse patterns but keep their symbol.
+const HATCH_PATTERNS: [(&[f64], f64); 16] = [
+    (&[], 1.0),
+    (&[0.0], 0.45),
+    (&[90.0], 0.45),
+    (&[45.0], 0.45),
+    (&[135.0], 0.45),
+    (&[0.0, 90.0], 0.45),
+    (&[45.0, 135.0], 0.45),
+    (&[0.0], 0.25),
+    (&[90.0], 0.25),
+    (&[45.0], 0.25),
+    (&[135.0], 0.25),
+    (&[0.0, 90.0], 0.25),
+    (&[45.0, 135.0], 0.25),
+    (&[0.0, 45.0], 0.4),
+    (&[90.0, 135.0], 0.4),
+    (&[0.0, 60.0, 120.0], 0.4),
+];
+
+/// The symbol printed for each tile colour, shared by the pane pages, pane legends and summary
+/// pages so a colour has the same symbol wherever it appears.
+pub(crate) struct TileSymbols {
+    symbols: HashMap<RGB, (String, usize)>,    // symbol and hatch pattern of each colour
+    mono: bool,                                // print_mode "mono"
+}
+
+impl TileSymbols {
+
+    /// Assign a symbol to every colour used in the mosaic using `scheme`, one of SYMBOL_SCHEMES,
+    /// drawn in `print_mode`, one of PRINT_MODES.
+    pub(crate) fn new(scheme: &str,
+                      print_mode: &str,
+                      all_colors: &modtile::AllColors,
+                      tile_color_count_vec: &[(Vec<u8>, i32)]) -> Result<TileSymbols, String> {
+
+        if !SYMBOL_SCHEMES.contains(&scheme) {
+            return Err(format!("Unknown tile_symbols {} - expected one of {:?}", scheme, symbol_scheme_names()));
+        }
+        if !PRINT_MODES.contains(&print_mode) {
+            return Err(format!("Unknown print_mode {} - expected one of {:?}", print_mode, print_mode_names()));
+        }
+
+        let mut symbols: HashMap<RGB, (String, usize)> = HashMap::new();
+        for (i, bc) in tile_color_count_vec.iter().enumerate() {
+            let rgb = RGB(bc.0[0], bc.0[1], bc.0[2]);
+            let palette_pos = all_colors.colors.iter().position(|tc| tc.rgb == rgb);
+            let symbol = match (scheme, palette_pos) {
+                ("count", _) => i.to_string(),
+                ("index", Some(pos)) => (pos + 1).to_string(),
+                ("number", Some(pos)) => all_colors.colors[pos].number.to_owned(),
+                ("letter", Some(pos)) => letter_symbol(pos),
+                ("glyph", Some(pos)) => stitch_symbol(pos),
+                // a colour missing from the palette falls back to its usage position
+                _ => format!("?{}", i),
+            };
+            // hatch patterns follow the palette order too unless numbering by count
+            let pattern = match (scheme, palette_pos) {
+                ("count", _) | (_, None) => i,
+                (_, Some(pos)) => pos,
+            };
+            symbols.insert(rgb, (symbol, pattern % HATCH_PATTERNS.len()));
+        }
+
+        Ok(TileSymbols { symbols, mono: print_mode == "mono" })
+    } // new
+
+    /// Symbol for a tile colour, "?" if the colour is not used in the mosaic
+    pub(crate) fn symbol(&self, rgb: &RGB) -> &str {
+        self.symbols.get(rgb).map(|s| s.0.as_str()).unwrap_or("?")
+    }
+
+    // Hatch pattern used for a tile colour in mono print mode
+    fn pattern(&self, rgb: &RGB) -> usize {
+        self.symbols.get(rgb).map(|s| s.1).unwrap_or(0)
+    }
+}
+
+// Spreadsheet style column letters, 0 -> A, 25 -> Z, 26 -> AA
+pub(crate) fn letter_symbol(n: usize) -> String {
+    let mut n = n + 1;
+    let mut letters: Vec<char> = Vec::new();
+    while n > 0 {
+        n -= 1;
+        letters.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Draw a tile colour circle centred on (center_x_mm, center_y_mm) with its symbol in the middle.
+/// The circle has a thin black outline and the symbol is black on light colours and white on dark
+/// ones so pages stay readable when printed in black and white.
+/// In mono print mode the circle is not filled, it gets the colour's hatch pattern instead and the
+/// symbol is printed black on a white disc.
+pub(crate) fn draw_symbol_circle(current_layer: &PdfLayerReference,
+                                 fonts: &PdfFonts,
+                                 symbols: &TileSymbols,
+                                 rgb: &RGB,
+                                 (center_x_mm, center_y_mm, radius_mm): (f64, f64, f64)) {
+
+    let black = Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None));
+    let white = Color::Rgb(Rgb::new(1.0, 1.0, 1.0, None));
+    let center_x_pt: Pt = Mm(center_x_mm).into();
+    let center_y_pt: Pt = Mm(center_y_mm).into();
+
+    if symbols.mono {
+        // hatching first then a white disc behind the symbol and the outline on top
+        draw_hatch(current_layer, HATCH_PATTERNS[symbols.pattern(rgb)], (center_x_mm, center_y_mm, radius_mm));
+        current_layer.set_fill_color(white.clone());
+        current_layer.add_shape(Line {
+            points: printpdf::utils::calculate_points_for_circle(Mm(radius_mm * 0.6), Mm(center_x_mm), Mm(center_y_mm)),
+            is_closed: true,
+            has_fill: true,
+            has_stroke: false,
+            is_clipping_path: false,
+        });
+        current_layer.set_outline_color(black.clone());
+        current_layer.set_outline_thickness(0.8);
+        draw_circle_with_pts_no_fill(&current_layer, center_x_pt, center_y_pt, Mm(radius_mm).into());
+    } else {
+        current_layer.set_fill_color(Color::Rgb(Rgb::new(rgb.0 as f64 / 255.0, rgb.1 as f64 / 255.0, rgb.2 as f64 / 255.0, None)));
+        current_layer.set_outline_color(black.clone());
+        current_layer.set_outline_thickness(0.3);
+        draw_circle_with_pts(&current_layer, center_x_pt, center_y_pt, Mm(radius_mm).into());
+    }
+
+    // largest font that fits the symbol inside the circle, roughly 0.55em per character
+    let symbol = symbols.symbol(rgb);
+    let char_count = symbol.chars().count().max(1) as f64;
+    let mm_per_pt = 25.4 / 72.0;
+    let text_scale = if symbols.mono { 0.7 } else { 1.0 };
+    let font_size = text_scale * (1.4 * radius_mm / (char_count * 0.55 * mm_per_pt)).min(1.2 * radius_mm / mm_per_pt).min(20.0);
+    let text_x_mm = center_x_mm - char_count * 0.55 * font_size * mm_per_pt / 2.0;
+    let text_y_mm = center_y_mm - 0.35 * font_size * mm_per_pt;
+
+    // relative luminance decides between black and white text, always black on the mono white disc
+    let luma = 0.299 * rgb.0 as f64 + 0.587 * rgb.1 as f64 + 0.114 * rgb.2 as f64;
+    current_layer.set_fill_color(if symbols.mono || luma > 140.0 { black } else { white });
+    current_layer.use_text(symbol, font_size, Mm(text_x_mm), Mm(text_y_mm), &fonts.regular);
+} // draw_symbol_circle
+
+// Draw parallel hatch lines across a circle, one set of lines for each angle in the pattern.
+// Each line is cut to the chord of the circle so no clipping is needed.
+fn draw_hatch(current_layer: &PdfLayerReference,
+              (angles, spacing): (&[f64], f64),
+              (center_x_mm, center_y_mm, radius_mm): (f64, f64, f64)) {
+
+    current_layer.set_outline_color(Color::Rgb(Rgb::new(0.35, 0.35, 0.35, None)));
+    current_layer.set_outline_thickness(0.4);
+
+    let step_mm = spacing * radius_mm;
+    let steps = (radius_mm / step_mm).floor() as i32;
+    for angle in angles {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        for k in -steps..=steps {
+            // offset of the line from the centre along its normal and half the chord length
+            let offset_mm = k as f64 * step_mm;
+            let half_mm = (radius_mm * radius_mm - offset_mm * offset_mm).max(0.0).sqrt();
+            if half_mm <= 0.0 {
+                continue;
+            }
+            let mid_x_mm = center_x_mm - offset_mm * sin;
+            let mid_y_mm = center_y_mm + offset_mm * cos;
+            current_layer.add_shape(Line {
+                points: get_points_for_line(Mm(mid_x_mm - half_mm * cos), Mm(mid_y_mm - half_mm * sin),
+                                            Mm(mid_x_mm + half_mm * cos), Mm(mid_y_mm + half_mm * sin)),
+                is_closed: false,
+                has_fill: false,
+                has_stroke: true,
+                is_clipping_path: false,
+            });
+        }
+    }
+} // draw_hatch
@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+
+use crate::modtile::Config;
+
+// Units for the lengths in the config, output_width, tile_size_x, tile_space_x etc.
+// A length is a plain number in the config's "units" (mm when not set) or a string with its own
+// unit such as "60cm", "2.5 in" or "600px". Pixels are converted with "preview_dpi".
+// Lengths are converted to mm when the config is loaded and everything downstream works in mm.
+//   mm - millimetres
+//   cm - centimetres
+//   in - inches
+//   px - pixels of the rendered mosaic image, preview_dpi pixels per inch
+pub const UNITS: [(&str, f64); 4] = [("mm", 1.0), ("cm", 10.0), ("in", 25.4), ("px", 0.0)];
+
+// Names of the units, used for error messages
+pub fn unit_names() -> Vec<&'static str> {
+    UNITS.iter().map(|u| u.0).collect()
+}
+
+// Pixels per inch of the rendered mosaic image when "preview_dpi" is not set.
+// One pixel per mm, the size the mosaic image has always been rendered at.
+pub const DEFAULT_PREVIEW_DPI: f64 = 25.4;
+
+/// A length from the config, kept as written so it can be saved again, and the same length in mm
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Length {
+    pub value: f64,                      // number as written in the config
+    pub unit: Option<&'static str>,      // unit written with the number, one of UNITS
+    pub mm: f64,                         // the length in mm, see resolve_lengths
+}
+
+impl Length {
+
+    /// A length in mm, e.g. the tile pitch of a board preset
+    pub fn from_mm(mm: f64) -> Length {
+        Length { value: mm, unit: Some("mm"), mm }
+    }
+
+    /// A plain number in the config's units
+    pub fn new(value: f64) -> Length {
+        Length { value, unit: None, mm: value }
+    }
+
+    // Work out the length in mm, plain numbers are in `default_unit`
+    fn resolve(&mut self, default_unit: &str, px_per_mm: f64) {
+        self.mm = to_mm(self.value, self.unit.unwrap_or(default_unit), px_per_mm);
+    }
+}
+
+// A length left out of the config, e.g. no space between tiles
+impl Default for Length {
+    fn default() -> Length {
+        Length::new(0.0)
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.unit {
+            Some(unit) => write!(f, "{}{}", self.value, unit),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+// How a length appears in the JSON config, a number or a number and unit in a string
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LengthValue {
+    Number(f64),
+    Text(String),
+}
+
+impl TryFrom<LengthValue> for Length {
+    type Error = String;
+
+    fn try_from(v: LengthValue) -> Result<Length, String> {
+        match v {
+            LengthValue::Number(value) => Ok(Length::new(value)),
+            LengthValue::Text(text) => {
+                let text = text.trim();
+                let split = text.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(text.len());
+                let (number, unit) = (text[..split].trim(), text[split..].trim().to_lowercase());
+                let value: f64 = number.parse().map_err(|_| format!("length \"{}\" must be a number with an optional unit, e.g. \"60cm\"", text))?;
+                if unit.is_empty() {
+                    return Ok(Length::new(value));
+                }
+                match UNITS.iter().find(|u| u.0 == unit) {
+                    Some(u) => Ok(Length { value, unit: Some(u.0), mm: value }),
+                    None => Err(format!("unknown unit \"{}\" in length \"{}\" - expected one of {:?}", unit, text, unit_names())),
+                }
+            }
+        }
+    }
+}
+
+impl From<Length> for LengthValue {
+    fn from(length: Length) -> LengthValue {
+        match length.unit {
+            Some(unit) => LengthValue::Text(format!("{}{}", length.value, unit)),
+            None => LengthValue::Number(length.value),
+        }
+    }
+}
+
+impl Serialize for Length {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        LengthValue::from(*self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Length, D::Error> {
+        Length::try_from(LengthValue::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+// Convert a number in `unit` to mm, an unknown unit is taken as mm (the config checks report it)
+pub fn to_mm(value: f64, unit: &str, px_per_mm: f64) -> f64 {
+    match UNITS.iter().find(|u| u.0 == unit) {
+        Some(("px", _)) => value / px_per_mm,
+        Some((_, mm_per_unit)) => value * mm_per_unit,
+        None => value,
+    }
+}
+
+// Convert mm to `unit`, the inverse of to_mm
+pub fn from_mm(mm: f64, unit: &str, px_per_mm: f64) -> f64 {
+    match UNITS.iter().find(|u| u.0 == unit) {
+        Some(("px", _)) => mm * px_per_mm,
+        Some((_, mm_per_unit)) => mm / mm_per_unit,
+        None => mm,
+    }
+}
+
+/// Unit the config's plain numbers are in, mm when not set
+pub fn config_unit(cfg: &Config) -> &str {
+    cfg.units.as_deref().unwrap_or("mm")
+}
+
+/// Pixels per mm of the rendered mosaic image
+pub fn preview_px_per_mm(cfg: &Config) -> f64 {
+    cfg.preview_dpi.filter(|dpi| *dpi > 0.0).unwrap_or(DEFAULT_PREVIEW_DPI) / 25.4
+}
+
+/// Convert every length in the config to mm, called once the config is loaded and any board preset applied
+pub fn resolve_lengths(cfg: &mut Config) {
+    let unit = config_unit(cfg).to_owned();
+    let px_per_mm = preview_px_per_mm(cfg);
+    for length in [&mut cfg.output_width, &mut cfg.output_height,
+                   &mut cfg.tile_size_x, &mut cfg.tile_size_y,
+                   &mut cfg.tile_space_x, &mut cfg.tile_space_y] {
+        length.resolve(&unit, px_per_mm);
+    }
+}
+
+/// Size of a length in mm for printing in the pdfs, in the config's units or mm when those are pixels,
+/// e.g. "25.6 cm"
+pub fn format_length(mm: f64, cfg: &Config) -> String {
+    let unit = match config_unit(cfg) {
+        "px" => "mm",
+        unit => unit,
+    };
+    let value = from_mm(mm, unit, preview_px_per_mm(cfg));
+    format!("{} {}", format_number(value), unit)
+}
+
+// Up to two decimals, trailing zeros dropped
+pub fn format_number(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+/// Finished size in mm of a mosaic of `tile_cols` x `tile_rows` tiles, tiles plus the spaces between them
+pub fn finished_size_mm(cfg: &Config, tile_cols: usize, tile_rows: usize) -> (f64, f64) {
+    (tile_cols as f64 * cfg.tile_size_x.mm + tile_cols.saturating_sub(1) as f64 * cfg.tile_space_x.mm,
+     tile_rows as f64 * cfg.tile_size_y.mm + tile_rows.saturating_sub(1) as f64 * cfg.tile_space_y.mm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(json: &str) -> Result<Length, String> {
+        serde_json::from_str::<Length>(json).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn parses_numbers_and_units() {
+        assert_eq!(length("12.5").unwrap(), Length::new(12.5));
+        assert_eq!(length("\"60cm\"").unwrap(), Length { value: 60.0, unit: Some("cm"), mm: 60.0 });
+        assert_eq!(length("\" 2.5 IN \"").unwrap(), Length { value: 2.5, unit: Some("in"), mm: 2.5 });
+        assert_eq!(length("\"7\"").unwrap(), Length::new(7.0));
+        assert!(length("\"60ft\"").unwrap_err().contains("unknown unit \"ft\""));
+        assert!(length("\"cm\"").unwrap_err().contains("must be a number"));
+    }
+
+    #[test]
+    fn saves_lengths_as_written() {
+        for json in ["12.5", "\"60cm\"", "\"600px\""] {
+            assert_eq!(serde_json::to_string(&length(json).unwrap()).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn resolves_lengths_to_mm() {
+        let mut cfg = Config {
+            units: Some("cm".to_owned()),
+            preview_dpi: Some(50.8),
+            output_width: Length::new(60.0),
+            output_height: length("\"100px\"").unwrap(),
+            tile_size_x: length("\"0.5in\"").unwrap(),
+            tile_size_y: length("\"8mm\"").unwrap(),
+            ..Default::default()
+        };
+        resolve_lengths(&mut cfg);
+        assert_eq!(cfg.output_width.mm, 600.0);
+        assert_eq!(cfg.output_height.mm, 50.0);
+        assert_eq!(cfg.tile_size_x.mm, 12.7);
+        assert_eq!(cfg.tile_size_y.mm, 8.0);
+        assert_eq!(cfg.tile_space_x.mm, 0.0);
+        assert_eq!(from_mm(12.7, "in", 2.0), 0.5);
+        assert_eq!(format_length(256.0, &cfg), "25.6 cm");
+    }
+
+    #[test]
+    fn finished_size_has_a_space_between_tiles() {
+        let cfg = Config {
+            tile_size_x: Length::from_mm(8.0),
+            tile_size_y: Length::from_mm(5.0),
+            tile_space_x: Length::from_mm(2.0),
+            ..Default::default()
+        };
+        assert_eq!(finished_size_mm(&cfg, 72, 10), (718.0, 50.0));
+        assert_eq!(finished_size_mm(&cfg, 1, 1), (8.0, 5.0));
+        assert_eq!(finished_size_mm(&cfg, 0, 0), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,274 @@
+use std::path::Path;
+
+use crate::error::{ConfigProblem, TileError};
+use crate::modtile::Config;
+use crate::page_layout::PageLayout;
+use crate::{dither, pdf_util, preset, tile_symbols, units};
+
+// Checks of every config setting made before any work is done.
+// All the problems found are reported together, each with the JSON key at fault and where
+// possible a suggested fix, e.g. the nearest pane size that divides the mosaic evenly.
+
+/// Check `cfg`, loaded from `config_path`, after any board preset has been applied.
+/// `image_dims` is the size of the input image in pixels if it could be read, the checks that
+/// depend on the number of tiles in the mosaic are skipped without it.
+pub(crate) fn validate_config(cfg: &Config, config_path: &str, image_dims: Option<(u32, u32)>) -> Result<(), TileError> {
+
+    let mut problems: Vec<ConfigProblem> = Vec::new();
+    let mut problem = |key: &str, message: String, suggestion: Option<String>| {
+        problems.push(ConfigProblem { key: key.to_owned(), message, suggestion });
+    };
+
+    // files, a missing or unreadable palette or input image is reported when it is loaded
+    if cfg.tile_colors.trim().is_empty() {
+        problem("tile_colors", "no tile colour file given".to_owned(),
+                Some(format!("name a palette file or a board \"preset\" from {:?} to use its bundled palette", preset::preset_names())));
+    }
+    if cfg.input.trim().is_empty() {
+        problem("input", "no input image given".to_owned(), None);
+    }
+    match image::ImageFormat::from_path(&cfg.output) {
+        Ok(format) if format.can_write() => {}
+        _ => problem("output", format!("cannot save the mosaic image as {}", cfg.output), Some("end the file name with .png or .jpg".to_owned())),
+    }
+    if let Some(dir) = Path::new(&cfg.output).parent() {
+        if !dir.as_os_str().is_empty() && !dir.is_dir() {
+            problem("output", format!("output directory {} does not exist", dir.display()), Some("create it first".to_owned()));
+        }
+    }
+
+    // units, lengths have been converted to mm but suggestions are given in the config's units
+    let unit = units::config_unit(cfg);
+    if !units::unit_names().contains(&unit) {
+        problem("units", format!("unknown units {}", unit), suggest_name(unit, &units::unit_names()));
+    }
+    if let Some(dpi) = cfg.preview_dpi {
+        if dpi.is_nan() || dpi <= 0.0 {
+            problem("preview_dpi", format!("{} must be greater than 0", dpi), Some(format!("leave it out for {} dpi", units::DEFAULT_PREVIEW_DPI)));
+        }
+    }
+    let px_per_mm = units::preview_px_per_mm(cfg);
+    let in_units = |mm: f64| units::format_number(units::from_mm(mm, unit, px_per_mm));
+
+    // sizes
+    for (key, value) in [("output_width", cfg.output_width), ("output_height", cfg.output_height)] {
+        if value.mm.is_nan() || value.mm < 1.0 {
+            problem(key, format!("{} must be at least 1mm", value), None);
+        }
+    }
+    for (key, value, output_key, output) in [("tile_size_x", cfg.tile_size_x, "output_width", cfg.output_width),
+                                             ("tile_size_y", cfg.tile_size_y, "output_height", cfg.output_height)] {
+        if value.mm.is_nan() || value.mm <= 0.0 {
+            problem(key, format!("{} must be greater than 0", value), None);
+        } else if value.mm > output.mm {
+            problem(key, format!("{} is larger than {} {}", value, output_key, output),
+                    Some(format!("use a tile size of at most {}", in_units(output.mm))));
+        }
+    }
+    for (key, value) in [("tile_space_x", cfg.tile_space_x), ("tile_space_y", cfg.tile_space_y)] {
+        if value.mm.is_nan() || value.mm < 0.0 {
+            problem(key, format!("{} cannot be negative", value), Some("use 0 for tiles that touch".to_owned()));
+        }
+    }
+    for (key, value) in [("tiles_per_pane_width", cfg.tiles_per_pane_width), ("tiles_per_pane_height", cfg.tiles_per_pane_height)] {
+        if value == 0 {
+            problem(key, "must be at least 1".to_owned(), None);
+        }
+    }
+
+    // named settings
+    if let Some(name) = &cfg.preset {
+        if preset::get_preset(name).is_none() {
+            problem("preset", format!("unknown preset {}", name), suggest_name(name, &preset::preset_names()));
+        }
+    }
+    if let Err(e) = PageLayout::from_config(cfg.page.as_ref()) {
+        problem("page", e, None);
+    }
+    let named: [(&str, &Option<String>, Vec<&str>); 4] = [
+        ("summary_order", &cfg.summary_order, pdf_util::summary_order_names()),
+        ("tile_symbols", &cfg.tile_symbols, tile_symbols::symbol_scheme_names()),
+        ("print_mode", &cfg.print_mode, tile_symbols::print_mode_names()),
+        ("dither", &cfg.dither, dither::dither_mode_names()),
+    ];
+    for (key, value, names) in named.iter() {
+        if let Some(value) = value {
+            if !names.contains(&value.as_str()) {
+                problem(key, format!("unknown {} {}", key, value), suggest_name(value, names));
+            }
+        }
+    }
+
+    // the mosaic must split into whole panes, only checked when the sizes themselves are usable
+    let sizes_ok = cfg.output_width.mm >= 1.0 && cfg.output_height.mm >= 1.0 && cfg.tile_size_x.mm > 0.0 && cfg.tile_size_y.mm > 0.0
+                   && cfg.tiles_per_pane_width > 0 && cfg.tiles_per_pane_height > 0;
+    if let (true, Some((img_width, img_height))) = (sizes_ok, image_dims) {
+        if let Ok((output_width, output_height)) = crate::get_max_box(img_width as f64, img_height as f64, cfg.output_width.mm, cfg.output_height.mm) {
+            let axes = [("width", output_width, cfg.tile_size_x.mm, cfg.tiles_per_pane_width, img_width, "tiles_per_pane_width", "output_width"),
+                        ("height", output_height, cfg.tile_size_y.mm, cfg.tiles_per_pane_height, img_height, "tiles_per_pane_height", "output_height")];
+            for (axis, output, tile_size, per_pane, img_px, pane_key, output_key) in axes {
+                let tile_count = (output / tile_size).round() as usize;
+                if tile_count < per_pane {
+                    problem(pane_key, format!("{} tiles per pane but the mosaic is only {} tiles in {}", per_pane, tile_count, axis),
+                            Some(format!("use at most {} or set {} to at least {}", tile_count.max(1), output_key, in_units(per_pane as f64 * tile_size))));
+                } else if !tile_count.is_multiple_of(per_pane) {
+                    // with a board preset the pane size is the board so only the output size can change
+                    let suggestion = if cfg.preset.is_some() {
+                        format!("set {} to {} for whole boards", output_key, in_units(nearest_multiple(tile_count, per_pane) as f64 * tile_size))
+                    } else {
+                        let divisor = nearest_divisor(tile_count, per_pane);
+                        format!("use {} tiles per pane ({} panes) or set {} to {}", divisor, tile_count / divisor,
+                                output_key, in_units(nearest_multiple(tile_count, per_pane) as f64 * tile_size))
+                    };
+                    problem(pane_key, format!("{} does not divide the {} tiles in {}, {} tiles would be left out",
+                                              per_pane, tile_count, axis, tile_count % per_pane),
+                            Some(suggestion));
+                }
+                // each tile covers at least one input pixel
+                let img_px = img_px as usize;
+                if tile_count > img_px {
+                    problem(output_key, format!("{} tiles in {} but the input image is only {} pixels", tile_count, axis, img_px),
+                            Some(format!("use a larger tile size or set {} to at most {}", output_key, in_units(img_px as f64 * tile_size))));
+                }
+                // the rendered mosaic image is rounded to whole pixels per tile, see main::image_px,
+                // each tile needs at least one pixel at preview_dpi
+                let mosaic_px = output * px_per_mm;
+                if tile_count > 0 && tile_count as f64 > mosaic_px.round() {
+                    let dpi = (tile_count as f64 / output * 25.4).ceil();
+                    problem("preview_dpi", format!("{} tiles in {} but the mosaic image is only {} pixels at {} dpi",
+                                                   tile_count, axis, mosaic_px.round(), units::format_number(px_per_mm * 25.4)),
+                            Some(format!("set preview_dpi to at least {}", dpi)));
+                }
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(TileError::Invalid { path: Path::new(config_path).to_path_buf(), problems })
+    }
+} // validate_config
+
+// Divisor of `count` closest to `wanted`, the smaller one on a tie
+fn nearest_divisor(count: usize, wanted: usize) -> usize {
+    (1..=count).filter(|d| count.is_multiple_of(*d))
+               .min_by_key(|d| (d.abs_diff(wanted), *d))
+               .unwrap_or(1)
+}
+
+// Multiple of `per_pane` closest to `count`, at least one pane
+fn nearest_multiple(count: usize, per_pane: usize) -> usize {
+    let below = count / per_pane * per_pane;
+    let above = below + per_pane;
+    if below > 0 && count - below <= above - count { below } else { above }
+}
+
+// "did you mean" for a mistyped name, the closest of `names` within a couple of edits
+fn suggest_name(value: &str, names: &[&str]) -> Option<String> {
+    match closest_name(value, names) {
+        Some(closest) => Some(format!("did you mean {}?", closest)),
+        None => Some(format!("expected one of {:?}", names)),
+    }
+}
+
+// The name in `names` within a couple of edits of a mistyped `value`
+pub(crate) fn closest_name<'a>(value: &str, names: &[&'a str]) -> Option<&'a str> {
+    let value = value.to_lowercase();
+    let closest = names.iter().min_by_key(|name| edit_distance(&value, name))?;
+    if edit_distance(&value, closest) <= 2 { Some(closest) } else { None }
+}
+
+// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(diag + if ca == *cb { 0 } else { 1 });
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Length;
+
+    fn config(size_mm: f64, tile_mm: f64, per_pane: usize, preview_dpi: Option<f64>) -> Config {
+        Config {
+            tile_colors: "palette.json".to_owned(),
+            input: "input.png".to_owned(),
+            output: "output.png".to_owned(),
+            output_width: Length::new(size_mm),
+            output_height: Length::new(size_mm),
+            tile_size_x: Length::new(tile_mm),
+            tile_size_y: Length::new(tile_mm),
+            tiles_per_pane_width: per_pane,
+            tiles_per_pane_height: per_pane,
+            preview_dpi,
+            ..Default::default()
+        }
+    }
+
+    fn problem_keys(cfg: &Config, image_dims: (u32, u32)) -> Vec<String> {
+        match validate_config(cfg, "test.json", Some(image_dims)) {
+            Ok(()) => Vec::new(),
+            Err(TileError::Invalid { problems, .. }) => problems.into_iter().map(|p| p.key).collect(),
+            Err(e) => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        assert!(problem_keys(&config(600.0, 10.0, 10, None), (553, 553)).is_empty());
+        assert!(problem_keys(&config(100.0, 10.0, 10, Some(7.0)), (553, 553)).is_empty());
+    }
+
+    #[test]
+    fn tiles_smaller_than_a_mosaic_image_pixel() {
+        // 50 tiles across a 28 pixel mosaic image
+        assert_eq!(problem_keys(&config(100.0, 2.0, 10, Some(7.0)), (553, 553)), vec!["preview_dpi", "preview_dpi"]);
+        assert!(problem_keys(&config(100.0, 2.0, 10, Some(13.0)), (553, 553)).is_empty());
+    }
+
+    #[test]
+    fn panes_must_divide_the_tiles() {
+        assert_eq!(problem_keys(&config(450.0, 10.0, 10, None), (553, 553)), vec!["tiles_per_pane_width", "tiles_per_pane_height"]);
+    }
+
+    #[test]
+    fn tiles_must_fit_the_input_image() {
+        assert_eq!(problem_keys(&config(600.0, 10.0, 10, None), (40, 40)), vec!["output_width", "output_height"]);
+    }
+
+    #[test]
+    fn tiles_that_split_pixels_are_valid() {
+        // 8 tiles of 67.5 pixels, each tile takes 67 and the last 4 pixels are left out
+        assert!(problem_keys(&config(40.0, 5.0, 8, None), (540, 540)).is_empty());
+    }
+
+    #[test]
+    fn nearest_divisor_and_multiple() {
+        assert_eq!(nearest_divisor(45, 10), 9);
+        assert_eq!(nearest_divisor(12, 5), 4);
+        assert_eq!(nearest_divisor(7, 3), 1);
+        assert_eq!(nearest_multiple(45, 10), 40);
+        assert_eq!(nearest_multiple(46, 10), 50);
+        assert_eq!(nearest_multiple(3, 10), 10);
+    }
+
+    #[test]
+    fn edit_distance_and_suggestions() {
+        assert_eq!(edit_distance("mono", "mono"), 0);
+        assert_eq!(edit_distance("mnoo", "mono"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(closest_name("MNOO", &["color", "mono"]), Some("mono"));
+        assert_eq!(closest_name("sepia", &["color", "mono"]), None);
+    }
+}
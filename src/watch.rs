@@ -0,0 +1,336 @@
+use clap::ArgMatches;
+use euclid::Box2D;
+use image::{DynamicImage, GenericImageView};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+use crate::error::TileError;
+use crate::modtile::{self, Config, RGB};
+use crate::run_report::RunReport;
+use crate::{config_file, fonts, Mosaic};
+
+// render --watch, render once then again each time the config (or a config it extends), the palette,
+// the input image or a font file changes. Only the stages whose inputs changed are run again:
+//   input image, mosaic size, tile size or pane size   load the image and average each tile's colour
+//   palette, dither or preview_dpi                      match the tile colours and draw the mosaic image
+//   other settings except the DOCUMENT_KEYS below      save the mosaic image and tile json, --dxf and --ldraw
+//   anything, including a font file                    the instructions pdf, run config, --swatch, --cross-stitch and --poster
+// e.g. changing the page size only makes the pdf again. Problems are logged and the last good outputs
+// kept, watching carries on until interrupted.
+
+// Settings only used by the printed documents, the poster is printed with the fonts so is one of them
+const DOCUMENT_KEYS: [&str; 9] = ["page", "summary_order", "tile_symbols", "print_mode", "title", "author", "checklist", "font", "heading_font"];
+
+// Saving a file often shows as several changes, wait this long after the last one before rendering
+const SETTLE_TIME: Duration = Duration::from_millis(300);
+
+// Modification time and size of a file, None if it cannot be read
+type FileStamp = Option<(SystemTime, u64)>;
+
+// Tile boxes in input image pixels with their average colours, pane by pane as from create_out_panes
+type TileAverages = Vec<Vec<(Box2D<i32, i32>, RGB)>>;
+
+// The input image file as last loaded
+#[derive(Clone, PartialEq)]
+struct ImageKey {
+    path: String,
+    stamp: FileStamp,
+}
+
+// What the tile averages were worked out from
+#[derive(Clone, PartialEq)]
+struct AveragesKey {
+    image: ImageKey,
+    tiles: (usize, usize),          // tiles across and down
+    pane: (usize, usize),           // tiles across and down each pane
+}
+
+// What the matched tile colours and mosaic image were made from
+#[derive(Clone, PartialEq)]
+struct MosaicKey {
+    averages: AveragesKey,
+    palette: String,
+    palette_stamp: FileStamp,
+    dither: Option<String>,
+    image_px: (f64, f64),           // size of the mosaic image, changes with preview_dpi
+}
+
+// The results of the last run kept for the next, each with what it was made from
+#[derive(Default)]
+struct WatchCache {
+    image: Option<(ImageKey, DynamicImage)>,
+    averages: Option<(AveragesKey, TileAverages)>,
+    mosaic: Option<(MosaicKey, Mosaic)>,
+    mosaic_files: Option<String>,   // settings the mosaic image and exports were written with
+    documents: Option<String>,      // settings and font files the documents were written with
+    inputs: Vec<PathBuf>,           // palette, image and font files of the last config loaded
+}
+
+pub(crate) fn watch_command(matches: &ArgMatches) -> Result<(), TileError> {
+
+    let config_path: &str = matches.get_one::<String>("config").expect("config argument missing");
+    let (sender, events) = channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(TileError::Watch)?;
+    let mut watched_dirs: HashSet<PathBuf> = HashSet::new();
+    let mut cache = WatchCache::default();
+
+    loop {
+        // a bad config or missing file is reported and waited out, the next save may fix it
+        match cache.render_changes(matches) {
+            Ok(true) => {}
+            Ok(false) => log::info!("No settings or input files changed"),
+            Err(e) => log::error!("{}", e),
+        }
+
+        // the config may now extend another base or name another palette or image
+        let mut files: Vec<PathBuf> = config_file::config_files(Path::new(config_path));
+        files.extend(cache.inputs.iter().cloned());
+        let files: HashSet<PathBuf> = files.iter().map(|file| watch_path(file)).collect();
+
+        // editors often save by replacing the file so the directories are watched rather than the files,
+        // a directory that does not exist yet is tried again after the next change
+        let dirs: HashSet<PathBuf> = files.iter().filter_map(|file| file.parent().map(Path::to_path_buf)).collect();
+        watched_dirs.retain(|dir| dirs.contains(dir) || watcher.unwatch(dir).is_err());
+        for dir in dirs {
+            if !watched_dirs.contains(&dir) {
+                match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                    Ok(()) => { watched_dirs.insert(dir); }
+                    Err(e) => log::warn!("Cannot watch {} for changes - {}", dir.display(), e),
+                }
+            }
+        }
+
+        log::info!("Watching {} files for changes, Ctrl+C to stop", files.len());
+        if !wait_for_change(&events, &files) {
+            return Ok(());
+        }
+    }
+} // watch_command
+
+impl WatchCache {
+
+    // Render again from the stages whose inputs changed since the last run, false when nothing had changed
+    fn render_changes(&mut self, matches: &ArgMatches) -> Result<bool, TileError> {
+
+        let mut report = RunReport::new("watch");
+        let (cfg, board) = report.time("load config", |_| crate::load_run_config(matches))?;
+        self.inputs = input_files(&cfg);
+        crate::report_inputs(&mut report, matches, &cfg);
+
+        // input image
+        let image_key = ImageKey { path: cfg.input.clone(), stamp: stamp(&cfg.input) };
+        if self.image.as_ref().map(|(key, _)| key) != Some(&image_key) {
+            let input_img = report.time("load input image", |_| crate::load_input_image(&cfg))?;
+            self.image = Some((image_key.clone(), input_img));
+        }
+        let input_img: &DynamicImage = &self.image.as_ref().expect("input image loaded above").1;
+
+        // tile averages
+        let grid = crate::mosaic_grid(&cfg, input_img.width() as f64, input_img.height() as f64)?;
+        let averages_key = AveragesKey {
+            image: image_key,
+            tiles: (grid.width_tile_count, grid.height_tile_count),
+            pane: (cfg.tiles_per_pane_width, cfg.tiles_per_pane_height),
+        };
+        if self.averages.as_ref().map(|(key, _)| key) != Some(&averages_key) {
+            let averages = report.time("tile averages", |_| crate::tile_averages(input_img, &grid, &cfg));
+            self.averages = Some((averages_key.clone(), averages));
+        } else {
+            log::info!("Input image and tile grid unchanged, reusing the tile averages");
+        }
+
+        // tile colours and mosaic image
+        let mosaic_key = MosaicKey {
+            averages: averages_key,
+            palette: cfg.tile_colors.clone(),
+            palette_stamp: stamp(&cfg.tile_colors),
+            dither: cfg.dither.clone(),
+            image_px: (grid.width_px, grid.height_px),
+        };
+        let rematched = self.mosaic.as_ref().map(|(key, _)| key) != Some(&mosaic_key);
+        if rematched {
+            let averages = self.averages.as_ref().expect("tile averages made above").1.clone();
+            // forget the old mosaic first so a failed match is tried again on the next change
+            self.mosaic = None;
+            let mosaic = report.time("match tile colours", |_| crate::match_mosaic(&cfg, input_img.clone(), grid, averages))?;
+            crate::log_tile_list(&mosaic);
+            self.mosaic = Some((mosaic_key, mosaic));
+        } else {
+            log::info!("Palette and tile grid unchanged, reusing the tile colours");
+        }
+        let mosaic: &Mosaic = &self.mosaic.as_ref().expect("tile colours matched above").1;
+        crate::report_counts(&mut report, mosaic, &cfg);
+
+        // outputs made from the tile grid
+        let mosaic_settings = mosaic_settings(&cfg);
+        let write_mosaic_files = rematched || self.mosaic_files.as_ref() != Some(&mosaic_settings);
+        if write_mosaic_files {
+            self.mosaic_files = None;
+            report.time("save mosaic image and json", |report| crate::save_mosaic(mosaic, &cfg, report))?;
+            crate::write_mosaic_extras(matches, mosaic, &cfg, board, &mut report)?;
+            self.mosaic_files = Some(mosaic_settings);
+        }
+
+        // printed documents
+        let document_settings = document_settings(&cfg);
+        if rematched || self.documents.as_ref() != Some(&document_settings) {
+            self.documents = None;
+            report.time("instructions pdf", |report| crate::write_pdf(mosaic, &cfg, crate::parts_board(matches, board).as_ref(), report))?;
+            if !write_mosaic_files {
+                crate::save_run_config(&cfg, &mut report)?;
+            }
+            crate::write_printed_extras(matches, mosaic, &cfg, &mut report)?;
+            self.documents = Some(document_settings);
+        } else if !write_mosaic_files {
+            return Ok(false);
+        }
+
+        crate::write_report(matches, &cfg, report)?;
+        Ok(true)
+    } // render_changes
+}
+
+// The settings the mosaic image and exports are written with, all but the DOCUMENT_KEYS
+fn mosaic_settings(cfg: &Config) -> String {
+    let mut settings = serde_json::to_value(cfg).unwrap_or_default();
+    if let Some(map) = settings.as_object_mut() {
+        DOCUMENT_KEYS.iter().for_each(|key| { map.remove(*key); });
+    }
+    settings.to_string()
+}
+
+// The settings the printed documents are written with, with the font files as the settings may
+// name the same file after it changed
+fn document_settings(cfg: &Config) -> String {
+    let font_stamps: Vec<FileStamp> = [&cfg.font, &cfg.heading_font].iter()
+        .map(|font| font.as_deref().and_then(stamp))
+        .collect();
+    format!("{} {:?}", serde_json::to_value(cfg).unwrap_or_default(), font_stamps)
+}
+
+// Wait for one of `files` to change, false if the watcher stopped
+fn wait_for_change(events: &Receiver<notify::Result<notify::Event>>, files: &HashSet<PathBuf>) -> bool {
+    let changed = |event: notify::Result<notify::Event>| match event {
+        // reading a file is also an event, renders read the files watched
+        Ok(event) => matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+                     && event.paths.iter().any(|path| files.contains(&watch_path(path))),
+        Err(e) => {
+            log::warn!("File watch error - {}", e);
+            false
+        }
+    };
+    loop {
+        match events.recv() {
+            Ok(event) => if changed(event) { break },
+            Err(_) => return false,
+        }
+    }
+    // let the save finish
+    loop {
+        match events.recv_timeout(SETTLE_TIME) {
+            Ok(_) => {}
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+} // wait_for_change
+
+// The palette, input image and font files a config reads, whether or not they exist yet so that
+// restoring a missing file renders again
+fn input_files(cfg: &Config) -> Vec<PathBuf> {
+    let mut files: Vec<&String> = vec![&cfg.input];
+    files.extend(Some(&cfg.tile_colors).filter(|palette| !modtile::is_builtin_palette(palette)));
+    files.extend(cfg.font.iter().chain(cfg.heading_font.iter()).filter(|font| !fonts::is_builtin_font(font)));
+    files.into_iter()
+        .filter(|file| !file.trim().is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+// `file` in its canonical directory, matching the paths of the watcher's events even if the file itself is missing
+fn watch_path(file: &Path) -> PathBuf {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    match file.file_name() {
+        Some(name) => dir.join(name),
+        None => dir,
+    }
+}
+
+fn stamp(path: &str) -> FileStamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Length;
+
+    fn config() -> Config {
+        Config {
+            tile_colors: "tile_json/colors.json".to_owned(),
+            input: "images/in.png".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn document_settings_only_remake_the_documents() {
+        let cfg = config();
+        let changed = Config { title: Some("Renamed".to_owned()), checklist: Some(true), ..config() };
+        assert_eq!(mosaic_settings(&changed), mosaic_settings(&cfg));
+        assert_ne!(document_settings(&changed), document_settings(&cfg));
+
+        let spaced = Config { tile_space_x: Length::from_mm(2.0), ..config() };
+        assert_ne!(mosaic_settings(&spaced), mosaic_settings(&cfg));
+        assert_ne!(document_settings(&spaced), document_settings(&cfg));
+    }
+
+    #[test]
+    fn a_changed_font_file_remakes_the_documents() {
+        let font = std::env::temp_dir().join(format!("img_tile_watch_font_{}.ttf", std::process::id()));
+        std::fs::write(&font, "first").unwrap();
+        let cfg = Config { font: Some(font.to_string_lossy().into_owned()), ..config() };
+        let before = document_settings(&cfg);
+        std::fs::write(&font, "second save").unwrap();
+        assert_ne!(document_settings(&cfg), before);
+        std::fs::remove_file(&font).unwrap();
+        assert_ne!(document_settings(&cfg), before);
+    }
+
+    #[test]
+    fn stage_keys_change_with_their_inputs() {
+        let image = ImageKey { path: "images/in.png".to_owned(), stamp: None };
+        let averages = AveragesKey { image: image.clone(), tiles: (60, 40), pane: (10, 10) };
+        let mosaic = MosaicKey { averages: averages.clone(), palette: "colors.json".to_owned(), palette_stamp: None,
+                                 dither: None, image_px: (600.0, 400.0) };
+        assert!(AveragesKey { pane: (20, 10), ..averages.clone() } != averages);
+        assert!(AveragesKey { image: ImageKey { stamp: Some((SystemTime::UNIX_EPOCH, 1)), ..image }, ..averages.clone() } != averages);
+        assert!(MosaicKey { dither: Some("atkinson".to_owned()), ..mosaic.clone() } != mosaic);
+        assert!(MosaicKey { image_px: (1200.0, 800.0), ..mosaic.clone() } != mosaic);
+        assert!(MosaicKey { averages: AveragesKey { tiles: (30, 20), ..averages }, ..mosaic.clone() } != mosaic);
+    }
+
+    #[test]
+    fn watches_missing_files_and_named_fonts_only() {
+        let cfg = Config {
+            font: Some("Helvetica".to_owned()),
+            heading_font: Some("fonts/missing.ttf".to_owned()),
+            input: " ".to_owned(),
+            ..config()
+        };
+        assert_eq!(input_files(&cfg), vec![PathBuf::from("tile_json/colors.json"), PathBuf::from("fonts/missing.ttf")]);
+
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        assert_eq!(watch_path(Path::new("missing.json")), cwd.join("missing.json"));
+        assert_eq!(watch_path(Path::new("./src/../src/missing.json")), cwd.join("src/missing.json"));
+        assert_eq!(watch_path(Path::new("no_dir/missing.json")), PathBuf::from("no_dir/missing.json"));
+    }
+}